@@ -67,6 +67,8 @@ export LD_LIBRARY_PATH="$(realpath .)"
 
 # Program-specific env vars
 export WINIT_UNIX_BACKEND=fbdev
+# The Car Thing's framebuffer doesn't report a usable physical size
+export WINIT_FBDEV_SCALE_FACTOR=1.5
 
 # Kill previous instance
 PS_RESULT="$(ps)"
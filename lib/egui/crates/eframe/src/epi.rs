@@ -98,6 +98,13 @@ pub trait App {
     #[cfg(not(feature = "glow"))]
     fn on_exit(&mut self) {}
 
+    /// Called when the app goes idle or wakes up again, see [`NativeOptions::idle`].
+    ///
+    /// The display is dimmed or blanked after this is called with the new state, and back on
+    /// before it is called with [`IdleState::Active`].
+    #[cfg(not(target_arch = "wasm32"))]
+    fn on_idle(&mut self, _state: IdleState) {}
+
     // ---------
     // Settings:
 
@@ -234,6 +241,28 @@ pub struct NativeOptions {
     /// The default is `true`.
     pub vsync: bool,
 
+    /// How many refreshes of the display each frame waits for, overrides [`Self::vsync`].
+    /// `0` doesn't wait, `2` halves the frame rate.
+    ///
+    /// Only used by the glow renderer. Intervals above `1` are only honoured on EGL, e.g. on kms
+    /// and fbdev.
+    ///
+    /// The default is `None`.
+    pub swap_interval: Option<u32>,
+
+    /// Paint at most this many frames per second, however often a repaint is requested.
+    /// Repaints that come too soon are put off and counted in
+    /// [`IntegrationInfo::frames_skipped`].
+    ///
+    /// Only used by the glow renderer. The default is `None`, no limit.
+    pub max_fps: Option<f32>,
+
+    /// Paint at most this many frames per second while there is no input, when only
+    /// animations and [`egui::Context::request_repaint`] keep repainting.
+    ///
+    /// Only used by the glow renderer. The default is `None`, [`Self::max_fps`].
+    pub animation_fps: Option<f32>,
+
     /// Set the level of the multisampling anti-aliasing (MSAA).
     ///
     /// Must be a power-of-two. Higher = more smooth 3D.
@@ -255,6 +284,59 @@ pub struct NativeOptions {
     /// `egui` doesn't need the stencil buffer, so the default value is 0.
     pub stencil_buffer: u8,
 
+    /// Create a [`crate::TextureUploader`], a GL context sharing textures with the one egui is
+    /// painted with, for uploading large images on another thread. Take it with
+    /// [`Frame::take_texture_uploader`].
+    ///
+    /// The uploader shares with the context it was created for. If that is lost (only on fbdev),
+    /// one that wasn't taken yet is dropped and textures uploaded through one that was never
+    /// arrive.
+    ///
+    /// Only used by the glow renderer. The default is `false`.
+    pub texture_uploader: bool,
+
+    /// Keep the GPU memory of textures within this many bytes, by evicting textures marked
+    /// evictable through [`Frame::texture_budget`]. The usage is shown in
+    /// [`egui::Context::texture_ui`].
+    ///
+    /// Only used by the glow renderer. The default is `None`, no limit.
+    pub texture_budget: Option<usize>,
+
+    /// Blend in gamma space instead of converting every fragment to linear and back, see
+    /// [`egui_glow::Painter::new_gamma_space`]. Much cheaper on slow OpenGL ES 2 GPUs, but
+    /// antialiased edges and translucent shapes come out a little darker.
+    ///
+    /// Only used by the glow renderer. The default is `false`.
+    #[cfg(feature = "glow")]
+    pub gamma_space_rendering: bool,
+
+    /// Show an overlay with where the time of the last frames went: `App::update`,
+    /// tessellation, texture upload and painting on the CPU, painting on the GPU where the
+    /// driver has timer queries, and what was painted. Toggle it with
+    /// [`Frame::set_profiler_hud`] or [`Self::profiler_hud_chord`].
+    ///
+    /// Only used by the glow renderer. The default is `false`.
+    #[cfg(feature = "glow")]
+    pub profiler_hud: bool,
+
+    /// Holding down all of these keys toggles the [`Self::profiler_hud`], e.g. a combination
+    /// of the hardware buttons of a device.
+    ///
+    /// Only used by the glow renderer. The default is empty, no chord.
+    #[cfg(feature = "glow")]
+    pub profiler_hud_chord: Vec<egui::Key>,
+
+    /// Options for running on the display of a device rather than on a desktop.
+    pub embedded: EmbeddedOptions,
+
+    /// Where and how the app state is persisted, see [`App::save`].
+    pub persistence: PersistenceOptions,
+
+    /// What to do after a while without input, see [`App::on_idle`].
+    ///
+    /// Only used by the glow renderer.
+    pub idle: IdleOptions,
+
     /// Specify wether or not hardware acceleration is preferred, required, or not.
     ///
     /// Default: [`HardwareAcceleration::Preferred`].
@@ -311,9 +393,23 @@ impl Default for NativeOptions {
             resizable: true,
             transparent: false,
             vsync: true,
+            swap_interval: None,
+            max_fps: None,
+            animation_fps: None,
             multisampling: 0,
             depth_buffer: 0,
             stencil_buffer: 0,
+            texture_uploader: false,
+            texture_budget: None,
+            #[cfg(feature = "glow")]
+            gamma_space_rendering: false,
+            #[cfg(feature = "glow")]
+            profiler_hud: false,
+            #[cfg(feature = "glow")]
+            profiler_hud_chord: vec![],
+            embedded: EmbeddedOptions::default(),
+            persistence: PersistenceOptions::default(),
+            idle: IdleOptions::default(),
             hardware_acceleration: HardwareAcceleration::Preferred,
             renderer: Renderer::default(),
             follow_system_theme: cfg!(target_os = "macos") || cfg!(target_os = "windows"),
@@ -346,6 +442,187 @@ impl NativeOptions {
     }
 }
 
+/// Options for running on the display of a device, e.g. with the kms or fbdev backends of
+/// winit on Linux, see [`NativeOptions::embedded`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmbeddedOptions {
+    /// Which windowing backend to use on Linux.
+    ///
+    /// The default is `None`: `WINIT_UNIX_BACKEND` if set, otherwise the first of Wayland,
+    /// X11, kms and fbdev that works.
+    pub backend: Option<DisplayBackend>,
+
+    /// The DRM card (e.g. `/dev/dri/card0`) kms opens, or the framebuffer device
+    /// (e.g. `/dev/fb0`) fbdev opens.
+    ///
+    /// The default is `None`: `WINIT_DRM_CARD` or `WINIT_FBDEV_PATH` if set, otherwise the first
+    /// one udev finds.
+    pub device_path: Option<std::path::PathBuf>,
+
+    /// How the painted image is rotated on the framebuffer, for panels mounted in another
+    /// orientation than the UI is laid out in. The window size stays the layout size. Touches
+    /// are rotated to match on kms and fbdev.
+    ///
+    /// Only used by the glow renderer. Default: [`egui_glow::Rotation::None`].
+    #[cfg(feature = "glow")]
+    pub rotation: egui_glow::Rotation,
+
+    /// Lay out with this many pixels per point instead of the scale factor of the display.
+    ///
+    /// The default is `None`.
+    pub pixels_per_point: Option<f32>,
+
+    /// Ignore mice and touchpads, so only touches and keys reach egui. egui still turns the
+    /// first finger on the screen into its pointer.
+    ///
+    /// The default is `false`.
+    pub touch_only: bool,
+
+    /// Never show the mouse cursor, whatever cursor icon egui asks for.
+    ///
+    /// The default is `false`.
+    pub hide_cursor: bool,
+
+    /// Keep a CPU copy of each texture egui manages, to upload them again if the GL context is
+    /// lost. That only happens on fbdev, elsewhere this does nothing. The copies take as much
+    /// memory as the textures, without them the textures are gone after a loss.
+    ///
+    /// Only used by the glow renderer. The default is `true`.
+    #[cfg(feature = "glow")]
+    pub keep_texture_copies: bool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for EmbeddedOptions {
+    fn default() -> Self {
+        Self {
+            backend: None,
+            device_path: None,
+            #[cfg(feature = "glow")]
+            rotation: egui_glow::Rotation::None,
+            pixels_per_point: None,
+            touch_only: false,
+            hide_cursor: false,
+            #[cfg(feature = "glow")]
+            keep_texture_copies: true,
+        }
+    }
+}
+
+/// Where and how the app state is persisted, see [`NativeOptions::persistence`].
+///
+/// Without a [`Self::storage_backend`] it goes to `app.ron` in a directory, which needs the
+/// `persistence` feature. Saves replace that file atomically.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+pub struct PersistenceOptions {
+    /// The directory `app.ron` is kept in, e.g. on a partition that is writable on a device.
+    ///
+    /// The default is `None`: the data directory the OS has for the app name, if there is a
+    /// home directory to put it in.
+    pub storage_dir: Option<std::path::PathBuf>,
+
+    /// Wait for each save to reach the disk (`fsync`), so that a save that has finished
+    /// survives a power cut.
+    ///
+    /// The default is `true`.
+    pub sync: bool,
+
+    /// Write the file at most once per this long, to limit the wear on flash storage. Saves
+    /// in between are written with the next save after it, or when the app exits.
+    ///
+    /// The default is [`std::time::Duration::ZERO`].
+    pub min_flush_interval: std::time::Duration,
+
+    /// Store the app state in this instead of a file, e.g. a key-value store. It is called
+    /// with the app name, `None` disables persistence.
+    ///
+    /// The default is `None`.
+    pub storage_backend: Option<StorageCreator>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for PersistenceOptions {
+    fn default() -> Self {
+        Self {
+            storage_dir: None,
+            sync: true,
+            min_flush_interval: std::time::Duration::ZERO,
+            storage_backend: None,
+        }
+    }
+}
+
+/// Creates the [`Storage`] of an app from its name, see [`PersistenceOptions::storage_backend`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type StorageCreator = std::sync::Arc<dyn Fn(&str) -> Option<Box<dyn Storage>>>;
+
+/// When to dim and blank the display without input, see [`NativeOptions::idle`].
+///
+/// The backlight and blanking are controlled through the fbdev backend of winit, on other
+/// backends only [`App::on_idle`] is called.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdleOptions {
+    /// Dim the backlight this long after the last input.
+    ///
+    /// The default is `None`, never.
+    pub dim_after: Option<std::time::Duration>,
+
+    /// The backlight brightness from 0 to 1 while dimmed.
+    ///
+    /// The default is `0.2`.
+    pub dim_brightness: f32,
+
+    /// Blank the display this long after the last input. Nothing is painted while it is
+    /// blanked, and the touch, click or key press that wakes it up doesn't reach egui.
+    ///
+    /// Only fbdev can blank the display. Elsewhere the app is still told through
+    /// [`App::on_idle`], but painting and input carry on as usual.
+    ///
+    /// The default is `None`, never.
+    pub blank_after: Option<std::time::Duration>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for IdleOptions {
+    fn default() -> Self {
+        Self {
+            dim_after: None,
+            dim_brightness: 0.2,
+            blank_after: None,
+        }
+    }
+}
+
+/// How idle the app is, see [`App::on_idle`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IdleState {
+    /// There was input recently.
+    Active,
+    /// [`IdleOptions::dim_after`] has passed.
+    Dimmed,
+    /// [`IdleOptions::blank_after`] has passed, nothing is painted if the display could be
+    /// blanked.
+    Blanked,
+}
+
+/// A windowing backend of winit on Linux, see [`EmbeddedOptions::backend`].
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DisplayBackend {
+    /// An X11 display server.
+    X11,
+    /// A Wayland compositor.
+    Wayland,
+    /// The DRM/KMS display controller, without a display server.
+    Kms,
+    /// The Linux framebuffer device, without a display server.
+    FbDev,
+}
+
 // ----------------------------------------------------------------------------
 
 /// Options when using `eframe` in a web page.
@@ -519,6 +796,26 @@ pub struct Frame {
     #[cfg(feature = "glow")]
     pub(crate) gl: Option<std::sync::Arc<glow::Context>>,
 
+    /// See [`NativeOptions::texture_uploader`].
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub(crate) texture_uploader: Option<crate::TextureUploader>,
+
+    /// See [`Frame::texture_budget`].
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub(crate) texture_budget: Option<egui_glow::TextureBudget>,
+
+    /// See [`Frame::request_screenshot`].
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub(crate) screenshot_requested: bool,
+
+    /// See [`Frame::screenshot`].
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub(crate) screenshot: Option<egui::ColorImage>,
+
+    /// See [`Frame::set_profiler_hud`].
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub(crate) profiler_hud: bool,
+
     /// Can be used to manage GPU resources for custom rendering with WGPU using [`egui::PaintCallback`]s.
     #[cfg(feature = "wgpu")]
     pub(crate) wgpu_render_state: Option<egui_wgpu::RenderState>,
@@ -565,6 +862,52 @@ impl Frame {
         self.gl.as_ref()
     }
 
+    /// The [`crate::TextureUploader`] requested with [`NativeOptions::texture_uploader`], the
+    /// first call takes it.
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub fn take_texture_uploader(&mut self) -> Option<crate::TextureUploader> {
+        self.texture_uploader.take()
+    }
+
+    /// The GPU memory the textures take, and where textures are marked evictable, see
+    /// [`NativeOptions::texture_budget`].
+    ///
+    /// Only available with the glow renderer.
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub fn texture_budget(&self) -> Option<&egui_glow::TextureBudget> {
+        self.texture_budget.as_ref()
+    }
+
+    /// Read the frame painted after this update back from the screen, it arrives in
+    /// [`Self::screenshot`] on the next update. That frame is painted in full.
+    ///
+    /// Only works with the glow renderer.
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_requested = true;
+    }
+
+    /// The screenshot asked for with [`Self::request_screenshot`] in the previous update, upright
+    /// and the size of the window in pixels. Only there during the update right after.
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub fn screenshot(&self) -> Option<&egui::ColorImage> {
+        self.screenshot.as_ref()
+    }
+
+    /// Show or hide the overlay of [`NativeOptions::profiler_hud`].
+    ///
+    /// Only works with the glow renderer.
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub fn set_profiler_hud(&mut self, enabled: bool) {
+        self.profiler_hud = enabled;
+    }
+
+    /// Whether the overlay of [`NativeOptions::profiler_hud`] is shown.
+    #[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+    pub fn profiler_hud(&self) -> bool {
+        self.profiler_hud
+    }
+
     /// The underlying WGPU render state.
     ///
     /// Only available when compiling with the `wgpu` feature and using [`Renderer::Wgpu`].
@@ -748,6 +1091,27 @@ pub struct IntegrationInfo {
     /// The OS native pixels-per-point
     pub native_pixels_per_point: Option<f32>,
 
+    /// Seconds from the start of a frame until it showed up on the display, for the most recent
+    /// frame that did.
+    ///
+    /// `None` if the platform can't tell, only kms and some fbdev drivers can.
+    pub frame_latency: Option<f32>,
+
+    /// Seconds between the starts of the previous frame and this one.
+    ///
+    /// `None` on the first frame, and on the web and with wgpu.
+    pub frame_time: Option<f32>,
+
+    /// How many requested repaints [`NativeOptions::max_fps`] and
+    /// [`NativeOptions::animation_fps`] have put off so far.
+    pub frames_skipped: u64,
+
+    /// Seconds the previous frame kept the CPU busy, from the start of [`App::update`] until
+    /// it was handed to the GPU, unlike [`Self::cpu_usage`] which only covers the UI code.
+    ///
+    /// `None` on the first frame, and on the web and with wgpu.
+    pub frame_cpu_time: Option<f32>,
+
     /// The position and size of the native window.
     #[cfg(not(target_arch = "wasm32"))]
     pub window_info: WindowInfo,
@@ -125,6 +125,9 @@ pub fn start_web(
 #[cfg(not(target_arch = "wasm32"))]
 mod native;
 
+#[cfg(all(feature = "glow", not(target_arch = "wasm32")))]
+pub use native::uploader::TextureUploader;
+
 /// This is how you start a native (desktop) app.
 ///
 /// The first argument is name of your app, used for the title bar of the native window
@@ -166,10 +166,29 @@ pub fn handle_app_output(
 // ----------------------------------------------------------------------------
 
 /// For loading/saving app state and/or egui memory to disk.
-pub fn create_storage(_app_name: &str) -> Option<Box<dyn epi::Storage>> {
+pub fn create_storage(
+    app_name: &str,
+    options: &epi::PersistenceOptions,
+) -> Option<Box<dyn epi::Storage>> {
+    if let Some(storage_backend) = &options.storage_backend {
+        return storage_backend(app_name);
+    }
+
     #[cfg(feature = "persistence")]
-    if let Some(storage) = super::file_storage::FileStorage::from_app_name(_app_name) {
-        return Some(Box::new(storage));
+    {
+        use super::file_storage::FileStorage;
+
+        let storage = match &options.storage_dir {
+            Some(storage_dir) => FileStorage::from_dir(storage_dir),
+            None => FileStorage::from_app_name(app_name),
+        };
+        if let Some(storage) = storage {
+            return Some(Box::new(
+                storage
+                    .with_sync(options.sync)
+                    .with_min_flush_interval(options.min_flush_interval),
+            ));
+        }
     }
     None
 }
@@ -186,6 +205,13 @@ pub struct EpiIntegration {
     /// When set, it is time to close the native window.
     close: bool,
     can_drag_window: bool,
+    /// See [`epi::NativeOptions::embedded`].
+    embedded: epi::EmbeddedOptions,
+    /// Drawn after [`epi::App::update`] while [`epi::Frame::profiler_hud`] is on.
+    #[cfg(feature = "glow")]
+    pub profiler_hud: super::profiler_hud::ProfilerHud,
+    /// How long the last [`epi::App::update`] took.
+    pub app_update_time: std::time::Duration,
 }
 
 impl EpiIntegration {
@@ -194,6 +220,7 @@ impl EpiIntegration {
         max_texture_side: usize,
         window: &winit::window::Window,
         system_theme: Option<Theme>,
+        embedded: &epi::EmbeddedOptions,
         storage: Option<Box<dyn epi::Storage>>,
         #[cfg(feature = "glow")] gl: Option<std::sync::Arc<glow::Context>>,
         #[cfg(feature = "wgpu")] wgpu_render_state: Option<egui_wgpu::RenderState>,
@@ -207,19 +234,35 @@ impl EpiIntegration {
                 system_theme,
                 cpu_usage: None,
                 native_pixels_per_point: Some(native_pixels_per_point(window)),
+                frame_latency: None,
+                frame_time: None,
+                frames_skipped: 0,
+                frame_cpu_time: None,
                 window_info: read_window_info(window, egui_ctx.pixels_per_point()),
             },
             output: Default::default(),
             storage,
             #[cfg(feature = "glow")]
             gl,
+            #[cfg(feature = "glow")]
+            texture_uploader: None,
+            #[cfg(feature = "glow")]
+            texture_budget: None,
+            #[cfg(feature = "glow")]
+            screenshot_requested: false,
+            #[cfg(feature = "glow")]
+            screenshot: None,
+            #[cfg(feature = "glow")]
+            profiler_hud: false,
             #[cfg(feature = "wgpu")]
             wgpu_render_state,
         };
 
         let mut egui_winit = egui_winit::State::new(event_loop);
         egui_winit.set_max_texture_side(max_texture_side);
-        let pixels_per_point = window.scale_factor() as f32;
+        let pixels_per_point = embedded
+            .pixels_per_point
+            .unwrap_or(window.scale_factor() as f32);
         egui_winit.set_pixels_per_point(pixels_per_point);
 
         Self {
@@ -230,6 +273,10 @@ impl EpiIntegration {
             pending_full_output: Default::default(),
             close: false,
             can_drag_window: false,
+            embedded: embedded.clone(),
+            #[cfg(feature = "glow")]
+            profiler_hud: Default::default(),
+            app_update_time: Default::default(),
         }
     }
 
@@ -251,6 +298,19 @@ impl EpiIntegration {
     pub fn on_event(&mut self, app: &mut dyn epi::App, event: &winit::event::WindowEvent<'_>) {
         use winit::event::{ElementState, MouseButton, WindowEvent};
 
+        if self.embedded.touch_only
+            && matches!(
+                event,
+                WindowEvent::CursorMoved { .. }
+                    | WindowEvent::CursorEntered { .. }
+                    | WindowEvent::CursorLeft { .. }
+                    | WindowEvent::MouseInput { .. }
+                    | WindowEvent::MouseWheel { .. }
+            )
+        {
+            return;
+        }
+
         match event {
             WindowEvent::CloseRequested => self.close = app.on_close_event(),
             WindowEvent::Destroyed => self.close = true,
@@ -263,6 +323,12 @@ impl EpiIntegration {
         }
 
         self.egui_winit.on_event(&self.egui_ctx, event);
+
+        if let (WindowEvent::ScaleFactorChanged { .. }, Some(pixels_per_point)) =
+            (event, self.embedded.pixels_per_point)
+        {
+            self.egui_winit.set_pixels_per_point(pixels_per_point);
+        }
     }
 
     pub fn update(
@@ -275,9 +341,19 @@ impl EpiIntegration {
         self.frame.info.window_info = read_window_info(window, self.egui_ctx.pixels_per_point());
         let raw_input = self.egui_winit.take_egui_input(window);
         let full_output = self.egui_ctx.run(raw_input, |egui_ctx| {
-            crate::profile_scope!("App::update");
-            app.update(egui_ctx, &mut self.frame);
+            {
+                crate::profile_scope!("App::update");
+                let app_update_start = std::time::Instant::now();
+                app.update(egui_ctx, &mut self.frame);
+                self.app_update_time = app_update_start.elapsed();
+            }
+            #[cfg(feature = "glow")]
+            self.profiler_hud.ui(egui_ctx, &mut self.frame.profiler_hud);
         });
+        #[cfg(feature = "glow")]
+        {
+            self.frame.screenshot = None;
+        }
         self.pending_full_output.append(full_output);
         let full_output = std::mem::take(&mut self.pending_full_output);
 
@@ -307,8 +383,11 @@ impl EpiIntegration {
     pub fn handle_platform_output(
         &mut self,
         window: &winit::window::Window,
-        platform_output: egui::PlatformOutput,
+        mut platform_output: egui::PlatformOutput,
     ) {
+        if self.embedded.hide_cursor {
+            platform_output.cursor_icon = egui::CursorIcon::None;
+        }
         self.egui_winit
             .handle_platform_output(window, &self.egui_ctx, platform_output);
     }
@@ -350,6 +429,17 @@ impl EpiIntegration {
             storage.flush();
         }
     }
+
+    /// Saves like [`Self::save`], then drops the storage so that it finishes writing, also what
+    /// it has put off. Call when the app exits.
+    pub fn save_and_close_storage(
+        &mut self,
+        app: &mut dyn epi::App,
+        window: &winit::window::Window,
+    ) {
+        self.save(app, window);
+        self.frame.storage = None;
+    }
 }
 
 #[cfg(feature = "persistence")]
@@ -1,16 +1,26 @@
 use std::{
     collections::HashMap,
+    io::Write as _,
     path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 // ----------------------------------------------------------------------------
 
 /// A key-value store backed by a [RON](https://github.com/ron-rs/ron) file on disk.
 /// Used to restore egui state, glium window position/size and app state.
+///
+/// The file is replaced atomically: each flush writes a new file next to it and renames it over
+/// the old one, so a crash or power cut leaves either the old or the new state, never a torn file.
 pub struct FileStorage {
     ron_filepath: PathBuf,
     kv: HashMap<String, String>,
     dirty: bool,
+    /// See [`Self::with_sync`].
+    sync: bool,
+    /// See [`Self::with_min_flush_interval`].
+    min_flush_interval: Duration,
+    last_flush: Option<Instant>,
     last_save_join_handle: Option<std::thread::JoinHandle<()>>,
 }
 
@@ -19,6 +29,10 @@ impl Drop for FileStorage {
         if let Some(join_handle) = self.last_save_join_handle.take() {
             join_handle.join().ok();
         }
+        // Left by a flush that came too soon after the last one
+        if self.dirty {
+            write_ron_atomically(&self.ron_filepath, &self.kv, self.sync);
+        }
     }
 }
 
@@ -30,29 +44,57 @@ impl FileStorage {
             kv: read_ron(&ron_filepath).unwrap_or_default(),
             ron_filepath,
             dirty: false,
+            sync: true,
+            min_flush_interval: Duration::ZERO,
+            last_flush: None,
             last_save_join_handle: None,
         }
     }
 
+    /// Store the state in `app.ron` in `dir`, which is created if missing.
+    pub fn from_dir(dir: impl Into<PathBuf>) -> Option<Self> {
+        let dir: PathBuf = dir.into();
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            tracing::warn!(
+                "Saving disabled: Failed to create app path at {:?}: {}",
+                dir,
+                err
+            );
+            None
+        } else {
+            Some(Self::from_ron_filepath(dir.join("app.ron")))
+        }
+    }
+
     /// Find a good place to put the files that the OS likes.
     pub fn from_app_name(app_name: &str) -> Option<Self> {
         if let Some(proj_dirs) = directories_next::ProjectDirs::from("", "", app_name) {
-            let data_dir = proj_dirs.data_dir().to_path_buf();
-            if let Err(err) = std::fs::create_dir_all(&data_dir) {
-                tracing::warn!(
-                    "Saving disabled: Failed to create app path at {:?}: {}",
-                    data_dir,
-                    err
-                );
-                None
-            } else {
-                Some(Self::from_ron_filepath(data_dir.join("app.ron")))
-            }
+            Self::from_dir(proj_dirs.data_dir())
         } else {
             tracing::warn!("Saving disabled: Failed to find path to data_dir.");
             None
         }
     }
+
+    /// Whether each flush waits for the file to reach the disk (`fsync`) before it replaces
+    /// the old one, and for the rename after. Without it a power cut shortly after a flush can
+    /// lose that flush, with it a flush that has finished survives.
+    ///
+    /// The default is `true`.
+    pub fn with_sync(mut self, sync: bool) -> Self {
+        self.sync = sync;
+        self
+    }
+
+    /// Flush at most once per `min_flush_interval`, to limit the wear on flash storage. A
+    /// flush that comes sooner is put off until the next flush after the interval, or until
+    /// the storage is dropped.
+    ///
+    /// The default is [`Duration::ZERO`], every flush with changes writes.
+    pub fn with_min_flush_interval(mut self, min_flush_interval: Duration) -> Self {
+        self.min_flush_interval = min_flush_interval;
+        self
+    }
 }
 
 impl crate::Storage for FileStorage {
@@ -69,10 +111,17 @@ impl crate::Storage for FileStorage {
 
     fn flush(&mut self) {
         if self.dirty {
+            if let Some(last_flush) = self.last_flush {
+                if last_flush.elapsed() < self.min_flush_interval {
+                    return;
+                }
+            }
             self.dirty = false;
+            self.last_flush = Some(Instant::now());
 
             let file_path = self.ron_filepath.clone();
             let kv = self.kv.clone();
+            let sync = self.sync;
 
             if let Some(join_handle) = self.last_save_join_handle.take() {
                 // wait for previous save to complete.
@@ -80,10 +129,7 @@ impl crate::Storage for FileStorage {
             }
 
             let join_handle = std::thread::spawn(move || {
-                let file = std::fs::File::create(&file_path).unwrap();
-                let config = Default::default();
-                ron::ser::to_writer_pretty(file, &kv, config).unwrap();
-                tracing::trace!("Persisted to {:?}", file_path);
+                write_ron_atomically(&file_path, &kv, sync);
             });
 
             self.last_save_join_handle = Some(join_handle);
@@ -91,6 +137,47 @@ impl crate::Storage for FileStorage {
     }
 }
 
+/// Writes `kv` to a temporary file next to `file_path` and renames it over `file_path`.
+fn write_ron_atomically(file_path: &Path, kv: &HashMap<String, String>, sync: bool) {
+    let ron = match ron::ser::to_string_pretty(kv, Default::default()) {
+        Ok(ron) => ron,
+        Err(err) => {
+            tracing::warn!("Failed to serialize app state: {}", err);
+            return;
+        }
+    };
+
+    match replace_file(file_path, ron.as_bytes(), sync) {
+        Ok(()) => tracing::trace!("Persisted to {:?}", file_path),
+        Err(err) => tracing::warn!("Failed to persist to {:?}: {}", file_path, err),
+    }
+}
+
+fn replace_file(file_path: &Path, contents: &[u8], sync: bool) -> std::io::Result<()> {
+    let mut tmp_path = file_path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+
+    let mut file = std::fs::File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    if sync {
+        file.sync_all()?;
+    }
+    // Windows can't rename open files
+    drop(file);
+    std::fs::rename(&tmp_path, file_path)?;
+
+    if sync {
+        // The rename only survives a power cut once the directory is on disk too. Not possible
+        // on every platform (e.g. Windows), the file itself is safe either way.
+        if let Some(dir) = file_path.parent() {
+            if let Ok(dir) = std::fs::File::open(dir) {
+                dir.sync_all().ok();
+            }
+        }
+    }
+    Ok(())
+}
+
 // ----------------------------------------------------------------------------
 
 fn read_ron<T>(ron_path: impl AsRef<Path>) -> Option<T>
@@ -114,3 +201,94 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Storage as _;
+
+    /// A fresh directory under the system temp dir, removed again on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!(
+                "eframe-file-storage-{}-{}",
+                name,
+                std::process::id()
+            ));
+            std::fs::remove_dir_all(&dir).ok();
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    fn stored(path: &Path) -> HashMap<String, String> {
+        read_ron(path).unwrap_or_default()
+    }
+
+    fn wait_for_save(storage: &mut FileStorage) {
+        if let Some(join_handle) = storage.last_save_join_handle.take() {
+            join_handle.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn keeps_the_old_file_when_writing_fails() {
+        let dir = TempDir::new("write-fails");
+        let path = dir.0.join("app.ron");
+        replace_file(&path, b"old", true).unwrap();
+
+        // A directory where the temporary file should go makes creating it fail
+        std::fs::create_dir(dir.0.join("app.ron.tmp")).unwrap();
+        assert!(replace_file(&path, b"new", true).is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"old");
+    }
+
+    #[test]
+    fn defers_a_flush_that_comes_too_soon() {
+        let dir = TempDir::new("deferred");
+        let path = dir.0.join("app.ron");
+        let mut storage = FileStorage::from_ron_filepath(&path)
+            .with_min_flush_interval(Duration::from_secs(3600));
+
+        storage.set_string("key", "first".to_owned());
+        storage.flush();
+        wait_for_save(&mut storage);
+        assert_eq!(stored(&path)["key"], "first");
+
+        storage.set_string("key", "second".to_owned());
+        storage.flush();
+        wait_for_save(&mut storage);
+        assert_eq!(stored(&path)["key"], "first");
+        assert!(storage.dirty);
+    }
+
+    #[test]
+    fn writes_pending_changes_on_drop() {
+        let dir = TempDir::new("drop");
+        let path = dir.0.join("app.ron");
+        let mut storage = FileStorage::from_ron_filepath(&path)
+            .with_min_flush_interval(Duration::from_secs(3600));
+
+        storage.set_string("key", "first".to_owned());
+        storage.flush();
+        storage.set_string("key", "second".to_owned());
+        storage.flush();
+        drop(storage);
+
+        assert_eq!(stored(&path)["key"], "second");
+        assert_eq!(
+            FileStorage::from_ron_filepath(&path)
+                .get_string("key")
+                .as_deref(),
+            Some("second")
+        );
+    }
+}
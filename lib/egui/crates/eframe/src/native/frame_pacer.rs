@@ -0,0 +1,123 @@
+//! Limits how often frames are painted, see [`crate::NativeOptions::max_fps`].
+
+use std::time::{Duration, Instant};
+
+pub(crate) struct FramePacer {
+    max_fps: Option<f32>,
+    animation_fps: Option<f32>,
+    last_frame_start: Option<Instant>,
+    /// Input arrived since the last frame, so the next one may come at `max_fps`
+    had_input: bool,
+    frames_skipped: u64,
+}
+
+impl FramePacer {
+    pub(crate) fn new(max_fps: Option<f32>, animation_fps: Option<f32>) -> Self {
+        Self {
+            max_fps,
+            animation_fps,
+            last_frame_start: None,
+            had_input: false,
+            frames_skipped: 0,
+        }
+    }
+
+    pub(crate) fn on_input(&mut self) {
+        self.had_input = true;
+    }
+
+    /// When the next frame may start, if it is too soon for it at `now`. The repaint is counted
+    /// as skipped then.
+    pub(crate) fn hold_back(&mut self, now: Instant) -> Option<Instant> {
+        let last_frame_start = self.last_frame_start?;
+        let fps = if self.had_input {
+            self.max_fps
+        } else {
+            match (self.max_fps, self.animation_fps) {
+                (Some(max_fps), Some(animation_fps)) => Some(max_fps.min(animation_fps)),
+                (max_fps, animation_fps) => max_fps.or(animation_fps),
+            }
+        };
+        let fps = fps.filter(|&fps| fps > 0.0)?;
+
+        let not_before = last_frame_start + Duration::from_secs_f32(1.0 / fps);
+        if now < not_before {
+            self.frames_skipped += 1;
+            Some(not_before)
+        } else {
+            None
+        }
+    }
+
+    /// Call when a frame starts being painted. Returns how long ago the previous one did.
+    pub(crate) fn frame_started(&mut self, now: Instant) -> Option<Duration> {
+        self.had_input = false;
+        let last_frame_start = self.last_frame_start.replace(now)?;
+        Some(now.saturating_duration_since(last_frame_start))
+    }
+
+    pub(crate) fn frames_skipped(&self) -> u64 {
+        self.frames_skipped
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::FramePacer;
+
+    fn frame_time(fps: f32) -> Duration {
+        Duration::from_secs_f32(1.0 / fps)
+    }
+
+    #[test]
+    fn the_first_frame_is_not_held_back() {
+        let mut pacer = FramePacer::new(Some(30.0), Some(10.0));
+        assert_eq!(pacer.hold_back(Instant::now()), None);
+    }
+
+    #[test]
+    fn animations_run_at_animation_fps() {
+        let mut pacer = FramePacer::new(Some(30.0), Some(10.0));
+        let start = Instant::now();
+        pacer.frame_started(start);
+
+        let now = start + Duration::from_millis(50);
+        assert_eq!(pacer.hold_back(now), Some(start + frame_time(10.0)));
+        assert_eq!(pacer.frames_skipped(), 1);
+        assert_eq!(pacer.hold_back(start + frame_time(10.0)), None);
+    }
+
+    #[test]
+    fn input_runs_at_max_fps() {
+        let mut pacer = FramePacer::new(Some(30.0), Some(10.0));
+        let start = Instant::now();
+        pacer.frame_started(start);
+        pacer.on_input();
+
+        let now = start + Duration::from_millis(20);
+        assert_eq!(pacer.hold_back(now), Some(start + frame_time(30.0)));
+        assert_eq!(pacer.hold_back(start + Duration::from_millis(50)), None);
+
+        // Until the next frame, which goes back to the animation rate
+        let next = start + Duration::from_millis(50);
+        pacer.frame_started(next);
+        assert_eq!(
+            pacer.hold_back(next + Duration::from_millis(50)),
+            Some(next + frame_time(10.0))
+        );
+    }
+
+    #[test]
+    fn animation_fps_does_not_limit_input() {
+        let mut pacer = FramePacer::new(None, Some(10.0));
+        let start = Instant::now();
+        pacer.frame_started(start);
+        assert!(pacer.hold_back(start).is_some());
+
+        pacer.on_input();
+        assert_eq!(pacer.hold_back(start), None);
+        assert_eq!(pacer.frames_skipped(), 1);
+    }
+}
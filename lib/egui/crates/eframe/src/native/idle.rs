@@ -0,0 +1,374 @@
+//! Dims and blanks the display after a while without input, see
+//! [`crate::NativeOptions::idle`].
+
+use std::time::Instant;
+
+use winit::event::{ElementState, TouchPhase, WindowEvent};
+
+use crate::epi::{self, IdleState};
+
+/// The backlight and power of the display, so that [`IdleMonitor`] can be tested without a
+/// window.
+pub(crate) trait IdleDisplay {
+    /// The backlight brightness from 0 to 1, `None` if there is no backlight to dim.
+    fn backlight(&self) -> Option<f32>;
+
+    /// Returns whether the brightness was set.
+    fn set_backlight(&self, brightness: f32) -> bool;
+
+    /// Returns whether the display was blanked or unblanked, which only fbdev can do.
+    fn set_blanked(&self, blanked: bool) -> bool;
+}
+
+pub(crate) struct IdleMonitor {
+    options: epi::IdleOptions,
+    state: IdleState,
+    last_input: Instant,
+    /// The backlight brightness at startup, restored on waking up and on exit
+    brightness: Option<f32>,
+    /// The backlight was dimmed
+    dimmed: bool,
+    /// The display was blanked, nothing is painted and waking input is swallowed only then
+    blanked: bool,
+    /// The display was woken by input that is still going on, e.g. a finger still on the
+    /// screen. Input is swallowed until it is released.
+    swallowing: bool,
+}
+
+impl IdleMonitor {
+    pub(crate) fn new(options: epi::IdleOptions, display: &impl IdleDisplay, now: Instant) -> Self {
+        Self {
+            options,
+            state: IdleState::Active,
+            last_input: now,
+            brightness: options.dim_after.and_then(|_| display.backlight()),
+            dimmed: false,
+            blanked: false,
+            swallowing: false,
+        }
+    }
+
+    /// The display is blanked, which it may not be in [`IdleState::Blanked`] if the backend
+    /// can't blank it.
+    pub(crate) fn is_blanked(&self) -> bool {
+        self.blanked
+    }
+
+    /// Wakes up on input. Returns whether the event should reach egui.
+    pub(crate) fn on_event(
+        &mut self,
+        display: &impl IdleDisplay,
+        app: &mut dyn epi::App,
+        event: &WindowEvent<'_>,
+        now: Instant,
+    ) -> bool {
+        if !is_input(event) {
+            return true;
+        }
+
+        self.last_input = now;
+        if self.blanked {
+            // Nothing was on the screen, so nothing should be clicked
+            self.swallowing = true;
+        }
+        if self.state != IdleState::Active {
+            self.set_state(display, app, IdleState::Active);
+        }
+
+        if self.swallowing {
+            if is_release(event) {
+                self.swallowing = false;
+            }
+            return false;
+        }
+        true
+    }
+
+    /// Dims or blanks the display once it is time to. Returns when to check again.
+    pub(crate) fn update(
+        &mut self,
+        display: &impl IdleDisplay,
+        app: &mut dyn epi::App,
+        now: Instant,
+    ) -> Option<Instant> {
+        let idle_for = now.saturating_duration_since(self.last_input);
+        let is_due = |after: Option<std::time::Duration>| after.map_or(false, |a| idle_for >= a);
+
+        let state = if is_due(self.options.blank_after) {
+            IdleState::Blanked
+        } else if is_due(self.options.dim_after) {
+            IdleState::Dimmed
+        } else {
+            IdleState::Active
+        };
+        if state != self.state {
+            self.set_state(display, app, state);
+        }
+
+        [self.options.dim_after, self.options.blank_after]
+            .into_iter()
+            .flatten()
+            .map(|after| self.last_input + after)
+            .filter(|&deadline| deadline > now)
+            .min()
+    }
+
+    /// Unblanks the display and restores the backlight, e.g. on exit.
+    pub(crate) fn restore(&mut self, display: &impl IdleDisplay) {
+        if self.blanked {
+            display.set_blanked(false);
+            self.blanked = false;
+        }
+        if self.dimmed {
+            if let Some(brightness) = self.brightness {
+                display.set_backlight(brightness);
+            }
+            self.dimmed = false;
+        }
+    }
+
+    fn set_state(&mut self, display: &impl IdleDisplay, app: &mut dyn epi::App, state: IdleState) {
+        if state == IdleState::Active {
+            self.restore(display);
+            app.on_idle(state);
+        } else {
+            app.on_idle(state);
+            if state == IdleState::Blanked && !self.blanked {
+                self.blanked = display.set_blanked(true);
+            } else if state == IdleState::Dimmed && !self.dimmed && self.brightness.is_some() {
+                self.dimmed = display.set_backlight(self.options.dim_brightness);
+            }
+        }
+        self.state = state;
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl IdleDisplay for winit::window::Window {
+    fn backlight(&self) -> Option<f32> {
+        use winit::platform::unix::WindowExtUnix as _;
+
+        self.fbdev_backlight()
+    }
+
+    fn set_backlight(&self, brightness: f32) -> bool {
+        use winit::platform::unix::WindowExtUnix as _;
+
+        check(self.fbdev_set_backlight(brightness), "set the backlight")
+    }
+
+    fn set_blanked(&self, blanked: bool) -> bool {
+        use winit::platform::unix::WindowExtUnix as _;
+
+        let what = if blanked {
+            "blank the display"
+        } else {
+            "unblank the display"
+        };
+        check(self.fbdev_set_blanked(blanked), what)
+    }
+}
+
+/// Warns about failures other than the backend not supporting it. Returns whether it worked.
+#[cfg(target_os = "linux")]
+fn check(result: Result<(), winit::error::ExternalError>, what: &str) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(winit::error::ExternalError::NotSupported(_)) => false,
+        Err(err) => {
+            tracing::warn!("Failed to {}: {}", what, err);
+            false
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl IdleDisplay for winit::window::Window {
+    fn backlight(&self) -> Option<f32> {
+        None
+    }
+
+    fn set_backlight(&self, _brightness: f32) -> bool {
+        false
+    }
+
+    fn set_blanked(&self, _blanked: bool) -> bool {
+        false
+    }
+}
+
+pub(crate) fn is_input(event: &WindowEvent<'_>) -> bool {
+    matches!(
+        event,
+        WindowEvent::KeyboardInput { .. }
+            | WindowEvent::ReceivedCharacter(_)
+            | WindowEvent::CursorMoved { .. }
+            | WindowEvent::MouseInput { .. }
+            | WindowEvent::MouseWheel { .. }
+            | WindowEvent::Touch(_)
+    )
+}
+
+/// A finger lifted, or a button or key let go of.
+fn is_release(event: &WindowEvent<'_>) -> bool {
+    match event {
+        WindowEvent::KeyboardInput { input, .. } => input.state == ElementState::Released,
+        WindowEvent::MouseInput { state, .. } => *state == ElementState::Released,
+        WindowEvent::Touch(touch) => {
+            matches!(touch.phase, TouchPhase::Ended | TouchPhase::Cancelled)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::time::{Duration, Instant};
+
+    use winit::dpi::PhysicalPosition;
+    use winit::event::{DeviceId, Touch, TouchPhase, WindowEvent};
+
+    use super::{IdleDisplay, IdleMonitor};
+    use crate::epi::{self, IdleOptions, IdleState};
+
+    struct FakeDisplay {
+        can_blank: bool,
+        blanked: Cell<bool>,
+        brightness: Cell<f32>,
+    }
+
+    impl FakeDisplay {
+        fn new(can_blank: bool) -> Self {
+            Self {
+                can_blank,
+                blanked: Cell::new(false),
+                brightness: Cell::new(0.8),
+            }
+        }
+    }
+
+    impl IdleDisplay for FakeDisplay {
+        fn backlight(&self) -> Option<f32> {
+            Some(self.brightness.get())
+        }
+
+        fn set_backlight(&self, brightness: f32) -> bool {
+            self.brightness.set(brightness);
+            true
+        }
+
+        fn set_blanked(&self, blanked: bool) -> bool {
+            if self.can_blank {
+                self.blanked.set(blanked);
+            }
+            self.can_blank
+        }
+    }
+
+    #[derive(Default)]
+    struct FakeApp {
+        states: Vec<IdleState>,
+    }
+
+    impl epi::App for FakeApp {
+        fn update(&mut self, _ctx: &egui::Context, _frame: &mut epi::Frame) {}
+
+        fn on_idle(&mut self, state: IdleState) {
+            self.states.push(state);
+        }
+    }
+
+    const OPTIONS: IdleOptions = IdleOptions {
+        dim_after: Some(Duration::from_secs(10)),
+        dim_brightness: 0.2,
+        blank_after: Some(Duration::from_secs(30)),
+    };
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[allow(unsafe_code)]
+    fn touch(phase: TouchPhase) -> WindowEvent<'static> {
+        WindowEvent::Touch(Touch {
+            // Only compared, never used to look anything up
+            device_id: unsafe { DeviceId::dummy() },
+            phase,
+            location: PhysicalPosition::new(0.0, 0.0),
+            force: None,
+            id: 0,
+        })
+    }
+
+    #[test]
+    fn dims_and_blanks_at_the_deadlines() {
+        let display = FakeDisplay::new(true);
+        let mut app = FakeApp::default();
+        let start = Instant::now();
+        let mut idle = IdleMonitor::new(OPTIONS, &display, start);
+
+        assert_eq!(
+            idle.update(&display, &mut app, start + secs(5)),
+            Some(start + secs(10))
+        );
+        assert!(app.states.is_empty());
+
+        assert_eq!(
+            idle.update(&display, &mut app, start + secs(10)),
+            Some(start + secs(30))
+        );
+        assert_eq!(app.states, [IdleState::Dimmed]);
+        assert_eq!(display.brightness.get(), 0.2);
+
+        assert_eq!(idle.update(&display, &mut app, start + secs(30)), None);
+        assert_eq!(app.states, [IdleState::Dimmed, IdleState::Blanked]);
+        assert!(idle.is_blanked());
+        assert!(display.blanked.get());
+
+        idle.restore(&display);
+        assert!(!display.blanked.get());
+        assert_eq!(display.brightness.get(), 0.8);
+    }
+
+    #[test]
+    fn swallows_the_waking_input_until_released() {
+        let display = FakeDisplay::new(true);
+        let mut app = FakeApp::default();
+        let start = Instant::now();
+        let mut idle = IdleMonitor::new(OPTIONS, &display, start);
+        idle.update(&display, &mut app, start + secs(30));
+
+        let now = start + secs(31);
+        assert!(!idle.on_event(&display, &mut app, &touch(TouchPhase::Started), now));
+        assert_eq!(app.states.last(), Some(&IdleState::Active));
+        assert!(!idle.is_blanked());
+        assert!(!display.blanked.get());
+        assert_eq!(display.brightness.get(), 0.8);
+
+        assert!(!idle.on_event(&display, &mut app, &touch(TouchPhase::Moved), now));
+        assert!(!idle.on_event(&display, &mut app, &touch(TouchPhase::Ended), now));
+        assert!(idle.on_event(&display, &mut app, &touch(TouchPhase::Started), now));
+
+        // The deadlines start over from the last input
+        assert_eq!(idle.update(&display, &mut app, now), Some(now + secs(10)));
+    }
+
+    #[test]
+    fn keeps_going_when_the_display_cant_be_blanked() {
+        let display = FakeDisplay::new(false);
+        let mut app = FakeApp::default();
+        let start = Instant::now();
+        let mut idle = IdleMonitor::new(OPTIONS, &display, start);
+        idle.update(&display, &mut app, start + secs(30));
+
+        assert_eq!(app.states.last(), Some(&IdleState::Blanked));
+        assert!(!idle.is_blanked());
+        assert!(idle.on_event(
+            &display,
+            &mut app,
+            &touch(TouchPhase::Started),
+            start + secs(31)
+        ));
+    }
+}
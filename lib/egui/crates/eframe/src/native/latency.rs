@@ -0,0 +1,101 @@
+//! Measures how long frames take from starting until they are on the display, see
+//! [`crate::IntegrationInfo::frame_latency`].
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Matches the frames glutin reports as presented to when they started.
+#[derive(Default)]
+pub(crate) struct FrameLatency {
+    /// Frames swapped so far, counted like glutin counts them
+    frames_swapped: u64,
+    /// When the frames that haven't been presented yet started, on the presentation clock
+    frame_starts: VecDeque<(u64, Duration)>,
+}
+
+impl FrameLatency {
+    /// Call after swapping a frame that started at `frame_start`, with the latest presentation
+    /// timestamp of the context. Returns the latency of the presented frame, if it is one of
+    /// ours.
+    pub(crate) fn frame_swapped(
+        &mut self,
+        frame_start: Duration,
+        timestamp: Option<glutin::PresentationTimestamp>,
+    ) -> Option<Duration> {
+        self.frames_swapped += 1;
+        self.frame_starts
+            .push_back((self.frames_swapped, frame_start));
+        // Nothing gets presented while the display is off
+        if self.frame_starts.len() > 16 {
+            self.frame_starts.pop_front();
+        }
+
+        let timestamp = timestamp?;
+        let mut latency = None;
+        while let Some(&(frame, start)) = self.frame_starts.front() {
+            if frame > timestamp.frame {
+                break;
+            }
+            if frame == timestamp.frame {
+                latency = Some(timestamp.presented.saturating_sub(start));
+            }
+            self.frame_starts.pop_front();
+        }
+        latency
+    }
+
+    /// Call once the context was created again, which counts its frames from the start.
+    pub(crate) fn context_recreated(&mut self) {
+        self.frames_swapped = 0;
+        self.frame_starts.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::FrameLatency;
+
+    fn presented(frame: u64, millis: u64) -> Option<glutin::PresentationTimestamp> {
+        Some(glutin::PresentationTimestamp {
+            frame,
+            presented: Duration::from_millis(millis),
+        })
+    }
+
+    #[test]
+    fn matches_the_presented_frame() {
+        let mut latency = FrameLatency::default();
+        let ms = Duration::from_millis;
+        assert_eq!(latency.frame_swapped(ms(0), None), None);
+        // Frame 1 is on the screen by the time frame 2 was swapped
+        assert_eq!(
+            latency.frame_swapped(ms(16), presented(1, 20)),
+            Some(ms(20))
+        );
+        // Frame 2 isn't yet
+        assert_eq!(latency.frame_swapped(ms(32), presented(1, 20)), None);
+        assert_eq!(
+            latency.frame_swapped(ms(48), presented(3, 55)),
+            Some(ms(23))
+        );
+    }
+
+    #[test]
+    fn counts_again_after_the_context_was_recreated() {
+        let mut latency = FrameLatency::default();
+        let ms = Duration::from_millis;
+        for frame in 1..=5 {
+            latency.frame_swapped(ms(frame * 16), presented(frame, frame * 16 + 5));
+        }
+
+        // The new context counts its swaps from 1 again
+        latency.context_recreated();
+        assert_eq!(latency.frame_swapped(ms(1000), None), None);
+        assert_eq!(
+            latency.frame_swapped(ms(1016), presented(1, 1010)),
+            Some(ms(10))
+        );
+    }
+}
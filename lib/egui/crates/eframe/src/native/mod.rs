@@ -1,5 +1,15 @@
 mod epi_integration;
+#[cfg(feature = "glow")]
+mod frame_pacer;
+#[cfg(feature = "glow")]
+mod idle;
+#[cfg(all(feature = "glow", target_os = "linux"))]
+mod latency;
+#[cfg(feature = "glow")]
+mod profiler_hud;
 pub mod run;
+#[cfg(feature = "glow")]
+pub mod uploader;
 
 /// File storage which can be used by native backends.
 #[cfg(feature = "persistence")]
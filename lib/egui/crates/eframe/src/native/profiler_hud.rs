@@ -0,0 +1,134 @@
+//! An overlay with the timings of the last frames, for profiling on a device without a
+//! `puffin_viewer` at hand. See [`crate::NativeOptions::profiler_hud`].
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How many frames the averages and maxima are over.
+const HISTORY: usize = 60;
+
+/// Where the time of one painted frame went, on the CPU unless noted.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FrameTimes {
+    pub update: Duration,
+    pub tessellate: Duration,
+    pub upload: Duration,
+    /// Issuing the GL calls of `paint_primitives`, not the GPU running them.
+    pub paint: Duration,
+    /// A few frames late, `None` without timer queries.
+    pub gpu: Option<Duration>,
+}
+
+#[derive(Default)]
+pub(crate) struct ProfilerHud {
+    /// Toggles the HUD when all are held down, see [`crate::NativeOptions::profiler_hud_chord`].
+    chord: Vec<egui::Key>,
+    history: VecDeque<FrameTimes>,
+}
+
+impl ProfilerHud {
+    pub(crate) fn new(chord: Vec<egui::Key>) -> Self {
+        Self {
+            chord,
+            history: VecDeque::with_capacity(HISTORY),
+        }
+    }
+
+    /// Toggles `enabled` on the chord, then shows the HUD if enabled.
+    pub(crate) fn ui(&mut self, ctx: &egui::Context, enabled: &mut bool) {
+        if self.chord_pressed(&ctx.input()) {
+            *enabled = !*enabled;
+        }
+        if !*enabled {
+            self.history.clear();
+            return;
+        }
+
+        egui::Area::new("eframe_profiler_hud")
+            .order(egui::Order::Debug)
+            .anchor(egui::Align2::RIGHT_TOP, egui::vec2(-8.0, 8.0))
+            .interactable(false)
+            .show(ctx, |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+                    self.labels(ctx, ui);
+                });
+            });
+    }
+
+    pub(crate) fn record(&mut self, times: FrameTimes) {
+        if self.history.len() == HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(times);
+    }
+
+    /// All keys of the chord held, and the last of them just pressed.
+    fn chord_pressed(&self, input: &egui::InputState) -> bool {
+        !self.chord.is_empty()
+            && self.chord.iter().all(|key| input.keys_down.contains(key))
+            && self.chord.iter().any(|&key| input.key_pressed(key))
+    }
+
+    fn labels(&self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.label(format!("{:<11}{:>7}{:>7}", "ms", "avg", "max"));
+        let phases: [(&str, fn(&FrameTimes) -> Duration); 4] = [
+            ("update", |times| times.update),
+            ("tessellate", |times| times.tessellate),
+            ("upload", |times| times.upload),
+            ("paint", |times| times.paint),
+        ];
+        for (name, phase) in phases {
+            let (avg, max) = avg_max(self.history.iter().map(phase));
+            ui.label(format!("{:<11}{:>7.2}{:>7.2}", name, avg, max));
+        }
+        if self.history.iter().any(|times| times.gpu.is_some()) {
+            let (avg, max) = avg_max(self.history.iter().filter_map(|times| times.gpu));
+            ui.label(format!("{:<11}{:>7.2}{:>7.2}", "gpu", avg, max));
+        } else {
+            ui.label(format!("{:<11}{:>14}", "gpu", "n/a"));
+        }
+
+        let stats = ctx.paint_stats();
+        ui.label(format!(
+            "{} triangles, {} vertices",
+            stats.indices.num_elements() / 3,
+            stats.vertices.num_elements()
+        ));
+        ui.label(format!(
+            "{} primitives, {} callbacks",
+            stats.clipped_primitives.num_elements(),
+            stats.num_callbacks
+        ));
+
+        let (num_textures, gpu_usage) = {
+            let tex_manager = ctx.tex_manager();
+            let tex_manager = tex_manager.read();
+            (tex_manager.num_allocated(), tex_manager.gpu_usage())
+        };
+        match gpu_usage {
+            Some(usage) => ui.label(format!(
+                "{} textures, {:.1} MB",
+                num_textures,
+                usage.bytes_used as f64 * 1e-6
+            )),
+            None => ui.label(format!("{} textures", num_textures)),
+        };
+    }
+}
+
+/// The average and maximum in milliseconds.
+fn avg_max(durations: impl Iterator<Item = Duration>) -> (f64, f64) {
+    let (mut sum, mut max, mut count) = (0.0, 0.0_f64, 0);
+    for duration in durations {
+        let ms = duration.as_secs_f64() * 1e3;
+        sum += ms;
+        max = max.max(ms);
+        count += 1;
+    }
+    if count == 0 {
+        (0.0, 0.0)
+    } else {
+        (sum / count as f64, max)
+    }
+}
@@ -33,14 +33,19 @@ fn create_display(
         HardwareAcceleration::Off => Some(false),
     };
 
+    let mut context_builder = glutin::ContextBuilder::new()
+        .with_hardware_acceleration(hardware_acceleration)
+        .with_depth_buffer(native_options.depth_buffer)
+        .with_multisampling(native_options.multisampling)
+        .with_srgb(true)
+        .with_stencil_buffer(native_options.stencil_buffer)
+        .with_vsync(native_options.vsync);
+    if let Some(swap_interval) = native_options.swap_interval {
+        context_builder = context_builder.with_swap_interval(swap_interval);
+    }
+
     let gl_window = unsafe {
-        glutin::ContextBuilder::new()
-            .with_hardware_acceleration(hardware_acceleration)
-            .with_depth_buffer(native_options.depth_buffer)
-            .with_multisampling(native_options.multisampling)
-            .with_srgb(true)
-            .with_stencil_buffer(native_options.stencil_buffer)
-            .with_vsync(native_options.vsync)
+        context_builder
             .build_windowed(window_builder, event_loop)
             .unwrap()
             .make_current()
@@ -52,6 +57,22 @@ fn create_display(
     (gl_window, gl)
 }
 
+/// A headless context sharing textures with `gl_window`, see [`NativeOptions::texture_uploader`].
+#[cfg(feature = "glow")]
+fn create_upload_context(
+    gl_window: &glutin::WindowedContext<glutin::PossiblyCurrent>,
+    event_loop: &EventLoop<RequestRepaintEvent>,
+) -> Option<glutin::Context<glutin::NotCurrent>> {
+    crate::profile_function!();
+
+    // Only the textures are shared, a pbuffer is the surface every EGL driver has
+    glutin::ContextBuilder::new()
+        .with_shared_lists(gl_window.context())
+        .build_headless(event_loop, glutin::dpi::PhysicalSize::new(1, 1))
+        .map_err(|err| tracing::warn!("Failed to create the texture upload context: {}", err))
+        .ok()
+}
+
 // ----------------------------------------------------------------------------
 
 pub use epi::NativeOptions;
@@ -67,6 +88,8 @@ trait WinitApp {
     fn is_focused(&self) -> bool;
     fn integration(&self) -> &EpiIntegration;
     fn window(&self) -> &winit::window::Window;
+    /// Nothing is painted while the display is blanked, so there is no point in redrawing.
+    fn is_blanked(&self) -> bool;
     fn save_and_destroy(&mut self);
     fn paint(&mut self) -> EventResult;
     fn on_event(&mut self, event: winit::event::Event<'_, RequestRepaintEvent>) -> EventResult;
@@ -76,15 +99,66 @@ trait WinitApp {
 ///
 /// We reuse the event-loop so we can support closing and opening an eframe window
 /// multiple times. This is just a limitation of winit.
-fn with_event_loop(f: impl FnOnce(&mut EventLoop<RequestRepaintEvent>)) {
+///
+/// It is created with the [`epi::EmbeddedOptions`] of the first window.
+fn with_event_loop(
+    native_options: &epi::NativeOptions,
+    f: impl FnOnce(&mut EventLoop<RequestRepaintEvent>),
+) {
     use std::cell::RefCell;
-    thread_local!(static EVENT_LOOP: RefCell<EventLoop<RequestRepaintEvent>> = RefCell::new(winit::event_loop::EventLoopBuilder::with_user_event().build()));
+    thread_local!(static EVENT_LOOP: RefCell<Option<EventLoop<RequestRepaintEvent>>> = RefCell::new(None));
 
     EVENT_LOOP.with(|event_loop| {
-        f(&mut *event_loop.borrow_mut());
+        let mut event_loop = event_loop.borrow_mut();
+        f(event_loop.get_or_insert_with(|| create_event_loop(native_options)));
     });
 }
 
+fn create_event_loop(native_options: &epi::NativeOptions) -> EventLoop<RequestRepaintEvent> {
+    let mut builder = winit::event_loop::EventLoopBuilder::with_user_event();
+    #[cfg(target_os = "linux")]
+    apply_embedded_options(&mut builder, &native_options.embedded);
+    #[cfg(not(target_os = "linux"))]
+    let _ = native_options;
+    builder.build()
+}
+
+/// Passes the [`epi::EmbeddedOptions`] that are about the display and input devices on to winit.
+#[cfg(target_os = "linux")]
+fn apply_embedded_options(
+    builder: &mut winit::event_loop::EventLoopBuilder<RequestRepaintEvent>,
+    embedded: &epi::EmbeddedOptions,
+) {
+    use winit::platform::unix::{EventLoopBuilderExtUnix as _, UnixBackend};
+
+    if let Some(backend) = embedded.backend {
+        builder.with_unix_backend(match backend {
+            epi::DisplayBackend::X11 => UnixBackend::X11,
+            epi::DisplayBackend::Wayland => UnixBackend::Wayland,
+            epi::DisplayBackend::Kms => UnixBackend::Kms,
+            epi::DisplayBackend::FbDev => UnixBackend::FbDev,
+        });
+    }
+    if let Some(device_path) = &embedded.device_path {
+        builder.with_device_path(device_path.clone());
+    }
+    // Also for `Rotation::None`, touches may be calibrated otherwise
+    #[cfg(feature = "glow")]
+    builder.with_touch_calibration(touch_calibration(embedded.rotation));
+}
+
+/// The libinput calibration matrix that maps touches on a panel to the UI painted on it with
+/// `rotation`, the inverse of the rotation.
+#[cfg(all(target_os = "linux", feature = "glow"))]
+fn touch_calibration(rotation: egui_glow::Rotation) -> [f32; 6] {
+    match rotation {
+        egui_glow::Rotation::None => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+        egui_glow::Rotation::Cw90 => [0.0, 1.0, 0.0, -1.0, 0.0, 1.0],
+        egui_glow::Rotation::Cw180 => [-1.0, 0.0, 1.0, 0.0, -1.0, 1.0],
+        egui_glow::Rotation::Cw270 => [0.0, -1.0, 1.0, 1.0, 0.0, 0.0],
+    }
+}
+
 fn run_and_return(event_loop: &mut EventLoop<RequestRepaintEvent>, mut winit_app: impl WinitApp) {
     use winit::platform::run_return::EventLoopExtRunReturn as _;
 
@@ -139,6 +213,7 @@ fn run_and_return(event_loop: &mut EventLoop<RequestRepaintEvent>, mut winit_app
         }
 
         *control_flow = match next_repaint_time.checked_duration_since(Instant::now()) {
+            None if winit_app.is_blanked() => ControlFlow::Wait,
             None => {
                 winit_app.window().request_redraw();
                 ControlFlow::Poll
@@ -210,6 +285,7 @@ fn run_and_exit(
         }
 
         *control_flow = match next_repaint_time.checked_duration_since(Instant::now()) {
+            None if winit_app.is_blanked() => ControlFlow::Wait,
             None => {
                 winit_app.window().request_redraw();
                 ControlFlow::Poll
@@ -230,6 +306,15 @@ mod glow_integration {
 
     use super::*;
 
+    fn create_painter(gl: &Arc<glow::Context>, gamma_space_rendering: bool) -> egui_glow::Painter {
+        let painter = if gamma_space_rendering {
+            egui_glow::Painter::new_gamma_space(gl.clone(), "")
+        } else {
+            egui_glow::Painter::new(gl.clone(), None, "")
+        };
+        painter.unwrap_or_else(|error| panic!("some OpenGL error occurred {}\n", error))
+    }
+
     struct GlowWinitApp {
         gl_window: glutin::WindowedContext<glutin::PossiblyCurrent>,
         gl: Arc<glow::Context>,
@@ -237,6 +322,18 @@ mod glow_integration {
         integration: epi_integration::EpiIntegration,
         app: Box<dyn epi::App>,
         is_focused: bool,
+        /// Between `Event::Suspended` and `Event::Resumed`
+        suspended: bool,
+        /// The surface is gone, either suspended or lost, and has to be recreated before painting
+        needs_resume: bool,
+        /// See [`epi::NativeOptions::gamma_space_rendering`], for creating the painter again
+        gamma_space_rendering: bool,
+        /// Finds what changed since the back buffer was drawn to, so only that gets repainted
+        damage_tracker: egui_glow::DamageTracker,
+        idle: super::idle::IdleMonitor,
+        frame_pacer: super::frame_pacer::FramePacer,
+        #[cfg(target_os = "linux")]
+        frame_latency: super::latency::FrameLatency,
     }
 
     impl GlowWinitApp {
@@ -246,7 +343,7 @@ mod glow_integration {
             native_options: &epi::NativeOptions,
             app_creator: epi::AppCreator,
         ) -> Self {
-            let storage = epi_integration::create_storage(app_name);
+            let storage = epi_integration::create_storage(app_name, &native_options.persistence);
             let window_settings = epi_integration::load_window_settings(storage.as_deref());
 
             let window_builder = epi_integration::window_builder(native_options, &window_settings)
@@ -254,8 +351,21 @@ mod glow_integration {
             let (gl_window, gl) = create_display(native_options, window_builder, event_loop);
             let gl = Arc::new(gl);
 
-            let painter = egui_glow::Painter::new(gl.clone(), None, "")
-                .unwrap_or_else(|error| panic!("some OpenGL error occurred {}\n", error));
+            #[allow(unused_mut)]
+            let mut painter = create_painter(&gl, native_options.gamma_space_rendering);
+            // Only an fbdev context can be lost, textures are uploaded again from these then
+            #[cfg(target_os = "linux")]
+            {
+                use winit::platform::unix::WindowExtUnix as _;
+                painter.set_keep_texture_copies(
+                    native_options.embedded.keep_texture_copies
+                        && gl_window.window().fbdev_display_state().is_some(),
+                );
+            }
+            painter.set_output_rotation(native_options.embedded.rotation);
+            painter
+                .texture_budget()
+                .set_budget(native_options.texture_budget);
 
             let system_theme = native_options.system_theme();
             let mut integration = epi_integration::EpiIntegration::new(
@@ -263,6 +373,7 @@ mod glow_integration {
                 painter.max_texture_side(),
                 gl_window.window(),
                 system_theme,
+                &native_options.embedded,
                 storage,
                 Some(gl.clone()),
                 #[cfg(feature = "wgpu")]
@@ -271,6 +382,17 @@ mod glow_integration {
             let theme = system_theme.unwrap_or(native_options.default_theme);
             integration.egui_ctx.set_visuals(theme.egui_visuals());
 
+            integration.frame.texture_budget = Some(painter.texture_budget());
+            integration.frame.profiler_hud = native_options.profiler_hud;
+            integration.profiler_hud =
+                super::profiler_hud::ProfilerHud::new(native_options.profiler_hud_chord.clone());
+            if native_options.texture_uploader {
+                integration.frame.texture_uploader =
+                    create_upload_context(&gl_window, event_loop).map(|context| {
+                        crate::TextureUploader::new(context, painter.upload_queue())
+                    });
+            }
+
             {
                 let event_loop_proxy = egui::mutex::Mutex::new(event_loop.create_proxy());
                 integration.egui_ctx.set_request_repaint_callback(move || {
@@ -291,6 +413,12 @@ mod glow_integration {
                 integration.warm_up(app.as_mut(), gl_window.window());
             }
 
+            let idle = super::idle::IdleMonitor::new(
+                native_options.idle,
+                gl_window.window(),
+                Instant::now(),
+            );
+
             Self {
                 gl_window,
                 gl,
@@ -298,8 +426,60 @@ mod glow_integration {
                 integration,
                 app,
                 is_focused: true,
+                suspended: false,
+                needs_resume: false,
+                gamma_space_rendering: native_options.gamma_space_rendering,
+                damage_tracker: egui_glow::DamageTracker::new(),
+                idle,
+                frame_pacer: super::frame_pacer::FramePacer::new(
+                    native_options.max_fps,
+                    native_options.animation_fps,
+                ),
+                #[cfg(target_os = "linux")]
+                frame_latency: Default::default(),
+            }
+        }
+
+        /// Matches the latest presented frame to when it started to get the frame latency.
+        #[cfg(target_os = "linux")]
+        fn frame_swapped(&mut self, frame_start: Duration) {
+            use glutin::platform::unix::WindowedContextExtUnix as _;
+
+            let timestamp = self.gl_window.presentation_timestamp();
+            if let Some(latency) = self.frame_latency.frame_swapped(frame_start, timestamp) {
+                self.integration.frame.info.frame_latency = Some(latency.as_secs_f32());
             }
         }
+
+        /// Recreates the surface and the textures on it, or the whole painter if the context
+        /// was lost. Returns `false` while the display is still unavailable.
+        fn resume_gl(&mut self) -> bool {
+            #[cfg(target_os = "linux")]
+            {
+                use glutin::platform::unix::WindowedContextExtUnix as _;
+
+                match self.gl_window.resume() {
+                    // Only the surface was gone, GL objects survive that
+                    Ok(false) => {}
+                    Ok(true) => {
+                        // Nothing of the old painter survived with its context
+                        let painter = create_painter(&self.gl, self.gamma_space_rendering);
+                        let lost = std::mem::replace(&mut self.painter, painter);
+                        self.painter.take_over_lost(lost);
+                        self.frame_latency.context_recreated();
+                        // It shares textures with the lost context
+                        if self.integration.frame.texture_uploader.take().is_some() {
+                            tracing::warn!("The GL context was lost, so was the texture uploader");
+                        }
+                    }
+                    Err(glutin::ContextError::ContextLost) => return false,
+                    Err(err) => panic!("failed to recreate the OpenGL surface: {}", err),
+                }
+            }
+            self.damage_tracker.invalidate();
+            self.needs_resume = false;
+            true
+        }
     }
 
     impl WinitApp for GlowWinitApp {
@@ -315,11 +495,16 @@ mod glow_integration {
             self.gl_window.window()
         }
 
+        fn is_blanked(&self) -> bool {
+            self.idle.is_blanked()
+        }
+
         fn save_and_destroy(&mut self) {
             self.integration
-                .save(&mut *self.app, self.gl_window.window());
+                .save_and_close_storage(&mut *self.app, self.gl_window.window());
             self.app.on_exit(Some(&self.gl));
             self.painter.destroy();
+            self.idle.restore(self.gl_window.window());
         }
 
         fn paint(&mut self) -> EventResult {
@@ -327,23 +512,38 @@ mod glow_integration {
             puffin::GlobalProfiler::lock().new_frame();
             crate::profile_scope!("frame");
 
+            let idle_deadline =
+                self.idle
+                    .update(self.gl_window.window(), self.app.as_mut(), Instant::now());
+            if self.idle.is_blanked() || self.suspended || (self.needs_resume && !self.resume_gl())
+            {
+                return EventResult::Wait;
+            }
+            if let Some(not_before) = self.frame_pacer.hold_back(Instant::now()) {
+                return EventResult::RepaintAt(not_before);
+            }
+
+            let cpu_start = Instant::now();
+            let frame_time = self.frame_pacer.frame_started(cpu_start);
+            self.integration.frame.info.frame_time = frame_time.map(|time| time.as_secs_f32());
+            self.integration.frame.info.frames_skipped = self.frame_pacer.frames_skipped();
+
+            #[cfg(target_os = "linux")]
+            let frame_start = glutin::platform::unix::monotonic_now();
+
             let Self {
                 gl_window,
-                gl,
                 app,
                 integration,
                 painter,
+                damage_tracker,
+                needs_resume,
                 ..
             } = self;
             let window = gl_window.window();
 
             let screen_size_in_pixels: [u32; 2] = window.inner_size().into();
-
-            egui_glow::painter::clear(
-                gl,
-                screen_size_in_pixels,
-                app.clear_color(&integration.egui_ctx.style().visuals),
-            );
+            let clear_color = app.clear_color(&integration.egui_ctx.style().visuals);
 
             let egui::FullOutput {
                 platform_output,
@@ -354,27 +554,148 @@ mod glow_integration {
 
             integration.handle_platform_output(window, platform_output);
 
+            let tessellate_start = std::time::Instant::now();
             let clipped_primitives = {
                 crate::profile_scope!("tessellate");
                 integration.egui_ctx.tessellate(shapes)
             };
+            let tessellate_time = tessellate_start.elapsed();
 
-            painter.paint_and_update_textures(
-                screen_size_in_pixels,
-                integration.egui_ctx.pixels_per_point(),
-                &clipped_primitives,
-                &textures_delta,
-            );
+            let upload_start = std::time::Instant::now();
+            for texture_id in painter.apply_uploads() {
+                damage_tracker.texture_changed(texture_id);
+            }
+            for (id, image_delta) in &textures_delta.set {
+                painter.set_texture(*id, image_delta);
+            }
+            let upload_time = upload_start.elapsed();
 
-            integration.post_rendering(app.as_mut(), window);
+            let screenshot_requested = std::mem::take(&mut integration.frame.screenshot_requested);
+            if screenshot_requested {
+                // The back buffer may hold an older frame where nothing changed
+                damage_tracker.invalidate();
+            }
 
-            {
-                crate::profile_scope!("swap_buffers");
-                gl_window.swap_buffers().unwrap();
+            let pixels_per_point = integration.egui_ctx.pixels_per_point();
+            let damage = if painter.supports_partial_redraw() {
+                damage_tracker.update(
+                    screen_size_in_pixels,
+                    pixels_per_point,
+                    &clipped_primitives,
+                    &textures_delta,
+                    gl_window.buffer_age(),
+                )
+            } else {
+                damage_tracker.invalidate();
+                Some(egui::Rect::from_min_size(
+                    egui::Pos2::ZERO,
+                    egui::vec2(
+                        screen_size_in_pixels[0] as f32,
+                        screen_size_in_pixels[1] as f32,
+                    ),
+                ))
+            };
+
+            let profiler_hud = integration.frame.profiler_hud;
+            painter.set_gpu_timing(profiler_hud);
+
+            let mut paint_time = std::time::Duration::ZERO;
+            if let Some(damage) = damage {
+                let paint_start = std::time::Instant::now();
+                painter.clear_damage(screen_size_in_pixels, clear_color, damage);
+                painter.paint_primitives_in(
+                    screen_size_in_pixels,
+                    pixels_per_point,
+                    &clipped_primitives,
+                    damage,
+                );
+                paint_time = paint_start.elapsed();
+
+                if screenshot_requested {
+                    let screen_rect = egui::Rect::from_min_size(
+                        egui::Pos2::ZERO,
+                        egui::vec2(
+                            screen_size_in_pixels[0] as f32,
+                            screen_size_in_pixels[1] as f32,
+                        ),
+                    );
+                    integration.frame.screenshot = Some(painter.read_screen_rgba(screen_rect));
+                    // Deliver it on the next update
+                    integration.egui_ctx.request_repaint();
+                }
+            }
+
+            for &id in &textures_delta.free {
+                painter.free_texture(id);
             }
 
+            if profiler_hud {
+                integration
+                    .profiler_hud
+                    .record(super::profiler_hud::FrameTimes {
+                        update: integration.app_update_time,
+                        tessellate: tessellate_time,
+                        upload: upload_time,
+                        paint: paint_time,
+                        gpu: painter.gpu_time(),
+                    });
+            }
+
+            integration
+                .egui_ctx
+                .tex_manager()
+                .write()
+                .set_gpu_usage(Some(painter.texture_budget().usage()));
+
+            integration.post_rendering(app.as_mut(), window);
+
+            integration.frame.info.frame_cpu_time = Some(cpu_start.elapsed().as_secs_f32());
+
+            // The frame didn't reach the screen and has to be drawn again
+            let mut retry = false;
+            let swapped = match damage {
+                Some(damage) => {
+                    crate::profile_scope!("swap_buffers");
+                    let result = if gl_window.swap_buffers_with_damage_supported() {
+                        let [x, y, width, height] =
+                            painter.damage_in_framebuffer(screen_size_in_pixels, damage);
+                        gl_window.swap_buffers_with_damage(&[glutin::Rect {
+                            x: x as u32,
+                            y: y as u32,
+                            width: width as u32,
+                            height: height as u32,
+                        }])
+                    } else {
+                        gl_window.swap_buffers()
+                    };
+                    match result {
+                        Ok(()) => true,
+                        Err(glutin::ContextError::ContextLost) => {
+                            tracing::warn!("OpenGL surface lost, recreating it on the next frame");
+                            *needs_resume = true;
+                            false
+                        }
+                        // kms: the previous page flip hasn't landed yet, e.g. right after a VT
+                        // switch
+                        Err(glutin::ContextError::IoError(err))
+                            if err.kind() == std::io::ErrorKind::WouldBlock =>
+                        {
+                            tracing::debug!("Frame not presented, drawing it again: {}", err);
+                            damage_tracker.invalidate();
+                            retry = true;
+                            false
+                        }
+                        Err(err) => panic!("swap_buffers failed: {}", err),
+                    }
+                }
+                None => false,
+            };
+
             let control_flow = if integration.should_close() {
                 EventResult::Exit
+            } else if *needs_resume || retry {
+                // If the display isn't back yet, winit sends a redraw once it is
+                EventResult::RepaintAsap
             } else if repaint_after.is_zero() {
                 EventResult::RepaintAsap
             } else if let Some(repaint_after_instant) =
@@ -389,9 +710,24 @@ mod glow_integration {
             } else {
                 EventResult::Wait
             };
+            // Wake up to dim or blank the display
+            let control_flow = match (control_flow, idle_deadline) {
+                (EventResult::Wait, Some(deadline)) => EventResult::RepaintAt(deadline),
+                (EventResult::RepaintAt(repaint_time), Some(deadline)) => {
+                    EventResult::RepaintAt(repaint_time.min(deadline))
+                }
+                (control_flow, _) => control_flow,
+            };
 
             integration.maybe_autosave(app.as_mut(), window);
 
+            #[cfg(target_os = "linux")]
+            if swapped {
+                self.frame_swapped(frame_start);
+            }
+            #[cfg(not(target_os = "linux"))]
+            let _ = swapped;
+
             if !self.is_focused {
                 // On Mac, a minimized Window uses up all CPU: https://github.com/emilk/egui/issues/325
                 // We can't know if we are minimized: https://github.com/rust-windowing/winit/issues/208
@@ -407,6 +743,25 @@ mod glow_integration {
 
         fn on_event(&mut self, event: winit::event::Event<'_, RequestRepaintEvent>) -> EventResult {
             match event {
+                #[cfg(target_os = "linux")]
+                winit::event::Event::Suspended => {
+                    use glutin::platform::unix::WindowedContextExtUnix as _;
+
+                    self.gl_window.suspend();
+                    self.suspended = true;
+                    self.needs_resume = true;
+                    EventResult::Wait
+                }
+                #[cfg(target_os = "linux")]
+                winit::event::Event::Resumed => {
+                    self.suspended = false;
+                    if self.needs_resume {
+                        EventResult::RepaintAsap
+                    } else {
+                        EventResult::Wait
+                    }
+                }
+
                 winit::event::Event::WindowEvent { event, .. } => {
                     match &event {
                         winit::event::WindowEvent::Focused(new_focused) => {
@@ -433,6 +788,18 @@ mod glow_integration {
                         _ => {}
                     }
 
+                    if !self.idle.on_event(
+                        self.gl_window.window(),
+                        self.app.as_mut(),
+                        &event,
+                        Instant::now(),
+                    ) {
+                        return EventResult::RepaintAsap;
+                    }
+                    if super::idle::is_input(&event) {
+                        self.frame_pacer.on_input();
+                    }
+
                     self.integration.on_event(self.app.as_mut(), &event);
 
                     if self.integration.should_close() {
@@ -447,19 +814,26 @@ mod glow_integration {
         }
     }
 
+    impl Drop for GlowWinitApp {
+        fn drop(&mut self) {
+            // Don't leave the display dark if we exit without `save_and_destroy`, e.g. on a panic
+            self.idle.restore(self.gl_window.window());
+        }
+    }
+
     pub fn run_glow(
         app_name: &str,
         native_options: &epi::NativeOptions,
         app_creator: epi::AppCreator,
     ) {
         if native_options.run_and_return {
-            with_event_loop(|event_loop| {
+            with_event_loop(native_options, |event_loop| {
                 let glow_eframe =
                     GlowWinitApp::new(event_loop, app_name, native_options, app_creator);
                 run_and_return(event_loop, glow_eframe);
             });
         } else {
-            let event_loop = winit::event_loop::EventLoopBuilder::with_user_event().build();
+            let event_loop = create_event_loop(native_options);
             let glow_eframe = GlowWinitApp::new(&event_loop, app_name, native_options, app_creator);
             run_and_exit(event_loop, glow_eframe);
         }
@@ -490,7 +864,7 @@ mod wgpu_integration {
             native_options: &epi::NativeOptions,
             app_creator: epi::AppCreator,
         ) -> Self {
-            let storage = epi_integration::create_storage(app_name);
+            let storage = epi_integration::create_storage(app_name, &native_options.persistence);
             let window_settings = epi_integration::load_window_settings(storage.as_deref());
 
             let window = epi_integration::window_builder(native_options, &window_settings)
@@ -525,6 +899,7 @@ mod wgpu_integration {
                 painter.max_texture_side().unwrap_or(2048),
                 &window,
                 system_theme,
+                &native_options.embedded,
                 storage,
                 #[cfg(feature = "glow")]
                 None,
@@ -576,8 +951,13 @@ mod wgpu_integration {
             &self.window
         }
 
+        fn is_blanked(&self) -> bool {
+            false
+        }
+
         fn save_and_destroy(&mut self) {
-            self.integration.save(&mut *self.app, &self.window);
+            self.integration
+                .save_and_close_storage(&mut *self.app, &self.window);
 
             #[cfg(feature = "glow")]
             self.app.on_exit(None);
@@ -716,13 +1096,13 @@ mod wgpu_integration {
         app_creator: epi::AppCreator,
     ) {
         if native_options.run_and_return {
-            with_event_loop(|event_loop| {
+            with_event_loop(native_options, |event_loop| {
                 let wgpu_eframe =
                     WgpuWinitApp::new(event_loop, app_name, native_options, app_creator);
                 run_and_return(event_loop, wgpu_eframe);
             });
         } else {
-            let event_loop = winit::event_loop::EventLoopBuilder::with_user_event().build();
+            let event_loop = create_event_loop(native_options);
             let wgpu_eframe = WgpuWinitApp::new(&event_loop, app_name, native_options, app_creator);
             run_and_exit(event_loop, wgpu_eframe);
         }
@@ -0,0 +1,49 @@
+#![allow(unsafe_code)]
+
+/// A GL context sharing textures with the one egui is painted with, for uploading large images
+/// on another thread instead of stalling painting. Request it with
+/// [`crate::NativeOptions::texture_uploader`] and take it with
+/// [`crate::Frame::take_texture_uploader`].
+///
+/// Move it to a worker thread and upload in [`Self::run`]:
+/// ```no_run
+/// # fn spawn(uploader: eframe::TextureUploader, decoded: std::sync::mpsc::Receiver<Vec<u8>>) {
+/// std::thread::spawn(move || {
+///     uploader.run(|gl, queue| {
+///         use eframe::glow::HasContext as _;
+///         for pixels in decoded {
+///             let texture = unsafe { gl.create_texture().unwrap() };
+///             // Bind `texture`, set its filters and upload `pixels` with `gl.tex_image_2d`
+///             let texture_id = queue.register(gl, texture);
+///             // Send `texture_id` to the app, it can be painted from the next frame on
+///         }
+///     })
+/// });
+/// # }
+/// ```
+pub struct TextureUploader {
+    context: glutin::Context<glutin::NotCurrent>,
+    queue: egui_glow::UploadQueue,
+}
+
+impl TextureUploader {
+    pub(crate) fn new(
+        context: glutin::Context<glutin::NotCurrent>,
+        queue: egui_glow::UploadQueue,
+    ) -> Self {
+        Self { context, queue }
+    }
+
+    /// Makes the context current on the calling thread and runs `f` with it. Hand the uploaded
+    /// textures to the painter through the [`egui_glow::UploadQueue`], which fences them. The
+    /// context is destroyed once `f` returns, the textures live on in the painter's context.
+    pub fn run<R>(
+        self,
+        f: impl FnOnce(&glow::Context, &egui_glow::UploadQueue) -> R,
+    ) -> Result<R, String> {
+        let context = unsafe { self.context.make_current() }
+            .map_err(|(_, err)| format!("Failed to make the texture upload context current: {}", err))?;
+        let gl = unsafe { glow::Context::from_loader_function(|s| context.get_proc_address(s)) };
+        Ok(f(&gl, &self.queue))
+    }
+}
@@ -203,6 +203,10 @@ impl AppRunner {
             system_theme,
             cpu_usage: None,
             native_pixels_per_point: Some(native_pixels_per_point()),
+            frame_latency: None,
+            frame_time: None,
+            frames_skipped: 0,
+            frame_cpu_time: None,
         };
         let storage = LocalStorage::default();
 
@@ -898,6 +898,11 @@ impl Context {
         clipped_primitives
     }
 
+    /// What the last [`Self::tessellate`] painted: shapes, vertices, triangles, callbacks.
+    pub fn paint_stats(&self) -> epaint::stats::PaintStats {
+        self.read().paint_stats
+    }
+
     // ---------------------------------------------------------------------
 
     /// How much space is used by panels and windows.
@@ -1200,6 +1205,24 @@ impl Context {
             textures.len(),
             bytes as f64 * 1e-6
         ));
+        if let Some(usage) = tex_mngr.gpu_usage() {
+            let budget = usage.budget.map_or_else(String::new, |budget| {
+                format!(" of {:.1} MB", budget as f64 * 1e-6)
+            });
+            ui.label(format!(
+                "GPU: {:.1} MB{} ({:.1} MB evictable), {} texture(s) evicted",
+                usage.bytes_used as f64 * 1e-6,
+                budget,
+                usage.bytes_evictable as f64 * 1e-6,
+                usage.num_evicted,
+            ));
+            if usage.bytes_copied > 0 {
+                ui.label(format!(
+                    "CPU copies: {:.1} MB",
+                    usage.bytes_copied as f64 * 1e-6
+                ));
+            }
+        }
         let max_preview_size = vec2(48.0, 32.0);
 
         ui.group(|ui| {
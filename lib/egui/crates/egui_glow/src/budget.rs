@@ -0,0 +1,194 @@
+//! Accounting for the GPU memory of the [`Painter`](crate::Painter)'s textures, and evicting
+//! textures to stay within a budget.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use egui::epaint::textures::TextureMemoryUsage;
+use egui::TextureId;
+
+type OnEvict = Box<dyn FnOnce(TextureId) + Send>;
+
+#[derive(Default)]
+struct Entry {
+    /// 0 until the painter has the texture, or for user textures of unknown size.
+    bytes: usize,
+    /// The CPU copy the painter keeps of the texture, see
+    /// [`Painter::set_keep_texture_copies`](crate::Painter::set_keep_texture_copies).
+    copy_bytes: usize,
+    /// The frame the texture was last painted in.
+    last_used: u64,
+    /// `Some` if the texture may be evicted.
+    on_evict: Option<OnEvict>,
+}
+
+#[derive(Default)]
+struct State {
+    budget: Option<usize>,
+    textures: HashMap<TextureId, Entry>,
+    frame: u64,
+    num_evicted: usize,
+}
+
+/// How much GPU memory the textures of a [`Painter`](crate::Painter) take, and the most they
+/// may take. Get one from [`Painter::texture_budget`](crate::Painter::texture_budget), cloning
+/// it is cheap.
+///
+/// Textures egui manages take `width * height * 4` bytes. User textures registered with
+/// [`Painter::register_native_texture`](crate::Painter::register_native_texture) or through the
+/// [`crate::UploadQueue`] only count once their size is reported with [`Self::set_bytes`].
+///
+/// CPU copies kept with [`Painter::set_keep_texture_copies`](crate::Painter::set_keep_texture_copies)
+/// are reported in [`TextureMemoryUsage::bytes_copied`], they don't count towards the budget but
+/// go with their texture when it is evicted.
+///
+/// Once the textures take more than the budget, the painter deletes textures marked with
+/// [`Self::set_evictable`] after painting, the least recently painted first. Textures painted
+/// in the frame just painted are kept, so the budget is exceeded rather than the screen broken.
+#[derive(Clone, Default)]
+pub struct TextureBudget {
+    state: Arc<Mutex<State>>,
+}
+
+impl TextureBudget {
+    /// Keep the textures within `budget` bytes, or don't limit them with `None` (the default).
+    pub fn set_budget(&self, budget: Option<usize>) {
+        self.state.lock().unwrap().budget = budget;
+    }
+
+    pub fn budget(&self) -> Option<usize> {
+        self.state.lock().unwrap().budget
+    }
+
+    /// Allow deleting the texture of `texture_id` when over budget, e.g. for album art that can
+    /// be loaded again. `on_evict` is called on the painting thread once it is deleted: drop
+    /// whatever holds `texture_id` (e.g. the [`egui::TextureHandle`]) and load it again when it
+    /// is needed. Until then painting it shows nothing.
+    ///
+    /// Only mark textures the app uploads again by itself, from a [`egui::TextureHandle`] it
+    /// can recreate. An `egui_extras::RetainedImage` drops its pixels once uploaded and never
+    /// uploads them again, so it would stay blank: drop the whole `RetainedImage` in `on_evict`
+    /// and load a new one instead.
+    pub fn set_evictable(
+        &self,
+        texture_id: TextureId,
+        on_evict: impl FnOnce(TextureId) + Send + 'static,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let frame = state.frame;
+        let entry = state.textures.entry(texture_id).or_insert_with(|| Entry {
+            // Not painted yet, but just loaded
+            last_used: frame,
+            ..Default::default()
+        });
+        entry.on_evict = Some(Box::new(on_evict));
+    }
+
+    /// Keep the texture of `texture_id` whatever the budget, which is the default.
+    pub fn set_unevictable(&self, texture_id: TextureId) {
+        if let Some(entry) = self.state.lock().unwrap().textures.get_mut(&texture_id) {
+            entry.on_evict = None;
+        }
+    }
+
+    /// Report the size of a user texture, e.g. one uploaded through the [`crate::UploadQueue`].
+    pub fn set_bytes(&self, texture_id: TextureId, bytes: usize) {
+        self.state
+            .lock()
+            .unwrap()
+            .textures
+            .entry(texture_id)
+            .or_default()
+            .bytes = bytes;
+    }
+
+    /// The bytes the texture of `texture_id` takes, if known.
+    pub fn bytes(&self, texture_id: TextureId) -> Option<usize> {
+        let state = self.state.lock().unwrap();
+        state
+            .textures
+            .get(&texture_id)
+            .map(|entry| entry.bytes)
+            .filter(|&bytes| bytes > 0)
+    }
+
+    pub fn usage(&self) -> TextureMemoryUsage {
+        let state = self.state.lock().unwrap();
+        TextureMemoryUsage {
+            bytes_used: state.textures.values().map(|entry| entry.bytes).sum(),
+            budget: state.budget,
+            bytes_evictable: state
+                .textures
+                .values()
+                .filter(|entry| entry.on_evict.is_some())
+                .map(|entry| entry.bytes)
+                .sum(),
+            num_evicted: state.num_evicted,
+            bytes_copied: state.textures.values().map(|entry| entry.copy_bytes).sum(),
+        }
+    }
+
+    /// The painter keeps a copy of `bytes` of the texture of `texture_id`, 0 once it dropped it.
+    pub(crate) fn set_copy_bytes(&self, texture_id: TextureId, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        if bytes > 0 {
+            state.textures.entry(texture_id).or_default().copy_bytes = bytes;
+        } else if let Some(entry) = state.textures.get_mut(&texture_id) {
+            entry.copy_bytes = 0;
+        }
+    }
+
+    pub(crate) fn freed(&self, texture_id: TextureId) {
+        self.state.lock().unwrap().textures.remove(&texture_id);
+    }
+
+    /// Starts a new frame, in which the textures of `painted` are painted.
+    pub(crate) fn painting(&self, painted: impl Iterator<Item = TextureId>) {
+        let mut state = self.state.lock().unwrap();
+        state.frame += 1;
+        let frame = state.frame;
+        for texture_id in painted {
+            state.textures.entry(texture_id).or_default().last_used = frame;
+        }
+    }
+
+    /// Picks the textures to delete to get within the budget and forgets them. Call their
+    /// callback once they are deleted.
+    pub(crate) fn evict(&self) -> Vec<(TextureId, OnEvict)> {
+        let mut state = self.state.lock().unwrap();
+        let budget = match state.budget {
+            Some(budget) => budget,
+            None => return vec![],
+        };
+        let mut bytes_used: usize = state.textures.values().map(|entry| entry.bytes).sum();
+        if bytes_used <= budget {
+            return vec![];
+        }
+
+        let frame = state.frame;
+        let mut candidates: Vec<(u64, TextureId)> = state
+            .textures
+            .iter()
+            .filter(|(_, entry)| {
+                entry.on_evict.is_some() && entry.bytes > 0 && entry.last_used < frame
+            })
+            .map(|(&texture_id, entry)| (entry.last_used, texture_id))
+            .collect();
+        candidates.sort_unstable_by_key(|&(last_used, _)| last_used);
+
+        let mut evicted = vec![];
+        for (_, texture_id) in candidates {
+            if bytes_used <= budget {
+                break;
+            }
+            if let Some(entry) = state.textures.remove(&texture_id) {
+                bytes_used -= entry.bytes;
+                if let Some(on_evict) = entry.on_evict {
+                    evicted.push((texture_id, on_evict));
+                }
+            }
+        }
+        state.num_evicted += evicted.len();
+        evicted
+    }
+}
@@ -0,0 +1,132 @@
+#![allow(unsafe_code)]
+use crate::check_for_gl_error;
+use glow::HasContext as _;
+
+/// A framebuffer that [`egui::PaintCallback`]s render into when the output is rotated, so that
+/// they can keep painting upright. The painter then draws its texture rotated onto the screen,
+/// clipped like any mesh.
+pub(crate) struct CallbackTarget {
+    gl: std::sync::Arc<glow::Context>,
+    fbo: glow::Framebuffer,
+    color_texture: glow::Texture,
+    depth_renderbuffer: glow::Renderbuffer,
+    /// The formats egui's own textures are in, so that the painter samples it the same way.
+    internal_format: u32,
+    format: u32,
+    texture_size: (i32, i32),
+}
+
+impl CallbackTarget {
+    pub(crate) unsafe fn new(
+        gl: std::sync::Arc<glow::Context>,
+        internal_format: u32,
+        format: u32,
+    ) -> Result<Self, String> {
+        let fbo = gl.create_framebuffer()?;
+
+        let color_texture = gl.create_texture()?;
+        gl.bind_texture(glow::TEXTURE_2D, Some(color_texture));
+        for (parameter, value) in [
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            // Drawn back pixel for pixel
+            (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+            (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+        ] {
+            gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+        }
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        // Callbacks drawing 3D want one, like they get from `PostProcess`
+        let depth_renderbuffer = gl.create_renderbuffer()?;
+
+        Ok(Self {
+            gl,
+            fbo,
+            color_texture,
+            depth_renderbuffer,
+            internal_format,
+            format,
+            texture_size: (0, 0),
+        })
+    }
+
+    pub(crate) fn fbo(&self) -> glow::Framebuffer {
+        self.fbo
+    }
+
+    pub(crate) fn texture(&self) -> glow::Texture {
+        self.color_texture
+    }
+
+    /// Binds the framebuffer, at least `width` x `height` large, and clears its bottom left
+    /// `width` x `height` to transparent, which is where the callback paints.
+    pub(crate) unsafe fn begin(&mut self, width: i32, height: i32) {
+        let gl = &self.gl;
+        if width > self.texture_size.0 || height > self.texture_size.1 {
+            // Grow only, callbacks often change size a little every frame while animating
+            let (width, height) = (
+                width.max(self.texture_size.0),
+                height.max(self.texture_size.1),
+            );
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.color_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                self.internal_format as i32,
+                width,
+                height,
+                0,
+                self.format,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(self.depth_renderbuffer));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH_COMPONENT16, width, height);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            self.texture_size = (width, height);
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(self.color_texture),
+            0,
+        );
+        gl.framebuffer_renderbuffer(
+            glow::FRAMEBUFFER,
+            glow::DEPTH_ATTACHMENT,
+            glow::RENDERBUFFER,
+            Some(self.depth_renderbuffer),
+        );
+
+        gl.viewport(0, 0, width, height);
+        gl.enable(glow::SCISSOR_TEST);
+        gl.scissor(0, 0, width, height);
+        gl.clear_color(0.0, 0.0, 0.0, 0.0);
+        gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+        gl.disable(glow::SCISSOR_TEST);
+
+        check_for_gl_error!(&self.gl, "CallbackTarget::begin");
+    }
+
+    /// Where in the texture the last `begin(width, height)` painted, as `[u_max, v_max]`.
+    pub(crate) fn uv_max(&self, width: i32, height: i32) -> [f32; 2] {
+        [
+            width as f32 / self.texture_size.0 as f32,
+            height as f32 / self.texture_size.1 as f32,
+        ]
+    }
+
+    pub(crate) unsafe fn destroy(&self) {
+        self.gl.delete_framebuffer(self.fbo);
+        self.gl.delete_texture(self.color_texture);
+        self.gl.delete_renderbuffer(self.depth_renderbuffer);
+    }
+}
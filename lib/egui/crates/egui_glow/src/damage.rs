@@ -0,0 +1,265 @@
+//! Finding out which parts of the screen changed between frames, so that only they are repainted.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use egui::emath::Rect;
+use egui::epaint::Primitive;
+use egui::{pos2, ClippedPrimitive, TextureId, TexturesDelta};
+
+/// How many frames of damage to remember, back buffers older than this are repainted in full.
+const MAX_BUFFER_AGE: usize = 4;
+
+/// Tracks which screen regions change between frames by comparing the clipped primitives.
+///
+/// Primitives are compared by content and position only, so two overlapping primitives that swap
+/// places in the paint order are not noticed.
+#[derive(Default)]
+pub struct DamageTracker {
+    /// The screen size in pixels and pixels per point of the last frame
+    screen: Option<([u32; 2], f32)>,
+
+    /// Hashes of the primitives painted last frame and the pixels they cover
+    previous: Vec<(u64, Rect)>,
+
+    /// The damage of the most recent frames in pixels, newest first
+    history: VecDeque<Rect>,
+
+    /// Textures replaced behind egui's back since the last frame, see [`Self::texture_changed`]
+    changed_textures: HashSet<TextureId>,
+}
+
+impl DamageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget everything, so that the next frame is repainted in full. Call this whenever the
+    /// back buffers may have lost their contents, e.g. after recreating the surface.
+    pub fn invalidate(&mut self) {
+        self.screen = None;
+        self.previous.clear();
+        self.history.clear();
+        self.changed_textures.clear();
+    }
+
+    /// Repaint everything painted with `texture_id` next frame, for textures that change outside
+    /// of the [`TexturesDelta`], e.g. through [`crate::UploadQueue`].
+    pub fn texture_changed(&mut self, texture_id: TextureId) {
+        self.changed_textures.insert(texture_id);
+    }
+
+    /// Returns the region in pixels that has to be repainted into a back buffer that was last
+    /// drawn to `buffer_age` frames ago, as reported by `EGL_EXT_buffer_age`. An age of `0`
+    /// means the contents are unknown and gets the whole screen.
+    ///
+    /// Returns `None` if nothing changed, the frame doesn't have to be painted at all then.
+    pub fn update(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[ClippedPrimitive],
+        textures_delta: &TexturesDelta,
+        buffer_age: u32,
+    ) -> Option<Rect> {
+        crate::profile_function!();
+
+        let screen_rect = Rect::from_min_max(
+            pos2(0.0, 0.0),
+            pos2(screen_size_px[0] as f32, screen_size_px[1] as f32),
+        );
+
+        // Setting a texture may change everything painted with it, even a partial update can
+        // overwrite texels that are already in use
+        let mut replaced: HashSet<TextureId> =
+            textures_delta.set.iter().map(|(id, _)| *id).collect();
+        replaced.extend(self.changed_textures.drain());
+
+        let mut damage = Rect::NOTHING;
+        let mut current = Vec::with_capacity(clipped_primitives.len());
+        for ClippedPrimitive {
+            clip_rect,
+            primitive,
+        } in clipped_primitives
+        {
+            let (hash, bounds, always_damaged) = match primitive {
+                Primitive::Mesh(mesh) => {
+                    let mut hasher = DefaultHasher::new();
+                    mesh.texture_id.hash(&mut hasher);
+                    hasher.write(bytemuck::cast_slice(&mesh.vertices));
+                    hasher.write(bytemuck::cast_slice(&mesh.indices));
+                    (
+                        hasher.finish(),
+                        mesh.calc_bounds(),
+                        replaced.contains(&mesh.texture_id),
+                    )
+                }
+                // We can't know what a callback paints
+                Primitive::Callback(callback) => (0, callback.rect, true),
+            };
+
+            let rect = to_pixels(bounds.intersect(*clip_rect), pixels_per_point, screen_rect);
+            if !rect.is_positive() {
+                continue;
+            }
+            if always_damaged {
+                damage = damage.union(rect);
+            }
+
+            let mut hasher = DefaultHasher::new();
+            hash.hash(&mut hasher);
+            hash_rect(rect, &mut hasher);
+            current.push((hasher.finish(), rect));
+        }
+
+        if self.screen != Some((screen_size_px, pixels_per_point)) {
+            self.screen = Some((screen_size_px, pixels_per_point));
+            self.history.clear();
+            damage = screen_rect;
+        } else {
+            // Whatever appeared or disappeared since the last frame
+            let mut unmatched: HashMap<u64, usize> = HashMap::new();
+            for (hash, _) in &self.previous {
+                *unmatched.entry(*hash).or_default() += 1;
+            }
+            for (hash, rect) in &current {
+                match unmatched.get_mut(hash) {
+                    Some(count) if *count > 0 => *count -= 1,
+                    _ => damage = damage.union(*rect),
+                }
+            }
+            for (hash, rect) in &self.previous {
+                if let Some(count) = unmatched.get_mut(hash) {
+                    if *count > 0 {
+                        *count -= 1;
+                        damage = damage.union(*rect);
+                    }
+                }
+            }
+        }
+        self.previous = current;
+
+        let buffer_age = buffer_age as usize;
+        let region = if buffer_age == 0 || buffer_age > self.history.len() + 1 {
+            screen_rect
+        } else {
+            // The back buffer also misses the damage of the frames since it was last drawn to
+            self.history
+                .iter()
+                .take(buffer_age - 1)
+                .fold(damage, |region, damage| region.union(*damage))
+        };
+        // A frame that isn't painted isn't swapped either, so it doesn't age the buffers
+        if !region.is_positive() {
+            return None;
+        }
+
+        self.history.push_front(damage);
+        self.history.truncate(MAX_BUFFER_AGE);
+        Some(region.intersect(screen_rect))
+    }
+}
+
+/// Rounds outwards so that anything touching a pixel repaints it.
+fn to_pixels(rect: Rect, pixels_per_point: f32, screen_rect: Rect) -> Rect {
+    Rect::from_min_max(
+        pos2(
+            (rect.min.x * pixels_per_point).floor(),
+            (rect.min.y * pixels_per_point).floor(),
+        ),
+        pos2(
+            (rect.max.x * pixels_per_point).ceil(),
+            (rect.max.y * pixels_per_point).ceil(),
+        ),
+    )
+    .intersect(screen_rect)
+}
+
+fn hash_rect(rect: Rect, hasher: &mut impl Hasher) {
+    for value in [rect.min.x, rect.min.y, rect.max.x, rect.max.y] {
+        hasher.write_u32(value.to_bits());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::DamageTracker;
+    use egui::epaint::{ImageDelta, Mesh, Primitive};
+    use egui::{
+        pos2, vec2, ClippedPrimitive, Color32, ColorImage, Rect, TextureFilter, TextureId,
+        TexturesDelta,
+    };
+
+    const SCREEN: [u32; 2] = [100, 100];
+
+    fn rect_at(x: f32, y: f32) -> Rect {
+        Rect::from_min_size(pos2(x, y), vec2(10.0, 10.0))
+    }
+
+    fn textured(texture_id: TextureId, rect: Rect) -> ClippedPrimitive {
+        let mut mesh = Mesh::with_texture(texture_id);
+        mesh.add_rect_with_uv(
+            rect,
+            Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+            Color32::WHITE,
+        );
+        ClippedPrimitive {
+            clip_rect: Rect::EVERYTHING,
+            primitive: Primitive::Mesh(mesh),
+        }
+    }
+
+    fn update(
+        tracker: &mut DamageTracker,
+        primitives: &[ClippedPrimitive],
+        textures_delta: &TexturesDelta,
+    ) -> Option<Rect> {
+        tracker.update(SCREEN, 1.0, primitives, textures_delta, 1)
+    }
+
+    #[test]
+    fn unchanged_frame_is_not_damaged() {
+        let mut tracker = DamageTracker::new();
+        let primitives = [textured(TextureId::default(), rect_at(10.0, 10.0))];
+        let delta = TexturesDelta::default();
+
+        let screen = Rect::from_min_max(pos2(0.0, 0.0), pos2(100.0, 100.0));
+        assert_eq!(update(&mut tracker, &primitives, &delta), Some(screen));
+        assert_eq!(update(&mut tracker, &primitives, &delta), None);
+    }
+
+    #[test]
+    fn moved_mesh_damages_old_and_new_bounds() {
+        let mut tracker = DamageTracker::new();
+        let delta = TexturesDelta::default();
+        let (old, new) = (rect_at(10.0, 10.0), rect_at(50.0, 60.0));
+
+        update(&mut tracker, &[textured(TextureId::default(), old)], &delta);
+        let damage = update(&mut tracker, &[textured(TextureId::default(), new)], &delta);
+        assert_eq!(damage, Some(old.union(new)));
+    }
+
+    #[test]
+    fn partial_texture_update_damages_its_meshes() {
+        let mut tracker = DamageTracker::new();
+        let texture_id = TextureId::User(1);
+        let rect = rect_at(20.0, 30.0);
+        let primitives = [
+            textured(texture_id, rect),
+            textured(TextureId::default(), rect_at(70.0, 70.0)),
+        ];
+
+        update(&mut tracker, &primitives, &TexturesDelta::default());
+        let mut delta = TexturesDelta::default();
+        delta.set.push((
+            texture_id,
+            ImageDelta::partial(
+                [0, 0],
+                ColorImage::new([1, 1], Color32::RED),
+                TextureFilter::Linear,
+            ),
+        ));
+        assert_eq!(update(&mut tracker, &primitives, &delta), Some(rect));
+    }
+}
@@ -0,0 +1,113 @@
+#![allow(unsafe_code)]
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use glow::HasContext as _;
+
+/// `GL_GPU_DISJOINT_EXT`, set when the timer was interrupted (e.g. by a clock change).
+const GPU_DISJOINT_EXT: u32 = 0x8FBB;
+
+/// Results come back a few frames late, so that many queries are in flight.
+const NUM_QUERIES: usize = 4;
+
+/// Measures how long the GPU takes to paint a frame with `GL_EXT_disjoint_timer_query` (GLES)
+/// or `GL_ARB_timer_query` (GL), without waiting for the GPU.
+pub(crate) struct GpuTimer {
+    gl: std::sync::Arc<glow::Context>,
+    /// The result of a disjoint timer query has to be checked for `GL_GPU_DISJOINT_EXT`.
+    disjoint: bool,
+    free: Vec<glow::Query>,
+    running: Option<glow::Query>,
+    pending: VecDeque<glow::Query>,
+    latest: Option<Duration>,
+}
+
+impl GpuTimer {
+    /// `None` if the GL has no timer queries.
+    pub(crate) fn new(gl: std::sync::Arc<glow::Context>) -> Option<Self> {
+        if cfg!(target_arch = "wasm32") {
+            // WebGL has them in another shape
+            return None;
+        }
+        let extensions = gl.supported_extensions();
+        let disjoint = extensions.contains("GL_EXT_disjoint_timer_query");
+        if !disjoint && !extensions.contains("GL_ARB_timer_query") {
+            return None;
+        }
+
+        let mut free = Vec::with_capacity(NUM_QUERIES);
+        for _ in 0..NUM_QUERIES {
+            match unsafe { gl.create_query() } {
+                Ok(query) => free.push(query),
+                Err(err) => {
+                    tracing::warn!("Failed to create a timer query: {}", err);
+                    for query in free {
+                        unsafe { gl.delete_query(query) };
+                    }
+                    return None;
+                }
+            }
+        }
+
+        Some(Self {
+            gl,
+            disjoint,
+            free,
+            running: None,
+            pending: VecDeque::new(),
+            latest: None,
+        })
+    }
+
+    pub(crate) fn begin(&mut self) {
+        self.poll();
+        // With all queries in flight this frame goes unmeasured
+        if let Some(query) = self.free.pop() {
+            unsafe { self.gl.begin_query(glow::TIME_ELAPSED, query) };
+            self.running = Some(query);
+        }
+    }
+
+    pub(crate) fn end(&mut self) {
+        if let Some(query) = self.running.take() {
+            unsafe { self.gl.end_query(glow::TIME_ELAPSED) };
+            self.pending.push_back(query);
+        }
+    }
+
+    /// The GPU time of the latest frame whose result is in.
+    pub(crate) fn latest(&self) -> Option<Duration> {
+        self.latest
+    }
+
+    fn poll(&mut self) {
+        while let Some(&query) = self.pending.front() {
+            let available = unsafe {
+                self.gl
+                    .get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE)
+            };
+            if available == 0 {
+                break;
+            }
+            let nanos = unsafe { self.gl.get_query_parameter_u32(query, glow::QUERY_RESULT) };
+            self.pending.pop_front();
+            self.free.push(query);
+
+            let disjoint =
+                self.disjoint && unsafe { self.gl.get_parameter_i32(GPU_DISJOINT_EXT) } != 0;
+            if !disjoint {
+                self.latest = Some(Duration::from_nanos(nanos as u64));
+            }
+        }
+    }
+
+    pub(crate) unsafe fn destroy(&mut self) {
+        if self.running.take().is_some() {
+            self.gl.end_query(glow::TIME_ELAPSED);
+        }
+        for query in self.free.drain(..).chain(self.pending.drain(..)) {
+            self.gl.delete_query(query);
+        }
+    }
+}
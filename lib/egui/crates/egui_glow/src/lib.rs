@@ -13,7 +13,15 @@
 
 pub mod painter;
 pub use glow;
-pub use painter::{CallbackFn, Painter};
+pub use painter::{CallbackFn, Painter, Rotation};
+mod budget;
+pub use budget::TextureBudget;
+mod damage;
+pub use damage::DamageTracker;
+mod upload;
+pub use upload::UploadQueue;
+mod callback_target;
+mod gpu_timer;
 mod misc_util;
 mod post_process;
 mod shader_version;
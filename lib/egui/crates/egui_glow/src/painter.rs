@@ -1,26 +1,28 @@
 #![allow(unsafe_code)]
 
 use std::{collections::HashMap, sync::Arc};
-use std::cmp::max;
-use std::f32::consts::PI;
 
-use egui::{emath::Rect, epaint::{Color32, Mesh, PaintCallbackInfo, Primitive, Vertex}, pos2, vec2};
-use egui::emath::Rot2;
+use egui::{
+    emath::Rect,
+    epaint::{Color32, Mesh, PaintCallbackInfo, Primitive, Vertex},
+    pos2,
+};
 use glow::HasContext as _;
 use memoffset::offset_of;
 
+use crate::budget::TextureBudget;
+use crate::callback_target::CallbackTarget;
 use crate::check_for_gl_error;
+use crate::gpu_timer::GpuTimer;
 use crate::misc_util::{compile_shader, link_program};
 use crate::post_process::PostProcess;
 use crate::shader_version::ShaderVersion;
+use crate::upload::{Upload, UploadQueue};
 use crate::vao;
 
 pub use glow::Context;
 
-#[cfg(not(ttsim))]
 const VERT_SRC: &str = include_str!("shader/vertex.glsl");
-#[cfg(ttsim)]
-const VERT_SRC: &str = include_str!("shader/vertex_ttsim.glsl");
 const FRAG_SRC: &str = include_str!("shader/fragment.glsl");
 
 pub type TextureFilter = egui::TextureFilter;
@@ -38,6 +40,57 @@ impl TextureFilterExt for TextureFilter {
     }
 }
 
+/// How the painted image is rotated clockwise on the framebuffer, for panels that are mounted
+/// in another orientation than the UI is laid out in.
+///
+/// The screen size given to the [`Painter`] is always the one egui lays out in. With
+/// [`Rotation::Cw90`] and [`Rotation::Cw270`] the framebuffer is as wide as the screen is tall.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Cw90,
+    Cw180,
+    Cw270,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+impl Rotation {
+    /// The size of the framebuffer showing a screen of `screen_size`.
+    pub fn framebuffer_size(self, [width, height]: [u32; 2]) -> [u32; 2] {
+        match self {
+            Rotation::None | Rotation::Cw180 => [width, height],
+            Rotation::Cw90 | Rotation::Cw270 => [height, width],
+        }
+    }
+
+    /// Maps a position on a screen of `screen_size` (origin top left) to the framebuffer
+    /// (origin top left).
+    fn apply(self, [width, height]: [f32; 2], pos: egui::Pos2) -> egui::Pos2 {
+        match self {
+            Rotation::None => pos,
+            Rotation::Cw90 => pos2(height - pos.y, pos.x),
+            Rotation::Cw180 => pos2(width - pos.x, height - pos.y),
+            Rotation::Cw270 => pos2(pos.y, width - pos.x),
+        }
+    }
+
+    /// The same mapping as [`Self::apply`] on positions normalized to 0-1, as a column-major
+    /// `mat3` for the vertex shader.
+    fn matrix(self) -> [f32; 9] {
+        match self {
+            Rotation::None => [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            Rotation::Cw90 => [0.0, 1.0, 0.0, -1.0, 0.0, 0.0, 1.0, 0.0, 1.0],
+            Rotation::Cw180 => [-1.0, 0.0, 0.0, 0.0, -1.0, 0.0, 1.0, 1.0, 1.0],
+            Rotation::Cw270 => [0.0, -1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 1.0],
+        }
+    }
+}
+
 /// An OpenGL painter using [`glow`].
 ///
 /// This is responsible for painting egui and managing egui textures.
@@ -52,26 +105,60 @@ pub struct Painter {
 
     program: glow::Program,
     u_screen_size: glow::UniformLocation,
+    u_rotation: glow::UniformLocation,
     u_sampler: glow::UniformLocation,
     is_webgl_1: bool,
     is_embedded: bool,
     vao: crate::vao::VertexArrayObject,
     srgb_support: bool,
+    /// See [`Painter::new_gamma_space`].
+    gamma_space: bool,
     post_process: Option<PostProcess>,
+    /// Where callbacks paint on rotated outputs, created on the first one.
+    callback_target: Option<CallbackTarget>,
+    /// Bound while a callback paints into [`Self::callback_target`].
+    callback_fbo: Option<glow::Framebuffer>,
     vbo: glow::Buffer,
     element_array_buffer: glow::Buffer,
 
     textures: HashMap<egui::TextureId, glow::Texture>,
 
-    next_native_tex_id: u64,
+    /// Textures uploaded on other threads, also hands out the ids of native textures
+    upload_queue: UploadQueue,
+
+    /// See [`Painter::texture_budget`].
+    texture_budget: TextureBudget,
+
+    /// See [`Painter::set_gpu_timing`].
+    gpu_timer: Option<GpuTimer>,
 
     /// Stores outdated OpenGL textures that are yet to be deleted
     textures_to_destroy: Vec<glow::Texture>,
 
+    /// CPU copies of the textures egui manages, see [`Painter::set_keep_texture_copies`].
+    texture_copies: Option<HashMap<egui::TextureId, TextureCopy>>,
+
+    /// See [`Painter::set_output_rotation`].
+    rotation: Rotation,
+
+    /// The screen size given to the last [`Painter::paint_primitives`], in pixels.
+    screen_size_px: [u32; 2],
+
+    /// Only this part of the screen (in pixels) is painted this frame, see
+    /// [`Painter::paint_and_update_textures_in`].
+    damage: Option<Rect>,
+
     /// Used to make sure we are destroyed correctly.
     destroyed: bool,
 }
 
+/// What was uploaded to a texture, in the format given to `upload_texture_srgb`.
+struct TextureCopy {
+    size: [usize; 2],
+    filter: TextureFilter,
+    data: Vec<u8>,
+}
+
 /// A callback function that can be used to compose an [`egui::PaintCallback`] for custom rendering
 /// with [`glow`].
 ///
@@ -109,6 +196,28 @@ impl Painter {
         gl: Arc<glow::Context>,
         pp_fb_extent: Option<[i32; 2]>,
         shader_prefix: &str,
+    ) -> Result<Painter, String> {
+        Self::new_impl(gl, pp_fb_extent, shader_prefix, false)
+    }
+
+    /// Create a painter that blends in gamma space, like [`Self::new`] does on ES 1.00 without
+    /// `EXT_sRGB`, but without converting every fragment to linear and back. That is much
+    /// cheaper on slow `mediump` GPUs, at the cost of e.g. antialiased edges and translucent
+    /// shapes looking a little darker than they should.
+    ///
+    /// There is no post processing pass and textures are sampled as they are.
+    ///
+    /// # Errors
+    /// Like [`Self::new`].
+    pub fn new_gamma_space(gl: Arc<glow::Context>, shader_prefix: &str) -> Result<Painter, String> {
+        Self::new_impl(gl, None, shader_prefix, true)
+    }
+
+    fn new_impl(
+        gl: Arc<glow::Context>,
+        pp_fb_extent: Option<[i32; 2]>,
+        shader_prefix: &str,
+        gamma_space: bool,
     ) -> Result<Painter, String> {
         crate::profile_function!();
         crate::check_for_gl_error_even_in_release!(&gl, "before Painter::new");
@@ -122,6 +231,9 @@ impl Painter {
         let srgb_support = gl.supported_extensions().contains("EXT_sRGB");
 
         let (post_process, srgb_support_define) = match (shader_version, srgb_support) {
+            // Colors stay sRGB encoded all the way through
+            _ if gamma_space => (None, "#define GAMMA_SPACE"),
+
             // WebGL2 support sRGB default
             (ShaderVersion::Es300, _) | (ShaderVersion::Es100, true) => unsafe {
                 // Add sRGB support marker for fragment shader
@@ -150,14 +262,21 @@ impl Painter {
             _ => (None, "#define SRGB_SUPPORTED"),
         };
 
+        let gamma_space_define = if gamma_space {
+            "#define GAMMA_SPACE"
+        } else {
+            ""
+        };
+
         unsafe {
             let vert = compile_shader(
                 &gl,
                 glow::VERTEX_SHADER,
                 &format!(
-                    "{}\n{}\n{}\n{}",
+                    "{}\n{}\n{}\n{}\n{}",
                     header,
                     shader_prefix,
+                    gamma_space_define,
                     shader_version.is_new_shader_interface(),
                     VERT_SRC
                 ),
@@ -180,6 +299,7 @@ impl Painter {
             gl.delete_shader(vert);
             gl.delete_shader(frag);
             let u_screen_size = gl.get_uniform_location(program, "u_screen_size").unwrap();
+            let u_rotation = gl.get_uniform_location(program, "u_rotation").unwrap();
             let u_sampler = gl.get_uniform_location(program, "u_sampler").unwrap();
 
             let vbo = gl.create_buffer()?;
@@ -226,17 +346,27 @@ impl Painter {
                 max_texture_side,
                 program,
                 u_screen_size,
+                u_rotation,
                 u_sampler,
                 is_webgl_1,
                 is_embedded: matches!(shader_version, ShaderVersion::Es100 | ShaderVersion::Es300),
                 vao,
                 srgb_support,
+                gamma_space,
                 post_process,
+                callback_target: None,
+                callback_fbo: None,
                 vbo,
                 element_array_buffer,
                 textures: Default::default(),
-                next_native_tex_id: 1 << 32,
+                upload_queue: UploadQueue::new(),
+                texture_budget: TextureBudget::default(),
+                gpu_timer: None,
                 textures_to_destroy: Vec::new(),
+                texture_copies: None,
+                rotation: Rotation::None,
+                screen_size_px: [0, 0],
+                damage: None,
                 destroyed: false,
             })
         }
@@ -251,6 +381,16 @@ impl Painter {
         self.max_texture_side
     }
 
+    /// Rotate everything painted from now on, e.g. for a panel mounted upright showing a UI that
+    /// is laid out in landscape. The default is [`Rotation::None`].
+    pub fn set_output_rotation(&mut self, rotation: Rotation) {
+        self.rotation = rotation;
+    }
+
+    pub fn output_rotation(&self) -> Rotation {
+        self.rotation
+    }
+
     /// The framebuffer we use as an intermediate render target,
     /// or `None` if we are painting to the screen framebuffer directly.
     ///
@@ -260,8 +400,12 @@ impl Painter {
     /// So if in a [`egui::Shape::Callback`] you need to use an offscreen FBO, you should
     /// then restore to this afterwards with
     /// `gl.bind_framebuffer(glow::FRAMEBUFFER, painter.intermediate_fbo());`
+    ///
+    /// On rotated outputs callbacks paint into a framebuffer of their own, which this returns
+    /// while they are called.
     pub fn intermediate_fbo(&self) -> Option<glow::Framebuffer> {
-        self.post_process.as_ref().map(|pp| pp.fbo())
+        self.callback_fbo
+            .or_else(|| self.post_process.as_ref().map(|pp| pp.fbo()))
     }
 
     unsafe fn prepare_painting(
@@ -289,7 +433,7 @@ impl Painter {
             glow::ONE,
         );
 
-        if !cfg!(target_arch = "wasm32") {
+        if !cfg!(target_arch = "wasm32") && !self.gamma_space {
             self.gl.enable(glow::FRAMEBUFFER_SRGB);
             check_for_gl_error!(&self.gl, "FRAMEBUFFER_SRGB");
         }
@@ -297,18 +441,17 @@ impl Painter {
         let width_in_points = width_in_pixels as f32 / pixels_per_point;
         let height_in_points = height_in_pixels as f32 / pixels_per_point;
 
-        if cfg!(ttsim) {
-            self.gl
-                .viewport(0, 0, width_in_pixels as i32, height_in_pixels as i32);
-        } else {
-            self.gl
-                .viewport(0, 0, height_in_pixels as i32, width_in_pixels as i32);
-        }
+        let [fb_width, fb_height] = self
+            .rotation
+            .framebuffer_size([width_in_pixels, height_in_pixels]);
+        self.gl.viewport(0, 0, fb_width as i32, fb_height as i32);
 
         self.gl.use_program(Some(self.program));
 
         self.gl
             .uniform_2_f32(Some(&self.u_screen_size), width_in_points, height_in_points);
+        self.gl
+            .uniform_matrix_3_f32_slice(Some(&self.u_rotation), false, &self.rotation.matrix());
         self.gl.uniform_1_i32(Some(&self.u_sampler), 0);
         self.gl.active_texture(glow::TEXTURE0);
 
@@ -341,6 +484,154 @@ impl Painter {
         }
     }
 
+    /// Like [`Self::paint_and_update_textures`], but only paints inside `damage`, a rect in pixels
+    /// from [`crate::DamageTracker::update`]. The rest of the back buffer has to hold the
+    /// previous frame already, clear `damage` with [`Self::clear_damage`] before calling this.
+    ///
+    /// Everything is painted if [`Self::supports_partial_redraw`] is `false`.
+    pub fn paint_and_update_textures_in(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        textures_delta: &egui::TexturesDelta,
+        damage: Rect,
+    ) {
+        crate::profile_function!();
+        for (id, image_delta) in &textures_delta.set {
+            self.set_texture(*id, image_delta);
+        }
+
+        self.paint_primitives_in(screen_size_px, pixels_per_point, clipped_primitives, damage);
+
+        for &id in &textures_delta.free {
+            self.free_texture(id);
+        }
+    }
+
+    /// Like [`Self::paint_primitives`], but only paints inside `damage`, see
+    /// [`Self::paint_and_update_textures_in`].
+    pub fn paint_primitives_in(
+        &mut self,
+        screen_size_px: [u32; 2],
+        pixels_per_point: f32,
+        clipped_primitives: &[egui::ClippedPrimitive],
+        damage: Rect,
+    ) {
+        self.damage = Some(damage);
+        self.paint_primitives(screen_size_px, pixels_per_point, clipped_primitives);
+        self.damage = None;
+    }
+
+    /// Like [`clear`], but only clears `damage`, a rect in pixels.
+    pub fn clear_damage(
+        &self,
+        screen_size_in_pixels: [u32; 2],
+        clear_color: egui::Rgba,
+        damage: Rect,
+    ) {
+        crate::profile_function!();
+        unsafe {
+            self.gl.enable(glow::SCISSOR_TEST);
+            set_clip_rect(
+                &self.gl,
+                self.rotation,
+                (screen_size_in_pixels[0], screen_size_in_pixels[1]),
+                1.0,
+                damage,
+            );
+            self.gl.clear_color(
+                clear_color[0],
+                clear_color[1],
+                clear_color[2],
+                clear_color[3],
+            );
+            self.gl.clear(glow::COLOR_BUFFER_BIT);
+            self.gl.disable(glow::SCISSOR_TEST);
+        }
+    }
+
+    /// Where `damage` (in pixels, e.g. from [`crate::DamageTracker::update`]) ends up in the
+    /// framebuffer, as `[x, y, width, height]` ready for `eglSwapBuffersWithDamageKHR`.
+    pub fn damage_in_framebuffer(&self, screen_size_in_pixels: [u32; 2], damage: Rect) -> [i32; 4] {
+        framebuffer_rect(
+            self.rotation,
+            (screen_size_in_pixels[0], screen_size_in_pixels[1]),
+            1.0,
+            damage,
+        )
+    }
+
+    /// Reads `rect` (in pixels) of the screen last painted back from the framebuffer that is
+    /// bound, so call it after painting and before swapping buffers.
+    ///
+    /// The image is upright whatever the [`Self::output_rotation`]. The framebuffer holds sRGB
+    /// encoded, premultiplied colors whether egui blended in linear space or not, which is what
+    /// [`Color32`] is, so they are taken as they are.
+    pub fn read_screen_rgba(&self, rect: Rect) -> egui::ColorImage {
+        crate::profile_function!();
+        self.assert_not_destroyed();
+
+        let [screen_width, screen_height] = self.screen_size_px;
+        let screen_rect = Rect::from_min_max(
+            pos2(0.0, 0.0),
+            pos2(screen_width as f32, screen_height as f32),
+        );
+        let rect = rect.intersect(screen_rect);
+        let rect = Rect::from_min_max(rect.min.round(), rect.max.round());
+        if !rect.is_positive() {
+            return egui::ColorImage::new([0, 0], Color32::TRANSPARENT);
+        }
+
+        let [x, y, width, height] =
+            framebuffer_rect(self.rotation, (screen_width, screen_height), 1.0, rect);
+        let mut pixels = vec![0_u8; (width * height * 4) as usize];
+        unsafe {
+            self.gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+            self.gl.read_pixels(
+                x,
+                y,
+                width,
+                height,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut pixels),
+            );
+        }
+        check_for_gl_error!(&self.gl, "read_screen_rgba");
+
+        // Look up the framebuffer pixel under the center of each screen pixel
+        let size = [screen_width as f32, screen_height as f32];
+        let [_, fb_height] = self
+            .rotation
+            .framebuffer_size([screen_width, screen_height]);
+        let [image_width, image_height] = [rect.width() as usize, rect.height() as usize];
+        let mut image = egui::ColorImage::new([image_width, image_height], Color32::TRANSPARENT);
+        for row in 0..image_height {
+            for column in 0..image_width {
+                let pos = rect.min + egui::vec2(column as f32 + 0.5, row as f32 + 0.5);
+                let pos = self.rotation.apply(size, pos);
+                // Rows are read bottom up
+                let fb_x = pos.x as i32 - x;
+                let fb_y = fb_height as i32 - 1 - pos.y as i32 - y;
+                let i = ((fb_y * width + fb_x) * 4) as usize;
+                image.pixels[row * image_width + column] = Color32::from_rgba_premultiplied(
+                    pixels[i],
+                    pixels[i + 1],
+                    pixels[i + 2],
+                    pixels[i + 3],
+                );
+            }
+        }
+        image
+    }
+
+    /// Whether painting only the damaged part of the screen works. It doesn't when painting
+    /// through an intermediate framebuffer.
+    pub fn supports_partial_redraw(&self) -> bool {
+        self.post_process.is_none()
+    }
+
     /// Main entry-point for painting a frame.
     ///
     /// You should call `target.clear_color(..)` before
@@ -369,26 +660,60 @@ impl Painter {
     ) {
         crate::profile_function!();
         self.assert_not_destroyed();
+        self.apply_uploads();
+        self.screen_size_px = screen_size_px;
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.begin();
+        }
+        // Everything on screen is in use, also what is outside the damage and not painted again
+        let painted = clipped_primitives
+            .iter()
+            .filter_map(|clipped| match &clipped.primitive {
+                Primitive::Mesh(mesh) => Some(mesh.texture_id),
+                Primitive::Callback(_) => None,
+            });
+        self.texture_budget.painting(painted);
 
         if let Some(ref mut post_process) = self.post_process {
+            let [fb_width, fb_height] = self.rotation.framebuffer_size(screen_size_px);
             unsafe {
-                post_process.begin(screen_size_px[0] as i32, screen_size_px[1] as i32);
+                post_process.begin(fb_width as i32, fb_height as i32);
                 post_process.bind();
                 self.gl.disable(glow::SCISSOR_TEST);
-                self.gl
-                    .viewport(0, 0, screen_size_px[0] as i32, screen_size_px[1] as i32);
+                self.gl.viewport(0, 0, fb_width as i32, fb_height as i32);
                 // use the same clear-color as was set for the screen framebuffer.
                 self.gl.clear(glow::COLOR_BUFFER_BIT);
             }
         }
         let size_in_pixels = unsafe { self.prepare_painting(screen_size_px, pixels_per_point) };
 
+        // In points, like the clip rects
+        let damage = self
+            .damage
+            .filter(|_| self.supports_partial_redraw())
+            .map(|damage| {
+                Rect::from_min_max(
+                    (damage.min.to_vec2() / pixels_per_point).to_pos2(),
+                    (damage.max.to_vec2() / pixels_per_point).to_pos2(),
+                )
+            });
+
         for egui::ClippedPrimitive {
             clip_rect,
             primitive,
         } in clipped_primitives
         {
-            set_clip_rect(&self.gl, size_in_pixels, pixels_per_point, *clip_rect);
+            let clip_rect = damage.map_or(*clip_rect, |damage| clip_rect.intersect(damage));
+            if !clip_rect.is_positive() {
+                continue;
+            }
+            set_clip_rect(
+                &self.gl,
+                self.rotation,
+                size_in_pixels,
+                pixels_per_point,
+                clip_rect,
+            );
 
             match primitive {
                 Primitive::Mesh(mesh) => {
@@ -407,26 +732,57 @@ impl Painter {
                         let rect_min_y = rect_min_y.round() as i32;
                         let rect_max_x = rect_max_x.round() as i32;
                         let rect_max_y = rect_max_y.round() as i32;
+                        let width = rect_max_x - rect_min_x;
+                        let height = rect_max_y - rect_min_y;
+                        if width <= 0 || height <= 0 {
+                            continue;
+                        }
+
+                        // Where to paint the rest, the post process framebuffer is bound below
+                        let screen_fbo =
+                            if self.rotation != Rotation::None && self.post_process.is_none() {
+                                unsafe { bound_framebuffer(&self.gl) }
+                            } else {
+                                None
+                            };
 
-                        if cfg!(ttsim) {
+                        let info = if self.rotation == Rotation::None {
                             unsafe {
                                 self.gl.viewport(
                                     rect_min_x,
                                     size_in_pixels.1 as i32 - rect_max_y,
-                                    rect_max_x - rect_min_x,
-                                    rect_max_y - rect_min_y,
+                                    width,
+                                    height,
                                 );
                             }
+                            egui::PaintCallbackInfo {
+                                viewport: callback.rect,
+                                clip_rect,
+                                pixels_per_point,
+                                screen_size_px,
+                            }
                         } else {
-                            // TODO(nulldev) Fix this calculation
-                            panic!("callback based rendering is currently not supported")
-                        }
-
-                        let info = egui::PaintCallbackInfo {
-                            viewport: callback.rect,
-                            clip_rect: *clip_rect,
-                            pixels_per_point,
-                            screen_size_px,
+                            // The callback paints upright into a framebuffer of its own, as if
+                            // the screen was just its rect. It is drawn rotated below.
+                            let target = match unsafe { self.begin_callback_target(width, height) }
+                            {
+                                Ok(target) => target,
+                                Err(err) => {
+                                    tracing::error!(
+                                        "Failed to create the framebuffer for callbacks: {}",
+                                        err
+                                    );
+                                    continue;
+                                }
+                            };
+                            self.callback_fbo = Some(target);
+                            let offset = callback.rect.min.to_vec2();
+                            egui::PaintCallbackInfo {
+                                viewport: callback.rect.translate(-offset),
+                                clip_rect: clip_rect.translate(-offset),
+                                pixels_per_point,
+                                screen_size_px: [width as u32, height as u32],
+                            }
                         };
 
                         if let Some(callback) = callback.callback.downcast_ref::<CallbackFn>() {
@@ -441,9 +797,24 @@ impl Painter {
                         unsafe {
                             if let Some(ref mut post_process) = self.post_process {
                                 post_process.bind();
+                            } else if self.callback_fbo.is_some() {
+                                self.gl.bind_framebuffer(glow::FRAMEBUFFER, screen_fbo);
                             }
                             self.prepare_painting(screen_size_px, pixels_per_point)
                         };
+
+                        if self.callback_fbo.take().is_some() {
+                            let pixel_rect = Rect::from_min_max(
+                                pos2(rect_min_x as f32, rect_min_y as f32),
+                                pos2(rect_max_x as f32, rect_max_y as f32),
+                            );
+                            self.paint_callback_target(
+                                pixel_rect,
+                                pixels_per_point,
+                                size_in_pixels,
+                                clip_rect,
+                            );
+                        }
                     }
                 }
             }
@@ -461,44 +832,141 @@ impl Painter {
 
             check_for_gl_error!(&self.gl, "painting");
         }
+
+        if let Some(gpu_timer) = &mut self.gpu_timer {
+            gpu_timer.end();
+        }
+        self.evict_textures();
+    }
+
+    /// Measure how long the GPU takes for [`Self::paint_primitives`], see [`Self::gpu_time`].
+    /// Does nothing if the GL has no timer queries (`GL_EXT_disjoint_timer_query` or
+    /// `GL_ARB_timer_query`).
+    pub fn set_gpu_timing(&mut self, enabled: bool) {
+        if enabled {
+            if self.gpu_timer.is_none() {
+                self.gpu_timer = GpuTimer::new(self.gl.clone());
+            }
+        } else if let Some(mut gpu_timer) = self.gpu_timer.take() {
+            unsafe { gpu_timer.destroy() };
+        }
+    }
+
+    /// How long the GPU took to paint the latest frame whose result is in, usually a few
+    /// frames ago. `None` unless turned on with [`Self::set_gpu_timing`] and supported.
+    pub fn gpu_time(&self) -> Option<std::time::Duration> {
+        self.gpu_timer.as_ref().and_then(GpuTimer::latest)
+    }
+
+    /// Deletes textures over the [`TextureBudget`].
+    fn evict_textures(&mut self) {
+        for (tex_id, on_evict) in self.texture_budget.evict() {
+            tracing::debug!("Evicting texture {:?}", tex_id);
+            if let Some(old_tex) = self.textures.remove(&tex_id) {
+                unsafe { self.gl.delete_texture(old_tex) };
+            }
+            if let Some(copies) = &mut self.texture_copies {
+                copies.remove(&tex_id);
+            }
+            on_evict(tex_id);
+        }
+    }
+
+    /// Binds the framebuffer callbacks paint into on rotated outputs, with a `width` x `height`
+    /// viewport, and returns it.
+    unsafe fn begin_callback_target(
+        &mut self,
+        width: i32,
+        height: i32,
+    ) -> Result<glow::Framebuffer, String> {
+        if self.callback_target.is_none() {
+            let (internal_format, format) = self.texture_format();
+            self.callback_target = Some(CallbackTarget::new(
+                self.gl.clone(),
+                internal_format,
+                format,
+            )?);
+        }
+        let target = self.callback_target.as_mut().unwrap();
+        target.begin(width, height);
+        Ok(target.fbo())
+    }
+
+    /// Draws what the last callback painted into [`Self::callback_target`] onto `pixel_rect`,
+    /// rotated and clipped like a mesh.
+    fn paint_callback_target(
+        &mut self,
+        pixel_rect: Rect,
+        pixels_per_point: f32,
+        size_in_pixels: (u32, u32),
+        clip_rect: Rect,
+    ) {
+        let target = match &self.callback_target {
+            Some(target) => target,
+            None => return,
+        };
+        let texture = target.texture();
+        let [u_max, v_max] = target.uv_max(pixel_rect.width() as i32, pixel_rect.height() as i32);
+
+        let rect = Rect::from_min_max(
+            (pixel_rect.min.to_vec2() / pixels_per_point).to_pos2(),
+            (pixel_rect.max.to_vec2() / pixels_per_point).to_pos2(),
+        );
+        // The framebuffer's rows go bottom up
+        let uv = Rect::from_min_max(pos2(0.0, v_max), pos2(u_max, 0.0));
+        let mut mesh = Mesh::default();
+        mesh.add_rect_with_uv(rect, uv, Color32::WHITE);
+
+        set_clip_rect(
+            &self.gl,
+            self.rotation,
+            size_in_pixels,
+            pixels_per_point,
+            clip_rect,
+        );
+        self.draw_mesh(&mesh, texture);
     }
 
     #[inline(never)] // Easier profiling
     fn paint_mesh(&mut self, mesh: &Mesh) {
         debug_assert!(mesh.is_valid());
         if let Some(texture) = self.texture(mesh.texture_id) {
-            unsafe {
-                self.gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
-                self.gl.buffer_data_u8_slice(
-                    glow::ARRAY_BUFFER,
-                    bytemuck::cast_slice(&mesh.vertices),
-                    glow::STREAM_DRAW,
-                );
+            self.draw_mesh(mesh, texture);
+        } else {
+            tracing::warn!("Failed to find texture {:?}", mesh.texture_id);
+        }
+    }
 
-                self.gl
-                    .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.element_array_buffer));
-                self.gl.buffer_data_u8_slice(
-                    glow::ELEMENT_ARRAY_BUFFER,
-                    bytemuck::cast_slice(&mesh.indices),
-                    glow::STREAM_DRAW,
-                );
+    fn draw_mesh(&mut self, mesh: &Mesh, texture: glow::Texture) {
+        unsafe {
+            self.gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+            self.gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                bytemuck::cast_slice(&mesh.vertices),
+                glow::STREAM_DRAW,
+            );
 
-                self.gl.bind_texture(glow::TEXTURE_2D, Some(texture));
-            }
+            self.gl
+                .bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(self.element_array_buffer));
+            self.gl.buffer_data_u8_slice(
+                glow::ELEMENT_ARRAY_BUFFER,
+                bytemuck::cast_slice(&mesh.indices),
+                glow::STREAM_DRAW,
+            );
 
-            unsafe {
-                self.gl.draw_elements(
-                    glow::TRIANGLES,
-                    mesh.indices.len() as i32,
-                    glow::UNSIGNED_INT,
-                    0,
-                );
-            }
+            self.gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        }
 
-            check_for_gl_error!(&self.gl, "paint_mesh");
-        } else {
-            tracing::warn!("Failed to find texture {:?}", mesh.texture_id);
+        unsafe {
+            self.gl.draw_elements(
+                glow::TRIANGLES,
+                mesh.indices.len() as i32,
+                glow::UNSIGNED_INT,
+                0,
+            );
         }
+
+        check_for_gl_error!(&self.gl, "paint_mesh");
     }
 
     // ------------------------------------------------------------------------
@@ -508,6 +976,16 @@ impl Painter {
 
         self.assert_not_destroyed();
 
+        if delta.pos.is_some() && !self.textures.contains_key(&tex_id) {
+            // Evicted, there is nothing left to update
+            return;
+        }
+        if delta.pos.is_none() {
+            let [w, h] = delta.image.size();
+            self.texture_budget
+                .set_bytes(tex_id, w * h * delta.image.bytes_per_pixel());
+        }
+
         let glow_texture = *self
             .textures
             .entry(tex_id)
@@ -527,6 +1005,7 @@ impl Painter {
                 let data: &[u8] = bytemuck::cast_slice(image.pixels.as_ref());
 
                 self.upload_texture_srgb(delta.pos, image.size, delta.filter, data);
+                self.keep_texture_copy(tex_id, delta.pos, image.size, delta.filter, data);
             }
             egui::ImageData::Font(image) => {
                 assert_eq!(
@@ -546,10 +1025,67 @@ impl Painter {
                     .collect();
 
                 self.upload_texture_srgb(delta.pos, image.size, delta.filter, &data);
+                self.keep_texture_copy(tex_id, delta.pos, image.size, delta.filter, &data);
             }
         };
     }
 
+    fn keep_texture_copy(
+        &mut self,
+        tex_id: egui::TextureId,
+        pos: Option<[usize; 2]>,
+        [w, h]: [usize; 2],
+        filter: TextureFilter,
+        data: &[u8],
+    ) {
+        let copies = match &mut self.texture_copies {
+            Some(copies) => copies,
+            None => return,
+        };
+
+        match pos {
+            None => {
+                copies.insert(
+                    tex_id,
+                    TextureCopy {
+                        size: [w, h],
+                        filter,
+                        data: data.to_vec(),
+                    },
+                );
+                self.texture_budget.set_copy_bytes(tex_id, data.len());
+            }
+            Some([x, y]) => {
+                // Without a copy of the whole texture there is nothing to patch
+                if let Some(copy) = copies.get_mut(&tex_id) {
+                    copy.filter = filter;
+                    let stride = copy.size[0] * 4;
+                    for (row, src) in data.chunks_exact(w * 4).enumerate() {
+                        let start = (y + row) * stride + x * 4;
+                        copy.data[start..start + w * 4].copy_from_slice(src);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The internal format and format textures are uploaded with.
+    fn texture_format(&self) -> (u32, u32) {
+        if self.gamma_space {
+            // Sampled without decoding
+            (glow::RGBA, glow::RGBA)
+        } else if self.is_webgl_1 {
+            let format = if self.srgb_support {
+                glow::SRGB_ALPHA
+            } else {
+                glow::RGBA
+            };
+            (format, format)
+        } else {
+            (glow::SRGB8_ALPHA8, glow::RGBA)
+        }
+    }
+
     fn upload_texture_srgb(
         &mut self,
         pos: Option<[usize; 2]>,
@@ -596,16 +1132,7 @@ impl Painter {
             );
             check_for_gl_error!(&self.gl, "tex_parameter");
 
-            let (internal_format, src_format) = if self.is_webgl_1 {
-                let format = if self.srgb_support {
-                    glow::SRGB_ALPHA
-                } else {
-                    glow::RGBA
-                };
-                (format, format)
-            } else {
-                (glow::SRGB8_ALPHA8, glow::RGBA)
-            };
+            let (internal_format, src_format) = self.texture_format();
 
             self.gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
 
@@ -645,6 +1172,96 @@ impl Painter {
         if let Some(old_tex) = self.textures.remove(&tex_id) {
             unsafe { self.gl.delete_texture(old_tex) };
         }
+        if let Some(copies) = &mut self.texture_copies {
+            copies.remove(&tex_id);
+        }
+        self.texture_budget.freed(tex_id);
+    }
+
+    /// Keep a CPU copy of every texture egui manages, so that [`Self::recreate_textures`] can
+    /// upload them again after the GL context was lost. Costs as much memory as the textures do,
+    /// see [`TextureMemoryUsage::bytes_copied`](egui::epaint::textures::TextureMemoryUsage::bytes_copied).
+    /// Off by default.
+    ///
+    /// Only textures uploaded in full after this is turned on are copied, so turn it on right
+    /// after creating the painter.
+    pub fn set_keep_texture_copies(&mut self, keep: bool) {
+        match (keep, &self.texture_copies) {
+            (true, None) => self.texture_copies = Some(HashMap::new()),
+            (false, Some(copies)) => {
+                for &tex_id in copies.keys() {
+                    self.texture_budget.set_copy_bytes(tex_id, 0);
+                }
+                self.texture_copies = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// See [`Self::set_keep_texture_copies`].
+    pub fn keeps_texture_copies(&self) -> bool {
+        self.texture_copies.is_some()
+    }
+
+    /// Uploads every texture egui manages again from its CPU copy, e.g. after the surface or
+    /// context was recreated. Needs [`Self::set_keep_texture_copies`].
+    ///
+    /// Native textures are left alone, whoever registered them has to replace them with
+    /// [`Self::replace_native_texture`] if they were lost.
+    pub fn recreate_textures(&mut self) {
+        crate::profile_function!();
+
+        self.assert_not_destroyed();
+
+        let copies = match self.texture_copies.take() {
+            Some(copies) => copies,
+            None => {
+                tracing::warn!("recreate_textures called without keeping texture copies");
+                return;
+            }
+        };
+
+        for (&tex_id, copy) in &copies {
+            if let Some(old_tex) = self.textures.remove(&tex_id) {
+                unsafe { self.gl.delete_texture(old_tex) };
+            }
+            let glow_texture = unsafe { self.gl.create_texture().unwrap() };
+            self.textures.insert(tex_id, glow_texture);
+            unsafe {
+                self.gl.bind_texture(glow::TEXTURE_2D, Some(glow_texture));
+            }
+            self.upload_texture_srgb(None, copy.size, copy.filter, &copy.data);
+        }
+
+        self.texture_copies = Some(copies);
+    }
+
+    /// Takes over from `lost`, a painter whose GL context was lost, once this painter was
+    /// created on the new context: its [`TextureBudget`], output rotation and the textures egui
+    /// manages, which are uploaded again from their copies (see
+    /// [`Self::set_keep_texture_copies`]).
+    ///
+    /// The GL objects of `lost` went with its context, so they are forgotten rather than
+    /// deleted. So are native textures, whoever made them has to register or upload them again.
+    /// The [`UploadQueue`] of `lost` is left behind too, as whatever uploads through it shares
+    /// textures with the lost context: uploads through it never arrive. Get the new one from
+    /// [`Self::upload_queue`] for a context sharing with the new one.
+    pub fn take_over_lost(&mut self, mut lost: Painter) {
+        crate::profile_function!();
+
+        self.assert_not_destroyed();
+
+        self.upload_queue = lost.upload_queue.detached();
+        self.texture_budget = lost.texture_budget.clone();
+        self.rotation = lost.rotation;
+        self.texture_copies = lost.texture_copies.take();
+        lost.destroyed = true;
+
+        if self.texture_copies.is_some() {
+            self.recreate_textures();
+        } else {
+            tracing::warn!("The GL context was lost without texture copies, textures are gone");
+        }
     }
 
     /// Get the [`glow::Texture`] bound to a [`egui::TextureId`].
@@ -660,8 +1277,7 @@ impl Painter {
     #[allow(clippy::needless_pass_by_value)] // False positive
     pub fn register_native_texture(&mut self, native: glow::Texture) -> egui::TextureId {
         self.assert_not_destroyed();
-        let id = egui::TextureId::User(self.next_native_tex_id);
-        self.next_native_tex_id += 1;
+        let id = self.upload_queue.allocate_id();
         self.textures.insert(id, native);
         id
     }
@@ -671,6 +1287,43 @@ impl Painter {
         if let Some(old_tex) = self.textures.insert(id, replacing) {
             self.textures_to_destroy.push(old_tex);
         }
+        if let Some(copies) = &mut self.texture_copies {
+            if copies.remove(&id).is_some() {
+                self.texture_budget.set_copy_bytes(id, 0);
+            }
+        }
+    }
+
+    /// For handing over textures uploaded through a shared GL context on another thread, so
+    /// that large textures don't stall painting.
+    pub fn upload_queue(&self) -> UploadQueue {
+        self.upload_queue.clone()
+    }
+
+    /// For accounting for the GPU memory of textures, and keeping it within a budget by evicting
+    /// textures that can be loaded again.
+    pub fn texture_budget(&self) -> TextureBudget {
+        self.texture_budget.clone()
+    }
+
+    /// Takes over the textures from the [`UploadQueue`] and returns the ids whose texture
+    /// changed, e.g. for [`crate::DamageTracker::texture_changed`]. Painting does this too.
+    pub fn apply_uploads(&mut self) -> Vec<egui::TextureId> {
+        let uploads = self.upload_queue.take();
+        let mut changed = Vec::with_capacity(uploads.len());
+        for upload in uploads {
+            match upload {
+                Upload::Set(id, texture, fence) => {
+                    fence.wait(&self.gl);
+                    if let Some(old_tex) = self.textures.insert(id, texture) {
+                        unsafe { self.gl.delete_texture(old_tex) };
+                    }
+                    changed.push(id);
+                }
+                Upload::Free(id) => self.free_texture(id),
+            }
+        }
+        changed
     }
 
     unsafe fn destroy_gl(&self) {
@@ -689,11 +1342,18 @@ impl Painter {
     /// that should be deleted.
     pub fn destroy(&mut self) {
         if !self.destroyed {
+            self.apply_uploads();
             unsafe {
                 self.destroy_gl();
                 if let Some(ref post_process) = self.post_process {
                     post_process.destroy();
                 }
+                if let Some(ref callback_target) = self.callback_target {
+                    callback_target.destroy();
+                }
+                if let Some(ref mut gpu_timer) = self.gpu_timer {
+                    gpu_timer.destroy();
+                }
             }
             self.destroyed = true;
         }
@@ -747,17 +1407,48 @@ impl Drop for Painter {
     }
 }
 
+/// The framebuffer currently bound, to bind it again after a callback painted elsewhere.
+unsafe fn bound_framebuffer(gl: &glow::Context) -> Option<glow::Framebuffer> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::num::NonZeroU32::new(gl.get_parameter_i32(glow::FRAMEBUFFER_BINDING) as u32)
+            .map(glow::NativeFramebuffer)
+    }
+    // Without post processing WebGL paints to the canvas
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = gl;
+        None
+    }
+}
+
 fn set_clip_rect(
     gl: &glow::Context,
+    rotation: Rotation,
     size_in_pixels: (u32, u32),
     pixels_per_point: f32,
     clip_rect: Rect,
 ) {
+    let [x, y, width, height] =
+        framebuffer_rect(rotation, size_in_pixels, pixels_per_point, clip_rect);
+    unsafe {
+        gl.scissor(x, y, width, height);
+    }
+}
+
+/// Where `rect` (in points) ends up in the framebuffer, as `[x, y, width, height]` with the
+/// origin at the bottom left like `glScissor` and `eglSwapBuffersWithDamageKHR` want it.
+fn framebuffer_rect(
+    rotation: Rotation,
+    size_in_pixels: (u32, u32),
+    pixels_per_point: f32,
+    rect: Rect,
+) -> [i32; 4] {
     // Transform clip rect to physical pixels:
-    let clip_min_x = pixels_per_point * clip_rect.min.x;
-    let clip_min_y = pixels_per_point * clip_rect.min.y;
-    let clip_max_x = pixels_per_point * clip_rect.max.x;
-    let clip_max_y = pixels_per_point * clip_rect.max.y;
+    let clip_min_x = pixels_per_point * rect.min.x;
+    let clip_min_y = pixels_per_point * rect.min.y;
+    let clip_max_x = pixels_per_point * rect.max.x;
+    let clip_max_y = pixels_per_point * rect.max.y;
 
     // Round to integer:
     let clip_min_x = clip_min_x.round() as i32;
@@ -771,46 +1462,16 @@ fn set_clip_rect(
     let clip_max_x = clip_max_x.clamp(clip_min_x, size_in_pixels.0 as i32);
     let clip_max_y = clip_max_y.clamp(clip_min_y, size_in_pixels.1 as i32);
 
-    // TODO(nulldev) Fix and enable clip rect
-    if cfg!(ttsim) {
-        unsafe {
-            gl.scissor(
-                clip_min_x,
-                size_in_pixels.1 as i32 - clip_max_y,
-                clip_max_x - clip_min_x,
-                clip_max_y - clip_min_y,
-            );
-        }
-    } else {
-        // Origin is bottom right
-        let clip_rect = rotate_rect(Rect::from(
-            [
-                pos2(clip_min_x as f32, clip_min_y as f32),
-                pos2(clip_max_x as f32, clip_max_y as f32)
-            ]
-        ));
-        unsafe {
-            gl.scissor(
-                clip_rect.min.x as i32,
-                clip_rect.min.y as i32,
-                clip_rect.width() as i32,
-                clip_rect.height() as i32,
-            );
-        }
-    }
-}
-
-fn rotate_rect(rect: Rect) -> Rect {
-    // Invert coordinate system vertically
+    let size = [size_in_pixels.0 as f32, size_in_pixels.1 as f32];
     let rect = Rect::from_two_pos(
-        pos2(rect.min.x, 480.0-rect.min.y),
-        pos2(rect.max.x, 480.0-rect.max.y)
+        rotation.apply(size, pos2(clip_min_x as f32, clip_min_y as f32)),
+        rotation.apply(size, pos2(clip_max_x as f32, clip_max_y as f32)),
     );
-    // Do rotation + offset to ensure it stays on screen
-    let rot = Rot2::from_angle(-PI/2.0);
-    let offset = vec2(0.0, 800.0);
-    Rect::from_two_pos(
-        (rot * rect.min.to_vec2() + offset).to_pos2(),
-        (rot * rect.max.to_vec2() + offset).to_pos2(),
-    )
+    let [_, fb_height] = rotation.framebuffer_size([size_in_pixels.0, size_in_pixels.1]);
+    [
+        rect.min.x as i32,
+        fb_height as i32 - rect.max.y as i32,
+        rect.width() as i32,
+        rect.height() as i32,
+    ]
 }
@@ -16,7 +16,13 @@ uniform sampler2D u_sampler;
     varying vec2 v_tc;
 #endif
 
-#ifdef SRGB_SUPPORTED
+#if defined(GAMMA_SPACE)
+    void main() {
+        // Texture and vertex colors are both sRGB encoded and premultiplied, and blending
+        // happens on the encoded values too. Cheap, but not quite correct.
+        gl_FragColor = v_rgba * texture2D(u_sampler, v_tc);
+    }
+#elif defined(SRGB_SUPPORTED)
     void main() {
         // The texture sampler is sRGB aware, and OpenGL already expects linear rgba output
         // so no need for any sRGB conversions here:
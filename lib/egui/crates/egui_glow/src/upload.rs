@@ -0,0 +1,136 @@
+//! Handing textures uploaded through a shared GL context on another thread over to the
+//! [`Painter`](crate::Painter), so that large images don't stall painting.
+
+#![allow(unsafe_code)]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use egui::TextureId;
+use glow::HasContext as _;
+
+/// Marks the point where the uploads of a shared context are finished, so that the painter's
+/// context can use the textures.
+pub(crate) struct UploadFence(Option<glow::Fence>);
+
+// Sync objects belong to the share group, any context in it may wait for them
+unsafe impl Send for UploadFence {}
+
+impl UploadFence {
+    /// Call with the uploading context current, after the uploads. Uses a sync object where there
+    /// are any (GL 3.2, GLES 3), otherwise waits for the uploads to finish with `glFinish`.
+    pub(crate) fn new(gl: &glow::Context) -> Self {
+        unsafe {
+            if has_sync_objects(gl) {
+                if let Ok(fence) = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0) {
+                    // Other contexts only see the fence once it is flushed
+                    gl.flush();
+                    return Self(Some(fence));
+                }
+            }
+            gl.finish();
+        }
+        Self(None)
+    }
+
+    /// Makes the current context wait for the uploads on the GPU, without blocking the CPU.
+    pub(crate) fn wait(self, gl: &glow::Context) {
+        if let Some(fence) = self.0 {
+            unsafe {
+                // GL_TIMEOUT_IGNORED
+                gl.wait_sync(fence, 0, u64::MAX);
+                gl.delete_sync(fence);
+            }
+        }
+    }
+}
+
+fn has_sync_objects(gl: &glow::Context) -> bool {
+    let version = unsafe { gl.get_parameter_string(glow::VERSION) };
+    // e.g. "OpenGL ES 3.2 Mesa 22.2.0" or "4.6 (Compatibility Profile) Mesa 22.2.0"
+    let es = version.contains(" ES ");
+    let start = match version.find(|c: char| c.is_ascii_digit()) {
+        Some(start) => start,
+        None => return false,
+    };
+    let mut numbers = version[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|n| n.parse::<u32>().unwrap_or(0));
+    let major = numbers.next().unwrap_or(0);
+    let minor = numbers.next().unwrap_or(0);
+    if es {
+        major >= 3
+    } else {
+        (major, minor) >= (3, 2) || gl.supported_extensions().contains("GL_ARB_sync")
+    }
+}
+
+pub(crate) enum Upload {
+    Set(TextureId, glow::Texture, UploadFence),
+    Free(TextureId),
+}
+
+/// Takes textures that were uploaded on other threads, through GL contexts that share
+/// textures with the painter's. The [`Painter`](crate::Painter) picks them up at the start of
+/// the next frame. Get one from [`Painter::upload_queue`](crate::Painter::upload_queue),
+/// cloning it is cheap.
+///
+/// Each upload is fenced, so the painter's context waits for it on the GPU before using the
+/// texture. Without sync objects (e.g. on GLES 2) the uploading thread waits with `glFinish`.
+#[derive(Clone)]
+pub struct UploadQueue {
+    next_id: Arc<AtomicU64>,
+    uploads: Arc<Mutex<Vec<Upload>>>,
+}
+
+impl UploadQueue {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: Arc::new(AtomicU64::new(1 << 32)),
+            uploads: Default::default(),
+        }
+    }
+
+    /// A new id for a native texture, shared with [`Painter::register_native_texture`](crate::Painter::register_native_texture).
+    pub(crate) fn allocate_id(&self) -> TextureId {
+        TextureId::User(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Hands `texture` over to the painter. Call with the uploading context current, once the
+    /// texture is uploaded. The texture can be painted with the returned id from the next frame
+    /// on, the painter owns it from then on and deletes it in [`Self::free`].
+    pub fn register(&self, gl: &glow::Context, texture: glow::Texture) -> TextureId {
+        let id = self.allocate_id();
+        self.replace(gl, id, texture);
+        id
+    }
+
+    /// Paints `texture` for `id` from the next frame on, deleting the texture it replaces. Call
+    /// with the uploading context current, once the texture is uploaded.
+    pub fn replace(&self, gl: &glow::Context, id: TextureId, texture: glow::Texture) {
+        let fence = UploadFence::new(gl);
+        self.uploads
+            .lock()
+            .unwrap()
+            .push(Upload::Set(id, texture, fence));
+    }
+
+    /// Deletes the texture of `id` at the start of the next frame.
+    pub fn free(&self, id: TextureId) {
+        self.uploads.lock().unwrap().push(Upload::Free(id));
+    }
+
+    /// A queue for a painter on a new context after the one of `self` was lost. Uploads through
+    /// `self` come from a context sharing with the lost one, so they stay behind, but ids go on
+    /// from those `self` handed out.
+    pub(crate) fn detached(&self) -> Self {
+        Self {
+            next_id: self.next_id.clone(),
+            uploads: Default::default(),
+        }
+    }
+
+    pub(crate) fn take(&self) -> Vec<Upload> {
+        std::mem::take(&mut *self.uploads.lock().unwrap())
+    }
+}
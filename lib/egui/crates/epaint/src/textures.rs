@@ -12,6 +12,8 @@ pub struct TextureManager {
     /// Information about currently allocated textures.
     metas: ahash::HashMap<TextureId, TextureMeta>,
     delta: TexturesDelta,
+    /// What the renderer last reported with [`Self::set_gpu_usage`].
+    gpu_usage: Option<TextureMemoryUsage>,
 }
 
 impl TextureManager {
@@ -114,6 +116,37 @@ impl TextureManager {
     pub fn num_allocated(&self) -> usize {
         self.metas.len()
     }
+
+    /// For renderers that account for texture memory: report it, to be shown in e.g.
+    /// `egui::Context::texture_ui`.
+    pub fn set_gpu_usage(&mut self, usage: Option<TextureMemoryUsage>) {
+        self.gpu_usage = usage;
+    }
+
+    /// The texture memory the renderer last reported, if it does.
+    pub fn gpu_usage(&self) -> Option<TextureMemoryUsage> {
+        self.gpu_usage
+    }
+}
+
+/// The GPU memory the renderer's textures take, see [`TextureManager::set_gpu_usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextureMemoryUsage {
+    /// Bytes taken by all textures, including user textures of known size.
+    pub bytes_used: usize,
+
+    /// How many bytes the renderer keeps textures within by evicting some, if it does.
+    pub budget: Option<usize>,
+
+    /// Bytes taken by textures that may be evicted.
+    pub bytes_evictable: usize,
+
+    /// How many textures were evicted so far.
+    pub num_evicted: usize,
+
+    /// Bytes of CPU memory taken by copies of the textures, kept by renderers that upload them
+    /// again after losing their context. They don't count towards the budget.
+    pub bytes_copied: usize,
 }
 
 /// Meta-data about an allocated texture.
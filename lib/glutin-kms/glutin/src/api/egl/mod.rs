@@ -36,8 +36,10 @@ use crate::api::dlloader::{SymTrait, SymWrapper};
 use crate::Rect;
 use crate::{
     Api, ContextError, CreationError, GlAttributes, GlRequest, PixelFormat,
-    PixelFormatRequirements, ReleaseBehavior, Robustness,
+    PixelFormatRequirements, PresentationTimestamp, ReleaseBehavior, Robustness,
 };
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 #[derive(Clone)]
 pub struct Egl(pub SymWrapper<ffi::egl::Egl>);
@@ -127,16 +129,34 @@ pub enum NativeDisplay {
     Android,
     // TODO: should be `EGLDeviceEXT`
     Device(ffi::EGLNativeDisplayType),
+    /// `EGL_MESA_platform_surfaceless`, for rendering without any window system.
+    Surfaceless,
     /// Don't specify any display type. Useful on windows. [`None`] means
     /// `EGL_DEFAULT_DISPLAY`.
     Other(Option<ffi::EGLNativeDisplayType>),
 }
 
+/// How many frames to remember the presentation of at most, EGL doesn't keep many either.
+const MAX_PENDING_FRAMES: usize = 16;
+
+/// Swapped frames whose presentation time is not known yet, kept while
+/// `EGL_ANDROID_get_frame_timestamps` is enabled on the surface.
+#[derive(Debug, Default)]
+struct FrameLog {
+    swapped: u64,
+    pending: VecDeque<(u64, ffi::egl::types::EGLuint64KHR)>,
+    latest: Option<PresentationTimestamp>,
+}
+
 #[derive(Debug)]
 pub struct Context {
     display: ffi::egl::types::EGLDisplay,
     context: ffi::egl::types::EGLContext,
     surface: Option<parking_lot::Mutex<ffi::egl::types::EGLSurface>>,
+    config_id: ffi::egl::types::EGLConfig,
+    /// Applied again to surfaces recreated by `on_surface_created`
+    swap_interval: AtomicU32,
+    frame_log: Option<parking_lot::Mutex<FrameLog>>,
     api: Api,
     pixel_format: PixelFormat,
 }
@@ -208,11 +228,10 @@ unsafe fn bind_and_get_api<'a>(
     }
 }
 
-fn get_native_display(native_display: &NativeDisplay) -> *const raw::c_void {
+/// The extensions that can be queried without any display, e.g. the supported platforms.
+fn client_extensions() -> Vec<String> {
     let egl = EGL.as_ref().unwrap();
-    // the first step is to query the list of extensions without any display, if
-    // supported
-    let dp_extensions = unsafe {
+    unsafe {
         let p = egl.QueryString(ffi::egl::NO_DISPLAY, ffi::egl::EXTENSIONS as i32);
 
         // this possibility is available only with EGL 1.5 or
@@ -225,7 +244,45 @@ fn get_native_display(native_display: &NativeDisplay) -> *const raw::c_void {
             let list = String::from_utf8(p.to_bytes().to_vec()).unwrap_or_default();
             list.split(' ').map(|e| e.to_string()).collect::<Vec<_>>()
         }
+    }
+}
+
+/// The devices EGL can render with, including software rasterizers like llvmpipe, to create
+/// displays for with [`NativeDisplay::Device`]. Empty if `EGL_EXT_device_enumeration` isn't
+/// supported.
+#[allow(dead_code)] // Only used on unix
+pub fn devices() -> Vec<ffi::EGLNativeDisplayType> {
+    let egl = match EGL.as_ref() {
+        Some(egl) => egl,
+        None => return vec![],
     };
+    let extensions = client_extensions();
+    let enumeration = extensions
+        .iter()
+        .any(|e| e == "EGL_EXT_device_enumeration" || e == "EGL_EXT_device_base");
+    if !enumeration || !egl.QueryDevicesEXT.is_loaded() {
+        return vec![];
+    }
+
+    unsafe {
+        let mut count = 0;
+        if egl.QueryDevicesEXT(0, std::ptr::null_mut(), &mut count) == 0 || count <= 0 {
+            return vec![];
+        }
+        let mut devices: Vec<ffi::egl::types::EGLDeviceEXT> = Vec::with_capacity(count as usize);
+        if egl.QueryDevicesEXT(count, devices.as_mut_ptr(), &mut count) == 0 {
+            return vec![];
+        }
+        devices.set_len(count.clamp(0, devices.capacity() as i32) as usize);
+        devices.into_iter().map(|device| device as ffi::EGLNativeDisplayType).collect()
+    }
+}
+
+fn get_native_display(native_display: &NativeDisplay) -> *const raw::c_void {
+    let egl = EGL.as_ref().unwrap();
+    // the first step is to query the list of extensions without any display, if
+    // supported
+    let dp_extensions = client_extensions();
 
     let has_dp_extension = |e: &str| dp_extensions.iter().any(|s| s == e);
 
@@ -325,6 +382,42 @@ fn get_native_display(native_display: &NativeDisplay) -> *const raw::c_void {
             )
         },
 
+        NativeDisplay::Device(display)
+            if has_dp_extension("EGL_EXT_platform_device")
+                && egl.GetPlatformDisplayEXT.is_loaded() =>
+        unsafe {
+            egl.GetPlatformDisplayEXT(
+                ffi::egl::PLATFORM_DEVICE_EXT,
+                display as *mut _,
+                std::ptr::null(),
+            )
+        },
+
+        NativeDisplay::Surfaceless
+            if has_dp_extension("EGL_MESA_platform_surfaceless")
+                && egl.GetPlatformDisplay.is_loaded() =>
+        unsafe {
+            egl.GetPlatformDisplay(
+                ffi::egl::PLATFORM_SURFACELESS_MESA,
+                ffi::egl::DEFAULT_DISPLAY as *mut _,
+                std::ptr::null(),
+            )
+        },
+
+        NativeDisplay::Surfaceless
+            if has_dp_extension("EGL_MESA_platform_surfaceless")
+                && egl.GetPlatformDisplayEXT.is_loaded() =>
+        unsafe {
+            egl.GetPlatformDisplayEXT(
+                ffi::egl::PLATFORM_SURFACELESS_MESA,
+                ffi::egl::DEFAULT_DISPLAY as *mut _,
+                std::ptr::null(),
+            )
+        },
+
+        // Falling back to the default display would pick up a window system after all
+        NativeDisplay::Surfaceless => std::ptr::null(),
+
         NativeDisplay::X11(Some(display))
         | NativeDisplay::Gbm(Some(display))
         | NativeDisplay::Wayland(Some(display))
@@ -501,6 +594,7 @@ impl Context {
             return Err(ContextError::ContextLost);
         }
 
+        let frame_id = self.next_frame_id(*surface);
         let ret = unsafe { egl.SwapBuffers(self.display, *surface) };
 
         if ret == 0 {
@@ -511,6 +605,7 @@ impl Context {
                 }
             }
         } else {
+            self.frame_swapped(frame_id);
             Ok(())
         }
     }
@@ -538,6 +633,7 @@ impl Context {
             ffirects.push(rect.height as ffi::egl::types::EGLint);
         }
 
+        let frame_id = self.next_frame_id(*surface);
         let ret = unsafe {
             egl.SwapBuffersWithDamageKHR(
                 self.display,
@@ -555,10 +651,98 @@ impl Context {
                 }
             }
         } else {
+            self.frame_swapped(frame_id);
             Ok(())
         }
     }
 
+    /// The id EGL gives the frame about to be swapped, if frame timestamps are enabled.
+    fn next_frame_id(
+        &self,
+        surface: ffi::egl::types::EGLSurface,
+    ) -> Option<ffi::egl::types::EGLuint64KHR> {
+        self.frame_log.as_ref()?;
+        let egl = EGL.as_ref().unwrap();
+        let mut frame_id = 0;
+        match unsafe { egl.GetNextFrameIdANDROID(self.display, surface, &mut frame_id) } {
+            ffi::egl::FALSE => None,
+            _ => Some(frame_id),
+        }
+    }
+
+    fn frame_swapped(&self, frame_id: Option<ffi::egl::types::EGLuint64KHR>) {
+        if let Some(ref frame_log) = self.frame_log {
+            let mut frame_log = frame_log.lock();
+            frame_log.swapped += 1;
+            if let Some(frame_id) = frame_id {
+                let frame = frame_log.swapped;
+                frame_log.pending.push_back((frame, frame_id));
+                if frame_log.pending.len() > MAX_PENDING_FRAMES {
+                    frame_log.pending.pop_front();
+                }
+            }
+        }
+    }
+
+    /// Returns when the most recent frame that made it to the screen did, if the platform has
+    /// `EGL_ANDROID_get_frame_timestamps`. Frames are counted from the first swap.
+    pub fn presentation_timestamp(&self) -> Option<PresentationTimestamp> {
+        let frame_log = self.frame_log.as_ref()?;
+        let surface = *self.surface.as_ref()?.lock();
+        let mut frame_log = frame_log.lock();
+        if surface == ffi::egl::NO_SURFACE {
+            return frame_log.latest;
+        }
+
+        let egl = EGL.as_ref().unwrap();
+        let names = [ffi::egl::DISPLAY_PRESENT_TIME_ANDROID as ffi::egl::types::EGLint];
+        while let Some(&(frame, frame_id)) = frame_log.pending.front() {
+            let mut value: ffi::egl::types::EGLnsecsANDROID = 0;
+            let ret = unsafe {
+                egl.GetFrameTimestampsANDROID(
+                    self.display,
+                    surface,
+                    frame_id,
+                    1,
+                    names.as_ptr(),
+                    &mut value,
+                )
+            };
+            // EGL forgot about the frame, or it was never shown
+            if ret == ffi::egl::FALSE
+                || value == ffi::egl::TIMESTAMP_INVALID_ANDROID as ffi::egl::types::EGLnsecsANDROID
+            {
+                frame_log.pending.pop_front();
+                continue;
+            }
+            if value == ffi::egl::TIMESTAMP_PENDING_ANDROID as ffi::egl::types::EGLnsecsANDROID {
+                break;
+            }
+
+            frame_log.latest = Some(PresentationTimestamp {
+                frame,
+                presented: std::time::Duration::from_nanos(value as u64),
+            });
+            frame_log.pending.pop_front();
+        }
+        frame_log.latest
+    }
+
+    /// Makes buffer swaps wait for `interval` refreshes of the screen, the context has to be
+    /// current.
+    pub fn set_swap_interval(&self, interval: u32) -> Result<(), ContextError> {
+        let egl = EGL.as_ref().unwrap();
+        let ret = unsafe { egl.SwapInterval(self.display, interval as ffi::egl::types::EGLint) };
+        if ret == ffi::egl::FALSE {
+            return Err(ContextError::OsError(format!(
+                "eglSwapInterval failed with 0x{:x}",
+                unsafe { egl.GetError() }
+            )));
+        }
+        self.swap_interval.store(interval, Ordering::Relaxed);
+        Ok(())
+    }
+
     #[inline]
     #[cfg(not(target_os = "windows"))]
     pub fn swap_buffers_with_damage_supported(&self) -> bool {
@@ -571,6 +755,72 @@ impl Context {
         self.pixel_format.clone()
     }
 
+    /// Destroys the window surface, the context stays alive. Drawing fails with
+    /// [`ContextError::ContextLost`] until [`Context::on_surface_created`] is called.
+    ///
+    /// The surface is kept if it can't be released, calling this again tries again.
+    pub unsafe fn on_surface_destroyed(&self) -> Result<(), ContextError> {
+        let egl = EGL.as_ref().unwrap();
+        let mut surface = self.surface.as_ref().unwrap().lock();
+        if *surface == ffi::egl::NO_SURFACE {
+            return Ok(());
+        }
+
+        let ret = egl.MakeCurrent(
+            self.display,
+            ffi::egl::NO_SURFACE,
+            ffi::egl::NO_SURFACE,
+            ffi::egl::NO_CONTEXT,
+        );
+        if ret == 0 {
+            return Err(ContextError::OsError(format!(
+                "eglMakeCurrent failed with 0x{:x}",
+                egl.GetError()
+            )));
+        }
+
+        egl.DestroySurface(self.display, *surface);
+        *surface = ffi::egl::NO_SURFACE;
+        Ok(())
+    }
+
+    /// Creates a new window surface for `nwin` after [`Context::on_surface_destroyed`] and makes
+    /// the context current with it.
+    pub unsafe fn on_surface_created(
+        &self,
+        nwin: ffi::EGLNativeWindowType,
+    ) -> Result<(), ContextError> {
+        let egl = EGL.as_ref().unwrap();
+        let mut surface = self.surface.as_ref().unwrap().lock();
+        if *surface != ffi::egl::NO_SURFACE {
+            return Ok(());
+        }
+
+        let new_surface =
+            egl.CreateWindowSurface(self.display, self.config_id, nwin, std::ptr::null());
+        if new_surface.is_null() {
+            return Err(ContextError::OsError(format!(
+                "eglCreateWindowSurface failed with 0x{:x}",
+                egl.GetError()
+            )));
+        }
+        *surface = new_surface;
+
+        let ret = egl.MakeCurrent(self.display, *surface, *surface, self.context);
+        if let Some(ref frame_log) = self.frame_log {
+            enable_frame_timestamps(self.display, *surface);
+            frame_log.lock().pending.clear();
+        }
+        drop(surface);
+        self.check_make_current(Some(ret))?;
+
+        // The interval belongs to the surface, a new one starts out with the default of 1
+        match self.swap_interval.load(Ordering::Relaxed) {
+            1 => Ok(()),
+            interval => self.set_swap_interval(interval),
+        }
+    }
+
     #[inline]
     pub fn buffer_age(&self) -> u32 {
         let egl = EGL.as_ref().unwrap();
@@ -605,17 +855,19 @@ impl Drop for Context {
             let surface = self.surface.as_ref().map(|s| *s.lock()).unwrap_or(ffi::egl::NO_SURFACE);
             // Ok, so we got to call `glFinish` before destroying the context
             // to ensure it actually gets destroyed. This requires making the
-            // this context current.
-            let mut guard = MakeCurrentGuard::new(self.display, surface, surface, self.context)
-                .map_err(ContextError::OsError)
-                .unwrap();
-
-            guard.if_any_same_then_invalidate(surface, surface, self.context);
-
-            let gl_finish_fn = self.get_proc_address("glFinish");
-            assert!(!gl_finish_fn.is_null());
-            let gl_finish_fn = std::mem::transmute::<_, extern "system" fn()>(gl_finish_fn);
-            gl_finish_fn();
+            // this context current. A lost context can't be made current, there
+            // is nothing left to finish then.
+            let mut guard =
+                MakeCurrentGuard::new(self.display, surface, surface, self.context).ok();
+
+            if let Some(ref mut guard) = guard {
+                guard.if_any_same_then_invalidate(surface, surface, self.context);
+
+                let gl_finish_fn = self.get_proc_address("glFinish");
+                assert!(!gl_finish_fn.is_null());
+                let gl_finish_fn = std::mem::transmute::<_, extern "system" fn()>(gl_finish_fn);
+                gl_finish_fn();
+            }
 
             egl.DestroyContext(self.display, self.context);
             self.context = ffi::egl::NO_CONTEXT;
@@ -891,31 +1143,56 @@ impl<'a> ContextPrototype<'a> {
             }
         };
 
+        let swap_interval = if self.opengl.vsync { self.opengl.swap_interval.max(1) } else { 0 };
+        let mut frame_timestamps = false;
         if let Some(surface) = surface {
-            // VSync defaults to enabled; disable it if it was not requested.
-            if !self.opengl.vsync {
+            // EGL defaults to an interval of 1, i.e. vsync
+            if swap_interval != 1 {
                 let _guard = MakeCurrentGuard::new(self.display, surface, surface, context)
                     .map_err(CreationError::OsError)?;
 
                 let egl = EGL.as_ref().unwrap();
                 unsafe {
-                    if egl.SwapInterval(self.display, 0) == ffi::egl::FALSE {
+                    if egl.SwapInterval(self.display, swap_interval as ffi::egl::types::EGLint)
+                        == ffi::egl::FALSE
+                    {
                         panic!("finish_impl: eglSwapInterval failed: 0x{:x}", egl.GetError());
                     }
                 }
             }
+
+            if self.extensions.iter().any(|s| s == "EGL_ANDROID_get_frame_timestamps") {
+                frame_timestamps = unsafe { enable_frame_timestamps(self.display, surface) };
+            }
         }
 
         Ok(Context {
             display: self.display,
             context,
             surface: surface.map(parking_lot::Mutex::new),
+            config_id: self.config_id,
+            swap_interval: AtomicU32::new(if surface.is_some() { swap_interval } else { 1 }),
+            frame_log: frame_timestamps.then(|| parking_lot::Mutex::new(FrameLog::default())),
             api: self.api,
             pixel_format: self.pixel_format,
         })
     }
 }
 
+/// Asks EGL to record when the frames of `surface` are presented.
+unsafe fn enable_frame_timestamps(
+    display: ffi::egl::types::EGLDisplay,
+    surface: ffi::egl::types::EGLSurface,
+) -> bool {
+    let egl = EGL.as_ref().unwrap();
+    egl.SurfaceAttrib(
+        display,
+        surface,
+        ffi::egl::TIMESTAMPS_ANDROID as ffi::egl::types::EGLint,
+        ffi::egl::TRUE as ffi::egl::types::EGLint,
+    ) != ffi::egl::FALSE
+}
+
 unsafe fn choose_fbconfig<F>(
     display: ffi::egl::types::EGLDisplay,
     egl_version: &(ffi::egl::types::EGLint, ffi::egl::types::EGLint),
@@ -1079,7 +1356,8 @@ where
         return Err(CreationError::OsError("eglChooseConfig failed".to_string()));
     }
 
-    // We're interested in those configs which allow our desired VSync.
+    // We're interested in those configs which allow our desired VSync. Longer intervals are
+    // clamped to the maximum by EGL, so any config with vsync will do for them.
     let desired_swap_interval = if opengl.vsync { 1 } else { 0 };
 
     let config_ids = config_ids
@@ -151,6 +151,18 @@ impl<'a, T: ContextCurrentState> ContextBuilder<'a, T> {
     #[inline]
     pub fn with_vsync(mut self, vsync: bool) -> Self {
         self.gl_attr.vsync = vsync;
+        self.gl_attr.swap_interval = vsync as u32;
+        self
+    }
+
+    /// Requests that buffer swaps wait for `interval` refreshes of the screen, `0` doesn't wait
+    /// at all. `with_vsync(true)` is the same as an interval of `1`.
+    ///
+    /// Intervals above `1` are only honoured by EGL, the other APIs treat them like `1`.
+    #[inline]
+    pub fn with_swap_interval(mut self, interval: u32) -> Self {
+        self.gl_attr.vsync = interval > 0;
+        self.gl_attr.swap_interval = interval;
         self
     }
 
@@ -607,6 +619,11 @@ pub struct GlAttributes<S> {
     ///
     /// The default is [`false`].
     pub vsync: bool,
+
+    /// How many refreshes of the screen a buffer swap waits for, `0` if `vsync` is off.
+    ///
+    /// The default is `0`.
+    pub swap_interval: u32,
 }
 
 impl<S> GlAttributes<S> {
@@ -623,6 +640,7 @@ impl<S> GlAttributes<S> {
             debug: self.debug,
             robustness: self.robustness,
             vsync: self.vsync,
+            swap_interval: self.swap_interval,
         }
     }
 
@@ -636,6 +654,7 @@ impl<S> GlAttributes<S> {
             debug: self.debug,
             robustness: self.robustness,
             vsync: self.vsync,
+            swap_interval: self.swap_interval,
         }
     }
 }
@@ -650,10 +669,20 @@ impl<S> Default for GlAttributes<S> {
             debug: cfg!(debug_assertions),
             robustness: Robustness::NotRobust,
             vsync: false,
+            swap_interval: 0,
         }
     }
 }
 
+/// When a frame showed up on the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PresentationTimestamp {
+    /// Counts the buffer swaps of the context, the first one is `1`.
+    pub frame: u64,
+    /// When the frame was presented, on `CLOCK_MONOTONIC` on unix.
+    pub presented: std::time::Duration,
+}
+
 // Rectangles to submit as buffer damage.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Rect {
@@ -7,8 +7,8 @@
 ))]
 
 use crate::platform::ContextTraitExt;
-pub use crate::platform_impl::{HeadlessContextExt, RawContextExt, RawHandle};
-use crate::{Context, ContextCurrentState};
+pub use crate::platform_impl::{monotonic_now, HeadlessContextExt, RawContextExt, RawHandle};
+use crate::{Context, ContextCurrentState, ContextError, PresentationTimestamp, WindowedContext};
 pub use glutin_egl_sys::EGLContext;
 #[cfg(feature = "x11")]
 pub use glutin_glx_sys::GLXContext;
@@ -30,3 +30,61 @@ impl<T: ContextCurrentState> ContextTraitExt for Context<T> {
         self.context.get_egl_display()
     }
 }
+
+/// Additional methods on [`WindowedContext`] that are specific to unix.
+pub trait WindowedContextExtUnix {
+    /// Destroys the window surface while the context keeps its GL objects, e.g. on
+    /// `Event::Suspended`. Drawing fails with [`ContextError::ContextLost`] until
+    /// [`resume`](Self::resume) is called.
+    ///
+    /// fbdev contexts also do this by themselves when the display is blanked or their VT goes to
+    /// the background. Does nothing for other contexts.
+    fn suspend(&self);
+
+    /// Recreates the window surface after [`suspend`](Self::suspend) or a
+    /// [`ContextError::ContextLost`] and makes the context current.
+    ///
+    /// After a context loss the whole context is created again and `true` is returned. All GL
+    /// objects are gone then and have to be recreated, and the new context doesn't share with
+    /// any other. GL objects survive a suspend.
+    ///
+    /// Fails with [`ContextError::ContextLost`] while the display is still unavailable, and for
+    /// good on a kms context whose monitor was unplugged.
+    fn resume(&self) -> Result<bool, ContextError>;
+
+    /// Makes buffer swaps wait for `interval` refreshes of the screen, `0` doesn't wait. The
+    /// context has to be current.
+    ///
+    /// Only supported by kms and fbdev contexts, fails with
+    /// [`ContextError::FunctionUnavailable`] for others.
+    fn set_swap_interval(&self, interval: u32) -> Result<(), ContextError>;
+
+    /// Returns when the most recent frame that made it to the screen did, compare it with
+    /// [`monotonic_now`].
+    ///
+    /// kms contexts know this from page flip events, fbdev contexts only if the driver has
+    /// `EGL_ANDROID_get_frame_timestamps`. `None` for other contexts.
+    fn presentation_timestamp(&self) -> Option<PresentationTimestamp>;
+}
+
+impl<T: ContextCurrentState> WindowedContextExtUnix for WindowedContext<T> {
+    #[inline]
+    fn suspend(&self) {
+        self.context.context.suspend()
+    }
+
+    #[inline]
+    fn resume(&self) -> Result<bool, ContextError> {
+        self.context.context.resume()
+    }
+
+    #[inline]
+    fn set_swap_interval(&self, interval: u32) -> Result<(), ContextError> {
+        self.context.context.set_swap_interval(interval)
+    }
+
+    #[inline]
+    fn presentation_timestamp(&self) -> Option<PresentationTimestamp> {
+        self.context.context.presentation_timestamp()
+    }
+}
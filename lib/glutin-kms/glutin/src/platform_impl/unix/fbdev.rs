@@ -1,28 +1,169 @@
 #![cfg(feature = "kms")]
 
 use std::ffi::c_void;
-use crate::api::egl::{Context as EglContext, NativeDisplay, SurfaceType as EglSurfaceType};
+use crate::api::egl::{Context as EglContext, NativeDisplay, SurfaceType as EglSurfaceType, EGL};
 use crate::CreationError::{self, OsError};
 use crate::{Api, ContextError, GlAttributes, PixelFormat, PixelFormatRequirements, Rect};
 
 use glutin_egl_sys as ffi;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 // use raw_window_handle::{AndroidNdkWindowHandle, HasRawWindowHandle, RawWindowHandle};
 use winit::dpi;
 use winit::event_loop::EventLoopWindowTarget;
+use winit::platform::unix::{FbDisplayState, FbPixelFormat, WindowExtUnix};
 use winit::window::WindowBuilder;
 
 use std::sync::Arc;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Running,
+    /// The window surface is gone, GL objects survive
+    Suspended,
+    /// The context is gone with all GL objects, it is created again on resume
+    Lost,
+}
+
+/// What it takes to create the context of a window again after it was lost
+#[derive(Debug)]
+struct Recipe {
+    pf_reqs: PixelFormatRequirements,
+    gl_attr: GlAttributes<()>,
+    pixel_format: Option<FbPixelFormat>,
+}
+
 #[derive(Debug)]
 struct FbDevContext {
-    egl_context: EglContext,
-    stopped: Option<Mutex<bool>>,
+    egl_context: RwLock<EglContext>,
+    /// `None` for headless contexts
+    state: Option<Mutex<State>>,
+    recipe: Option<Recipe>,
+    display_state: Option<FbDisplayState>,
 }
 
 #[derive(Debug)]
 pub struct Context(Arc<FbDevContext>);
 
+/// The channel sizes of an EGL config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ConfigFormat {
+    red: u32,
+    green: u32,
+    blue: u32,
+    alpha: u32,
+    bits: u32,
+}
+
+/// Picks the candidate whose colour channels match the framebuffer exactly, preferring one that
+/// also matches its alpha channel and bits per pixel. Returns its index and whether it matches
+/// exactly.
+fn best_config(candidates: &[ConfigFormat], format: FbPixelFormat) -> Option<(usize, bool)> {
+    let mut best: Option<(usize, (bool, bool))> = None;
+    for (i, candidate) in candidates.iter().enumerate() {
+        if (candidate.red, candidate.green, candidate.blue)
+            != (format.red_bits, format.green_bits, format.blue_bits)
+        {
+            continue;
+        }
+
+        let rank = (candidate.alpha == format.alpha_bits, candidate.bits == format.bits_per_pixel);
+        if best.map_or(true, |(_, best_rank)| rank > best_rank) {
+            best = Some((i, rank));
+        }
+    }
+    best.map(|(i, rank)| (i, rank == (true, true)))
+}
+
+/// Picks the config that matches the framebuffer best, see [`best_config`]. Sets `error` if
+/// none matches.
+fn choose_config(
+    configs: Vec<ffi::egl::types::EGLConfig>,
+    display: ffi::egl::types::EGLDisplay,
+    format: FbPixelFormat,
+    error: &mut Option<String>,
+) -> Result<ffi::egl::types::EGLConfig, ()> {
+    let egl = EGL.as_ref().unwrap();
+    let attrib = |config, name| {
+        let mut value = 0;
+        unsafe {
+            egl.GetConfigAttrib(display, config, name as ffi::egl::types::EGLint, &mut value);
+        }
+        value as u32
+    };
+
+    let candidates: Vec<ConfigFormat> = configs
+        .iter()
+        .map(|&config| ConfigFormat {
+            red: attrib(config, ffi::egl::RED_SIZE),
+            green: attrib(config, ffi::egl::GREEN_SIZE),
+            blue: attrib(config, ffi::egl::BLUE_SIZE),
+            alpha: attrib(config, ffi::egl::ALPHA_SIZE),
+            bits: attrib(config, ffi::egl::BUFFER_SIZE),
+        })
+        .collect();
+    log::debug!("EGL configs for the framebuffer ({:?}): {:?}", format, candidates);
+
+    match best_config(&candidates, format) {
+        Some((i, exact)) => {
+            if !exact {
+                log::warn!(
+                    "No EGL config matches the framebuffer ({:?}) exactly, drawing may be slower",
+                    format
+                );
+            }
+            Ok(configs[i])
+        }
+        None => {
+            *error = Some(format!(
+                "No EGL config matches the pixel format of the framebuffer: {} bpp, R{}G{}B{}A{}",
+                format.bits_per_pixel,
+                format.red_bits,
+                format.green_bits,
+                format.blue_bits,
+                format.alpha_bits
+            ));
+            Err(())
+        }
+    }
+}
+
+/// Creates a context drawing to the framebuffer, picking a config for its `pixel_format`.
+fn create_window_context(
+    pf_reqs: &PixelFormatRequirements,
+    gl_attr: &GlAttributes<&EglContext>,
+    pixel_format: Option<FbPixelFormat>,
+) -> Result<EglContext, CreationError> {
+    /*let nwin =
+    if let RawWindowHandle::AndroidNdk(AndroidNdkWindowHandle { a_native_window, .. }) =
+    win.raw_window_handle()
+    {
+        a_native_window
+    } else {
+        return Err(OsError("raw_window_handle() is not for Android".to_string()));
+    };*/
+    let native_display = NativeDisplay::Android;
+    // eglChooseConfig takes the colour sizes as minimums, so e.g. the default 24 bit colour and
+    // 8 bit alpha would rule out every config for an RGB565 framebuffer. `choose_config` matches
+    // the framebuffer exactly instead.
+    let mut pf_reqs = pf_reqs.clone();
+    if pixel_format.is_some() {
+        pf_reqs.color_bits = None;
+        pf_reqs.alpha_bits = None;
+    }
+    let mut config_error = None;
+    EglContext::new(&pf_reqs, gl_attr, native_display, EglSurfaceType::Window, |c, d| {
+        match pixel_format {
+            Some(format) => choose_config(c, d, format, &mut config_error),
+            None => Ok(c[0]),
+        }
+    })
+    .map_err(|e| match (e, config_error.take()) {
+        (CreationError::NoAvailablePixelFormat, Some(error)) => CreationError::NotSupported(error),
+        (e, _) => e,
+    })
+    .and_then(|p| p.finish(0 as *const c_void)) // TODO The EGLNativeWindowType represents the fbdev index. For now, just use 0 always.
+}
+
 impl Context {
     #[inline]
     pub fn new_windowed<T>(
@@ -32,22 +173,24 @@ impl Context {
         gl_attr: &GlAttributes<&Self>,
     ) -> Result<(winit::window::Window, Self), CreationError> {
         let win = wb.build(el)?;
-        let gl_attr = gl_attr.clone().map_sharing(|c| &c.0.egl_context);
-        /*let nwin =
-            if let RawWindowHandle::AndroidNdk(AndroidNdkWindowHandle { a_native_window, .. }) =
-            win.raw_window_handle()
-            {
-                a_native_window
-            } else {
-                return Err(OsError("raw_window_handle() is not for Android".to_string()));
-            };*/
-        let native_display = NativeDisplay::Android;
-        let egl_context =
-            EglContext::new(pf_reqs, &gl_attr, native_display, EglSurfaceType::Window, |c, _| {
-                Ok(c[0])
-            })
-                .and_then(|p| p.finish(0 as *const c_void))?; // TODO The EGLNativeWindowType represents the fbdev index. For now, just use 0 always.
-        let ctx = Arc::new(FbDevContext { egl_context, stopped: Some(Mutex::new(false)) });
+        let sharing = gl_attr.sharing.map(|c| c.0.egl_context.read());
+        let pixel_format = win.fbdev_pixel_format();
+        let egl_context = create_window_context(
+            pf_reqs,
+            &gl_attr.clone().set_sharing(sharing.as_deref()),
+            pixel_format,
+        )?;
+        drop(sharing);
+        let ctx = Arc::new(FbDevContext {
+            egl_context: RwLock::new(egl_context),
+            state: Some(Mutex::new(State::Running)),
+            recipe: Some(Recipe {
+                pf_reqs: pf_reqs.clone(),
+                gl_attr: gl_attr.clone().set_sharing(None),
+                pixel_format,
+            }),
+            display_state: win.fbdev_display_state(),
+        });
 
         let context = Context(ctx);
 
@@ -59,43 +202,121 @@ impl Context {
         _el: &EventLoopWindowTarget<T>,
         pf_reqs: &PixelFormatRequirements,
         gl_attr: &GlAttributes<&Context>,
-        size: dpi::PhysicalSize<u32>,
+        size: Option<dpi::PhysicalSize<u32>>,
     ) -> Result<Self, CreationError> {
-        let gl_attr = gl_attr.clone().map_sharing(|c| &c.0.egl_context);
+        let sharing = gl_attr.sharing.map(|c| c.0.egl_context.read());
+        let gl_attr = gl_attr.clone().set_sharing(sharing.as_deref());
+        let surface_type =
+            if size.is_some() { EglSurfaceType::PBuffer } else { EglSurfaceType::Surfaceless };
         let context = EglContext::new(
             pf_reqs,
             &gl_attr,
             NativeDisplay::Android,
-            EglSurfaceType::PBuffer,
+            surface_type,
             |c, _| Ok(c[0]),
         )?;
-        let egl_context = context.finish_pbuffer(size)?;
-        let ctx = Arc::new(FbDevContext { egl_context, stopped: None });
+        let egl_context = match size {
+            Some(size) => context.finish_pbuffer(size)?,
+            None => context.finish_surfaceless()?,
+        };
+        let ctx = Arc::new(FbDevContext {
+            egl_context: RwLock::new(egl_context),
+            state: None,
+            recipe: None,
+            display_state: None,
+        });
         Ok(Context(ctx))
     }
 
-    #[inline]
-    pub unsafe fn make_current(&self) -> Result<(), ContextError> {
-        if let Some(ref stopped) = self.0.stopped {
-            let stopped = stopped.lock();
-            if *stopped {
-                return Err(ContextError::ContextLost);
-            }
+    /// Fails with `ContextLost` while the context is stopped, stopping it first if the display
+    /// went away since the last call.
+    fn check_stopped(&self) -> Result<(), ContextError> {
+        let state = match self.0.state {
+            Some(ref state) => state,
+            None => return Ok(()),
+        };
+        if self.0.display_state.as_ref().map_or(false, |state| !state.is_active()) {
+            self.suspend();
+        }
+        if *state.lock() != State::Running {
+            return Err(ContextError::ContextLost);
         }
+        Ok(())
+    }
 
-        self.0.egl_context.make_current()
+    fn mark_lost(&self, result: Result<(), ContextError>) -> Result<(), ContextError> {
+        if let (Err(ContextError::ContextLost), Some(state)) = (&result, &self.0.state) {
+            log::warn!("EGL context lost, drawing stops until the context is resumed");
+            *state.lock() = State::Lost;
+        }
+        result
     }
 
-    #[inline]
-    pub unsafe fn make_not_current(&self) -> Result<(), ContextError> {
-        if let Some(ref stopped) = self.0.stopped {
-            let stopped = stopped.lock();
-            if *stopped {
-                return Err(ContextError::ContextLost);
+    /// Destroys the window surface, e.g. when the display is blanked or our VT goes to the
+    /// background. Drawing fails with `ContextLost` until [`Context::resume`] is called.
+    pub fn suspend(&self) {
+        if let Some(ref state) = self.0.state {
+            let mut state = state.lock();
+            if *state == State::Running {
+                // `resume` tries again to release it
+                if let Err(e) = unsafe { self.0.egl_context.read().on_surface_destroyed() } {
+                    log::warn!("Failed to destroy the window surface: {}", e);
+                }
+                *state = State::Suspended;
             }
         }
+    }
 
-        self.0.egl_context.make_not_current()
+    /// Recreates the window surface after [`Context::suspend`] and makes the context current.
+    /// After a loss of the context the whole context is created again, without sharing, and
+    /// `true` is returned, GL objects survive a suspend but not a loss of the context.
+    pub fn resume(&self) -> Result<bool, ContextError> {
+        let (state, recipe) = match (&self.0.state, &self.0.recipe) {
+            (Some(state), Some(recipe)) => (state, recipe),
+            _ => return Ok(false),
+        };
+        let mut state = state.lock();
+        if *state == State::Running {
+            return Ok(false);
+        }
+        if self.0.display_state.as_ref().map_or(false, |state| !state.is_active()) {
+            return Err(ContextError::ContextLost);
+        }
+
+        let recreated = *state == State::Lost;
+        unsafe {
+            // The framebuffer only takes one window surface at a time
+            self.0.egl_context.read().on_surface_destroyed()?;
+            if recreated {
+                let egl_context = create_window_context(
+                    &recipe.pf_reqs,
+                    &recipe.gl_attr.clone().set_sharing(None),
+                    recipe.pixel_format,
+                )
+                .map_err(|e| {
+                    ContextError::OsError(format!("Failed to recreate the lost context: {}", e))
+                })?;
+                *self.0.egl_context.write() = egl_context;
+                self.0.egl_context.read().make_current()?;
+            } else {
+                self.0.egl_context.read().on_surface_created(0 as *const c_void)?;
+            }
+        }
+        *state = State::Running;
+        Ok(recreated)
+    }
+
+    #[inline]
+    pub unsafe fn make_current(&self) -> Result<(), ContextError> {
+        self.check_stopped()?;
+        let result = self.0.egl_context.read().make_current();
+        self.mark_lost(result)
+    }
+
+    #[inline]
+    pub unsafe fn make_not_current(&self) -> Result<(), ContextError> {
+        self.check_stopped()?;
+        self.0.egl_context.read().make_not_current()
     }
 
     #[inline]
@@ -103,63 +324,106 @@ impl Context {
 
     #[inline]
     pub fn is_current(&self) -> bool {
-        self.0.egl_context.is_current()
+        self.0.egl_context.read().is_current()
     }
 
     #[inline]
     pub fn get_proc_address(&self, addr: &str) -> *const core::ffi::c_void {
-        self.0.egl_context.get_proc_address(addr)
+        self.0.egl_context.read().get_proc_address(addr)
     }
 
     #[inline]
     pub fn buffer_age(&self) -> u32 {
-        self.0.egl_context.buffer_age()
+        self.0.egl_context.read().buffer_age()
     }
 
     #[inline]
     pub fn swap_buffers(&self) -> Result<(), ContextError> {
-        if let Some(ref stopped) = self.0.stopped {
-            let stopped = stopped.lock();
-            if *stopped {
-                return Err(ContextError::ContextLost);
-            }
-        }
-        self.0.egl_context.swap_buffers()
+        self.check_stopped()?;
+        let result = self.0.egl_context.read().swap_buffers();
+        self.mark_lost(result)
     }
 
     #[inline]
     pub fn swap_buffers_with_damage(&self, rects: &[Rect]) -> Result<(), ContextError> {
-        if let Some(ref stopped) = self.0.stopped {
-            let stopped = stopped.lock();
-            if *stopped {
-                return Err(ContextError::ContextLost);
-            }
-        }
-        self.0.egl_context.swap_buffers_with_damage(rects)
+        self.check_stopped()?;
+        let result = self.0.egl_context.read().swap_buffers_with_damage(rects);
+        self.mark_lost(result)
+    }
+
+    #[inline]
+    pub fn set_swap_interval(&self, interval: u32) -> Result<(), ContextError> {
+        self.check_stopped()?;
+        self.0.egl_context.read().set_swap_interval(interval)
+    }
+
+    #[inline]
+    pub fn presentation_timestamp(&self) -> Option<crate::PresentationTimestamp> {
+        self.0.egl_context.read().presentation_timestamp()
     }
 
     #[inline]
     pub fn swap_buffers_with_damage_supported(&self) -> bool {
-        self.0.egl_context.swap_buffers_with_damage_supported()
+        self.0.egl_context.read().swap_buffers_with_damage_supported()
     }
 
     #[inline]
     pub fn get_api(&self) -> Api {
-        self.0.egl_context.get_api()
+        self.0.egl_context.read().get_api()
     }
 
     #[inline]
     pub fn get_pixel_format(&self) -> PixelFormat {
-        self.0.egl_context.get_pixel_format()
+        self.0.egl_context.read().get_pixel_format()
     }
 
     #[inline]
     pub unsafe fn raw_handle(&self) -> ffi::EGLContext {
-        self.0.egl_context.raw_handle()
+        self.0.egl_context.read().raw_handle()
     }
 
     #[inline]
     pub unsafe fn get_egl_display(&self) -> ffi::EGLDisplay {
-        self.0.egl_context.get_egl_display()
+        self.0.egl_context.read().get_egl_display()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_config, ConfigFormat};
+    use winit::platform::unix::FbPixelFormat;
+
+    const XRGB8888: ConfigFormat = ConfigFormat { red: 8, green: 8, blue: 8, alpha: 0, bits: 32 };
+    const ARGB8888: ConfigFormat = ConfigFormat { red: 8, green: 8, blue: 8, alpha: 8, bits: 32 };
+    const RGB565: ConfigFormat = ConfigFormat { red: 5, green: 6, blue: 5, alpha: 0, bits: 16 };
+
+    fn framebuffer(config: ConfigFormat) -> FbPixelFormat {
+        FbPixelFormat {
+            bits_per_pixel: config.bits,
+            red_bits: config.red,
+            green_bits: config.green,
+            blue_bits: config.blue,
+            alpha_bits: config.alpha,
+        }
+    }
+
+    #[test]
+    fn picks_the_exact_match() {
+        let candidates = [ARGB8888, XRGB8888, RGB565];
+        assert_eq!(best_config(&candidates, framebuffer(RGB565)), Some((2, true)));
+        assert_eq!(best_config(&candidates, framebuffer(XRGB8888)), Some((1, true)));
+        assert_eq!(best_config(&candidates, framebuffer(ARGB8888)), Some((0, true)));
+    }
+
+    #[test]
+    fn falls_back_to_matching_colours() {
+        // An XRGB8888 framebuffer drawn to through a config with alpha
+        assert_eq!(best_config(&[RGB565, ARGB8888], framebuffer(XRGB8888)), Some((1, false)));
+    }
+
+    #[test]
+    fn rejects_other_colour_depths() {
+        assert_eq!(best_config(&[ARGB8888, XRGB8888], framebuffer(RGB565)), None);
+        assert_eq!(best_config(&[RGB565], framebuffer(XRGB8888)), None);
     }
 }
@@ -0,0 +1,89 @@
+use crate::api::egl::{self, Context as EglContext, NativeDisplay, SurfaceType as EglSurfaceType};
+use crate::{Api, ContextError, CreationError, GlAttributes, PixelFormat, PixelFormatRequirements};
+
+use glutin_egl_sys as ffi;
+use winit::dpi;
+
+/// An EGL context that needs neither a window system, an event loop nor a GPU, e.g. for
+/// rendering tests. Tries `EGL_MESA_platform_surfaceless` first, then every device from
+/// `EGL_EXT_platform_device` (which includes llvmpipe on Mesa), then the default display.
+#[derive(Debug)]
+pub struct Context {
+    egl_context: EglContext,
+}
+
+impl Context {
+    /// Renders into a pbuffer of `size`, or into framebuffer objects only if `size` is `None`.
+    pub fn new(
+        pf_reqs: &PixelFormatRequirements,
+        gl_attr: &GlAttributes<&Context>,
+        size: Option<dpi::PhysicalSize<u32>>,
+    ) -> Result<Self, CreationError> {
+        if egl::EGL.is_none() {
+            return Err(CreationError::NotSupported("libEGL could not be loaded".to_string()));
+        }
+        let gl_attr = gl_attr.clone().map_sharing(|c| &c.egl_context);
+
+        let native_displays = std::iter::once(NativeDisplay::Surfaceless)
+            .chain(egl::devices().into_iter().map(NativeDisplay::Device))
+            .chain(std::iter::once(NativeDisplay::Other(None)));
+
+        let mut errors = vec![];
+        for native_display in native_displays {
+            let surface_type =
+                if size.is_some() { EglSurfaceType::PBuffer } else { EglSurfaceType::Surfaceless };
+            let context = EglContext::new(pf_reqs, &gl_attr, native_display, surface_type, |c, _| {
+                Ok(c[0])
+            })
+            .and_then(|p| match size {
+                Some(size) => p.finish_pbuffer(size),
+                None => p.finish_surfaceless(),
+            });
+            match context {
+                Ok(egl_context) => return Ok(Context { egl_context }),
+                Err(err) => errors.push(Box::new(err)),
+            }
+        }
+        Err(CreationError::CreationErrors(errors))
+    }
+
+    #[inline]
+    pub unsafe fn make_current(&self) -> Result<(), ContextError> {
+        self.egl_context.make_current()
+    }
+
+    #[inline]
+    pub unsafe fn make_not_current(&self) -> Result<(), ContextError> {
+        self.egl_context.make_not_current()
+    }
+
+    #[inline]
+    pub fn is_current(&self) -> bool {
+        self.egl_context.is_current()
+    }
+
+    #[inline]
+    pub fn get_proc_address(&self, addr: &str) -> *const core::ffi::c_void {
+        self.egl_context.get_proc_address(addr)
+    }
+
+    #[inline]
+    pub fn get_api(&self) -> Api {
+        self.egl_context.get_api()
+    }
+
+    #[inline]
+    pub fn get_pixel_format(&self) -> PixelFormat {
+        self.egl_context.get_pixel_format()
+    }
+
+    #[inline]
+    pub unsafe fn raw_handle(&self) -> ffi::EGLContext {
+        self.egl_context.raw_handle()
+    }
+
+    #[inline]
+    pub unsafe fn get_egl_display(&self) -> ffi::EGLDisplay {
+        self.egl_context.get_egl_display()
+    }
+}
@@ -1,19 +1,27 @@
 #![cfg(feature = "kms")]
 
-use std::{num::NonZeroU32, os::unix::prelude::FromRawFd};
+use std::{
+    io,
+    num::NonZeroU32,
+    os::unix::prelude::FromRawFd,
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
+};
 
-use drm::control::{atomic::AtomicModeReq, property, AtomicCommitFlags, Device, ResourceHandle};
+use drm::control::{
+    atomic::AtomicModeReq, property, AtomicCommitFlags, Device, PageFlipFlags, ResourceHandle,
+};
 use gbm::{AsRaw, BufferObjectFlags};
 use parking_lot::Mutex;
 use winit::{
     event_loop::EventLoopWindowTarget,
-    platform::unix::{Card, EventLoopWindowTargetExtUnix},
+    platform::unix::{Card, EventLoopWindowTargetExtUnix, PageFlipTracker, WindowExtUnix},
     window::{Window, WindowBuilder},
 };
 
 use crate::{
     api::egl::{Egl, NativeDisplay, EGL},
-    ContextError, CreationError, GlAttributes, PixelFormat, PixelFormatRequirements, Rect,
+    ContextError, CreationError, GlAttributes, PixelFormat, PixelFormatRequirements,
+    PresentationTimestamp, Rect,
 };
 use glutin_egl_sys as ffi;
 
@@ -50,17 +58,63 @@ pub struct CtxLock {
 unsafe impl Send for CtxLock {}
 unsafe impl Sync for CtxLock {}
 
+/// The properties an atomic commit needs to present a frame.
+#[derive(Debug)]
+struct AtomicProps {
+    fb_id: property::Handle,
+    out_fence_ptr: property::Handle,
+    in_fence_fd: property::Handle,
+}
+
+impl AtomicProps {
+    /// Returns `None` if the device only supports the legacy API, like winit falls back to.
+    fn new(
+        card: &Card,
+        plane: drm::control::plane::Handle,
+        crtc: drm::control::crtc::Handle,
+    ) -> Result<Option<Self>, CreationError> {
+        if drm::Device::set_client_capability(card, drm::ClientCapability::Atomic, true).is_err() {
+            return Ok(None);
+        }
+        Ok(Some(AtomicProps {
+            fb_id: find_prop_id(card, plane, "FB_ID")
+                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
+            out_fence_ptr: find_prop_id(card, crtc, "OUT_FENCE_PTR")
+                .ok_or(CreationError::NotSupported("Could not get OUT_FENCE_PTR".into()))?,
+            in_fence_fd: find_prop_id(card, plane, "IN_FENCE_FD")
+                .ok_or(CreationError::NotSupported("Could not get IN_FENCE_FD".into()))?,
+        }))
+    }
+}
+
 #[derive(Debug)]
 pub struct Context {
     display: EglContext,
     ctx_lock: parking_lot::Mutex<CtxLock>,
-    fb_id_property: property::Handle,
-    out_fence_ptr_prop: property::Handle,
-    in_fence_fd_prop: property::Handle,
+    /// `None` when frames are presented with legacy page flips
+    atomic_props: Option<AtomicProps>,
     depth: u32,
     bpp: u32,
     plane: drm::control::plane::Handle,
     crtc: drm::control::crtc::Info,
+    /// Told about each flip so the event loop can hold redraws back until it lands
+    page_flips: Option<PageFlipTracker>,
+    /// How many vblanks to wait for between flips, flips are paced by the event loop (see
+    /// `PageFlipTracker::set_swap_interval`) rather than EGL
+    swap_interval: AtomicU32,
+    /// Cleared once the driver rejected an async flip for an interval of 0
+    async_flips: AtomicBool,
+    /// Flips queued so far, the last two of them still have to be matched to a vblank
+    flips: Mutex<QueuedFlips>,
+}
+
+#[derive(Debug, Default)]
+struct QueuedFlips {
+    count: u64,
+    /// The frame of the last queued flip
+    last: Option<u64>,
+    /// The frame of the flip before it, on screen while the last one is pending
+    previous: Option<u64>,
 }
 
 impl std::ops::Deref for Context {
@@ -71,6 +125,29 @@ impl std::ops::Deref for Context {
     }
 }
 
+/// Whether the driver rejected an async flip because it can't do them, rather than e.g. because
+/// it was busy.
+fn rejects_async_flips(error: &drm::SystemError) -> bool {
+    match *error {
+        drm::SystemError::InvalidArgument => true,
+        drm::SystemError::Unknown { errno } => errno as i32 == libc::EOPNOTSUPP,
+        _ => false,
+    }
+}
+
+/// Turns a failed commit or page flip into an error. The kernel rejects them with `EBUSY` while
+/// a flip is still in flight, e.g. one we gave up on when we lost DRM master. That is reported
+/// as `WouldBlock`, the frame can be drawn again once the flip landed.
+fn flip_error(what: &str, error: drm::SystemError) -> ContextError {
+    let busy = matches!(error, drm::SystemError::Unknown { errno } if errno as i32 == libc::EBUSY);
+    let message = format!("{}: {}", what, error);
+    if busy {
+        ContextError::IoError(io::Error::new(io::ErrorKind::WouldBlock, message))
+    } else {
+        ContextError::OsError(message)
+    }
+}
+
 fn find_prop_id<T: ResourceHandle>(
     card: &Card,
     handle: T,
@@ -95,7 +172,10 @@ impl Context {
         _size: Option<winit::dpi::PhysicalSize<u32>>,
     ) -> Result<Self, CreationError> {
         let mut gl_attr = gl_attr.clone().map_sharing(|c| &**c);
+        let swap_interval = if gl_attr.vsync { gl_attr.swap_interval.max(1) } else { 0 };
+        // Flips always wait for a vblank, which EGL has no say in
         gl_attr.vsync = true;
+        gl_attr.swap_interval = 1;
         let drm_ptr = el
             .drm_device()
             .ok_or(CreationError::NotSupported("GBM is not initialized".into()))?
@@ -117,12 +197,7 @@ impl Context {
         let crtc = el.drm_crtc().ok_or(CreationError::OsError("No crtc found".to_string()))?;
         let context = Context {
             display: context,
-            fb_id_property: find_prop_id(&display_ptr, plane, "FB_ID")
-                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
-            out_fence_ptr_prop: find_prop_id(&display_ptr, crtc.handle(), "OUT_FENCE_PTR")
-                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
-            in_fence_fd_prop: find_prop_id(&display_ptr, plane, "IN_FENCE_FD")
-                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
+            atomic_props: AtomicProps::new(&display_ptr, plane, crtc.handle())?,
             ctx_lock: Mutex::new(CtxLock {
                 surface: None,
                 previous_fb: None,
@@ -135,6 +210,10 @@ impl Context {
             }),
             plane,
             crtc: crtc.clone(),
+            page_flips: None,
+            swap_interval: AtomicU32::new(swap_interval),
+            async_flips: AtomicBool::new(true),
+            flips: Mutex::new(QueuedFlips::default()),
             depth: pf_reqs.depth_bits.unwrap_or(0) as u32,
             bpp: pf_reqs.alpha_bits.unwrap_or(0) as u32 + pf_reqs.color_bits.unwrap_or(0) as u32,
         };
@@ -151,17 +230,23 @@ impl Context {
         let window = wb.build(&el)?;
         let size = window.inner_size();
         let (width, height): (u32, u32) = size.into();
-        let ctx = Self::new_raw_context(
+        let mut ctx = Self::new_raw_context(
             el.drm_device()
                 .as_ref()
                 .ok_or(CreationError::NotSupported("GBM is not initialized".into()))?,
             width,
             height,
-            el.drm_plane().ok_or(CreationError::OsError("No plane found".to_string()))?,
-            el.drm_crtc().ok_or(CreationError::OsError("No crtc found".to_string()))?.clone(),
+            // Each window has its own output, which is not necessarily the primary one
+            window.drm_plane().ok_or(CreationError::OsError("No plane found".to_string()))?,
+            window.drm_crtc().ok_or(CreationError::OsError("No crtc found".to_string()))?.clone(),
             pf_reqs,
             gl_attr,
         )?;
+        ctx.page_flips = window.drm_page_flips();
+        if let Some(ref page_flips) = ctx.page_flips {
+            // Intervals above 1 are kept by the event loop holding redraws back
+            page_flips.set_swap_interval(ctx.swap_interval.load(Ordering::Relaxed));
+        }
         Ok((window, ctx))
     }
 
@@ -176,7 +261,10 @@ impl Context {
         gl_attr: &GlAttributes<&Context>,
     ) -> Result<Self, CreationError> {
         let mut gl_attr = gl_attr.clone().map_sharing(|c| &**c);
+        let swap_interval = if gl_attr.vsync { gl_attr.swap_interval.max(1) } else { 0 };
+        // Flips always wait for a vblank, which EGL has no say in
         gl_attr.vsync = true;
+        gl_attr.swap_interval = 1;
         let drm_ptr = display_ptr.clone();
         let display_ptr =
             gbm::Device::new(drm_ptr).map_err(|e| CreationError::OsError(e.to_string()))?;
@@ -203,12 +291,7 @@ impl Context {
 
         let ctx = Context {
             display,
-            fb_id_property: find_prop_id(&display_ptr, plane, "FB_ID")
-                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
-            out_fence_ptr_prop: find_prop_id(&display_ptr, crtc.handle(), "OUT_FENCE_PTR")
-                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
-            in_fence_fd_prop: find_prop_id(&display_ptr, plane, "IN_FENCE_FD")
-                .ok_or(CreationError::NotSupported("Could not get FB_ID".into()))?,
+            atomic_props: AtomicProps::new(&display_ptr, plane, crtc.handle())?,
             ctx_lock: Mutex::new(CtxLock {
                 surface: Some(surface),
                 previous_fb: None,
@@ -221,6 +304,10 @@ impl Context {
             }),
             plane,
             crtc: crtc.clone(),
+            page_flips: None,
+            swap_interval: AtomicU32::new(swap_interval),
+            async_flips: AtomicBool::new(true),
+            flips: Mutex::new(QueuedFlips::default()),
             depth: pf_reqs.depth_bits.unwrap_or(0) as u32,
             bpp: pf_reqs.alpha_bits.unwrap_or(0) as u32 + pf_reqs.color_bits.unwrap_or(0) as u32,
         };
@@ -265,6 +352,7 @@ impl Context {
         &self,
         egl: &Egl,
         gpu_fence: *const std::os::raw::c_void,
+        atomic_props: &AtomicProps,
     ) -> Result<(), ContextError> {
         let mut lock = self.ctx_lock.lock();
         lock.gpu_fence = gpu_fence;
@@ -291,44 +379,76 @@ impl Context {
             .add_framebuffer(&front_buffer, self.depth, self.bpp)
             .or_else(|e| Err(ContextError::OsError(format!("Error adding framebuffer: {}", e))))?;
 
+        // The previous flip has to land before we can commit again, `check_flip_landed` made
+        // sure of that. Without a window nobody tells us, so we wait for its fence.
         if !lock.kms_fence.is_null() {
-            unsafe {
-                let mut status: i32 = 0;
-                while status != ffi::egl::CONDITION_SATISFIED as i32 {
-                    status = egl.ClientWaitSyncKHR(
+            let kms_fence = std::mem::replace(&mut lock.kms_fence, std::ptr::null());
+            let status = match self.page_flips {
+                Some(_) => ffi::egl::CONDITION_SATISFIED as i32,
+                None => unsafe {
+                    egl.ClientWaitSyncKHR(
                         self.display.get_egl_display(),
-                        lock.kms_fence,
+                        kms_fence,
                         0,
                         ffi::egl::FOREVER,
-                    );
-                }
-                egl.DestroySyncKHR(self.display.get_egl_display(), lock.kms_fence);
+                    )
+                },
+            };
+            unsafe {
+                egl.DestroySyncKHR(self.display.get_egl_display(), kms_fence);
+            }
+            if status != ffi::egl::CONDITION_SATISFIED as i32 {
+                let _ = lock.device.destroy_framebuffer(fb);
+                return Err(ContextError::OsError(
+                    "Error waiting for the previous page flip".to_string(),
+                ));
             }
         }
         let mut atomic_req = AtomicModeReq::new();
         atomic_req.add_property(
             self.plane,
-            self.fb_id_property,
+            atomic_props.fb_id,
             property::Value::Framebuffer(Some(fb)),
         );
         if lock.kms_in_fence_fd != -1 {
             let fence_ptr: *mut i32 = &mut lock.kms_out_fence_fd;
             atomic_req.add_property(
                 self.crtc.handle(),
-                self.out_fence_ptr_prop,
+                atomic_props.out_fence_ptr,
                 property::Value::Unknown(fence_ptr as u64),
             );
             atomic_req.add_property(
                 self.plane,
-                self.in_fence_fd_prop,
+                atomic_props.in_fence_fd,
                 property::Value::Object(Some(
                     NonZeroU32::new(lock.kms_in_fence_fd as u32).unwrap(),
                 )),
             );
         }
-        lock.device
-            .atomic_commit(AtomicCommitFlags::NONBLOCK, atomic_req)
-            .or_else(|e| Err(ContextError::OsError(format!("Error setting crtc: {}", e))))?;
+        let flags = AtomicCommitFlags::NONBLOCK | AtomicCommitFlags::PAGE_FLIP_EVENT;
+        let committed = if self.wants_async_flip() {
+            let async_flags = flags | AtomicCommitFlags::PAGE_FLIP_ASYNC;
+            match lock.device.atomic_commit(async_flags, atomic_req.clone()) {
+                Ok(()) => true,
+                Err(e) if rejects_async_flips(&e) => {
+                    self.async_flips_unsupported(e);
+                    false
+                }
+                Err(e) => {
+                    let _ = lock.device.destroy_framebuffer(fb);
+                    return Err(flip_error("Error setting crtc", e));
+                }
+            }
+        } else {
+            false
+        };
+        if !committed {
+            if let Err(e) = lock.device.atomic_commit(flags, atomic_req) {
+                let _ = lock.device.destroy_framebuffer(fb);
+                return Err(flip_error("Error setting crtc", e));
+            }
+        }
+        self.flip_queued();
         if let Some(prev_fb) = lock.previous_fb {
             lock.device.destroy_framebuffer(prev_fb).or_else(|e| {
                 Err(ContextError::OsError(format!("Error destroying framebuffer: {}", e)))
@@ -366,8 +486,135 @@ impl Context {
         Ok(())
     }
 
+    /// Presents the frame with a legacy page flip. There are no fences here, drivers that only
+    /// have the legacy API wait for rendering to finish before flipping by themselves.
+    #[inline]
+    fn finish_swap_buffers_legacy(&self) -> Result<(), ContextError> {
+        let mut lock = self.ctx_lock.lock();
+
+        let front_buffer = unsafe {
+            lock.surface
+                .as_ref()
+                .ok_or(ContextError::OsError("This context is surfaceless".to_string()))?
+                .lock_front_buffer()
+                .or_else(|e| {
+                    Err(ContextError::OsError(format!("Error locking front buffer: {}", e)))
+                })?
+        };
+        let fb = lock
+            .device
+            .add_framebuffer(&front_buffer, self.depth, self.bpp)
+            .or_else(|e| Err(ContextError::OsError(format!("Error adding framebuffer: {}", e))))?;
+
+        // `check_flip_landed` made sure that the previous flip landed, the kernel rejects the
+        // flip otherwise
+        let flipped = self.wants_async_flip()
+            && match lock.device.page_flip(
+                self.crtc.handle(),
+                fb,
+                &[PageFlipFlags::PageFlipEvent, PageFlipFlags::PageFlipAsync],
+                None,
+            ) {
+                Ok(()) => true,
+                Err(e) if rejects_async_flips(&e) => {
+                    self.async_flips_unsupported(e);
+                    false
+                }
+                Err(e) => {
+                    let _ = lock.device.destroy_framebuffer(fb);
+                    return Err(flip_error("Error flipping page", e));
+                }
+            };
+        if !flipped {
+            let flags = [PageFlipFlags::PageFlipEvent];
+            if let Err(e) = lock.device.page_flip(self.crtc.handle(), fb, &flags, None) {
+                let _ = lock.device.destroy_framebuffer(fb);
+                return Err(flip_error("Error flipping page", e));
+            }
+        }
+        self.flip_queued();
+        if let Some(prev_fb) = lock.previous_fb {
+            lock.device.destroy_framebuffer(prev_fb).or_else(|e| {
+                Err(ContextError::OsError(format!("Error destroying framebuffer: {}", e)))
+            })?
+        }
+        lock.previous_fb = Some(fb);
+        lock.previous_bo = Some(front_buffer);
+        Ok(())
+    }
+
+    fn wants_async_flip(&self) -> bool {
+        self.swap_interval.load(Ordering::Relaxed) == 0 && self.async_flips.load(Ordering::Relaxed)
+    }
+
+    fn async_flips_unsupported(&self, error: impl std::fmt::Display) {
+        log::warn!("Async page flips are not supported, an interval of 0 acts like 1: {}", error);
+        self.async_flips.store(false, Ordering::Relaxed);
+    }
+
+    fn flip_queued(&self) {
+        if let Some(ref page_flips) = self.page_flips {
+            page_flips.flip_queued();
+        }
+        let mut flips = self.flips.lock();
+        flips.count += 1;
+        flips.previous = flips.last;
+        flips.last = Some(flips.count);
+    }
+
+    /// Returns the vblank the most recent flip landed on. Flips are counted from the first swap.
+    pub fn presentation_timestamp(&self) -> Option<PresentationTimestamp> {
+        let page_flips = self.page_flips.as_ref()?;
+        let vblank = page_flips.last_vblank()?;
+        let flips = self.flips.lock();
+        let frame = if page_flips.is_flip_pending() { flips.previous } else { flips.last }?;
+        Some(PresentationTimestamp { frame, presented: vblank.time })
+    }
+
+    /// Makes flips wait for `interval` vblanks. An interval of 0 flips as soon as possible,
+    /// tearing if the driver supports async flips.
+    pub fn set_swap_interval(&self, interval: u32) -> Result<(), ContextError> {
+        self.swap_interval.store(interval, Ordering::Relaxed);
+        if let Some(ref page_flips) = self.page_flips {
+            page_flips.set_swap_interval(interval);
+        }
+        Ok(())
+    }
+
+    /// Fails with `WouldBlock` if the previous flip has not landed yet, the kernel would reject
+    /// the commit. The event loop only sends `RedrawRequested` once it did, so this only fails
+    /// when presenting outside of a redraw. Draw the frame again on the next one.
+    fn check_flip_landed(&self) -> Result<(), ContextError> {
+        match self.page_flips {
+            Some(ref page_flips) if page_flips.is_flip_pending() => {
+                Err(ContextError::IoError(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "The previous page flip has not landed yet",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails with `ContextLost` once the monitor of the window was unplugged.
+    pub fn resume(&self) -> Result<(), ContextError> {
+        match self.page_flips {
+            Some(ref page_flips) if page_flips.is_output_lost() => Err(ContextError::ContextLost),
+            _ => Ok(()),
+        }
+    }
+
     #[inline]
     pub fn swap_buffers(&self) -> Result<(), ContextError> {
+        self.resume()?;
+        self.check_flip_landed()?;
+        let atomic_props = match self.atomic_props {
+            Some(ref atomic_props) => atomic_props,
+            None => {
+                (**self).swap_buffers()?;
+                return self.finish_swap_buffers_legacy();
+            }
+        };
         let egl = EGL.as_ref().unwrap();
         let attrib_list = [
             ffi::egl::SYNC_NATIVE_FENCE_FD_ANDROID as i32,
@@ -386,11 +633,20 @@ impl Context {
         assert!(!gpu_fence.is_null());
 
         (**self).swap_buffers()?;
-        self.finish_swap_buffers(egl, gpu_fence)
+        self.finish_swap_buffers(egl, gpu_fence, atomic_props)
     }
 
     #[inline]
     pub fn swap_buffers_with_damage(&self, rects: &[Rect]) -> Result<(), ContextError> {
+        self.resume()?;
+        self.check_flip_landed()?;
+        let atomic_props = match self.atomic_props {
+            Some(ref atomic_props) => atomic_props,
+            None => {
+                (**self).swap_buffers_with_damage(rects)?;
+                return self.finish_swap_buffers_legacy();
+            }
+        };
         let egl = EGL.as_ref().unwrap();
         let attrib_list = [
             ffi::egl::SYNC_NATIVE_FENCE_FD_ANDROID as i32,
@@ -409,7 +665,7 @@ impl Context {
         assert!(!gpu_fence.is_null());
 
         (**self).swap_buffers_with_damage(rects)?;
-        self.finish_swap_buffers(egl, gpu_fence)
+        self.finish_swap_buffers(egl, gpu_fence, atomic_props)
     }
 
     #[inline]
@@ -9,6 +9,7 @@
 #[cfg(not(any(feature = "x11", feature = "wayland", feature = "kms")))]
 compile_error!("at least one of the 'x11' or 'wayland' or `kms` features must be enabled");
 
+mod headless;
 mod kms;
 mod wayland;
 mod x11;
@@ -19,7 +20,7 @@ use self::x11::X11Context;
 use crate::api::osmesa;
 use crate::{
     Api, ContextCurrentState, ContextError, CreationError, GlAttributes, NotCurrent, PixelFormat,
-    PixelFormatRequirements, Rect,
+    PixelFormatRequirements, PresentationTimestamp, Rect,
 };
 use winit::platform::unix::Backend;
 #[cfg(feature = "x11")]
@@ -34,9 +35,17 @@ use winit::window::{Window, WindowBuilder};
 
 use std::marker::PhantomData;
 use std::os::raw;
+use std::time::Duration;
 #[cfg(feature = "x11")]
 use std::sync::Arc;
 
+/// The current time on `CLOCK_MONOTONIC`, the clock [`PresentationTimestamp`]s are on.
+pub fn monotonic_now() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
 /// Context handles available on Unix-like platforms.
 #[derive(Clone, Debug)]
 pub enum RawHandle {
@@ -58,6 +67,7 @@ pub enum ContextType {
     OsMesa,
     #[cfg(feature = "kms")]
     FbDev,
+    Headless,
 }
 
 #[derive(Debug)]
@@ -70,7 +80,8 @@ pub enum Context {
     Drm(kms::Context),
     OsMesa(osmesa::OsMesaContext),
     #[cfg(feature = "kms")]
-    FbDev(fbdev::Context)
+    FbDev(fbdev::Context),
+    Headless(headless::Context),
 }
 
 impl Context {
@@ -116,6 +127,13 @@ impl Context {
                         return Err(CreationError::PlatformSpecific(msg.into()));
                     }
                 },
+                ContextType::Headless => match *c {
+                    Context::Headless(_) => Ok(()),
+                    _ => {
+                        let msg = "Cannot share a headless EGL context with a non-headless context";
+                        Err(CreationError::PlatformSpecific(msg.into()))
+                    }
+                },
             }
         } else {
             Ok(())
@@ -230,14 +248,25 @@ impl Context {
                 });
                 return kms::Context::new_headless(&el, pf_reqs, &gl_attr, size).map(Context::Drm);
             }
+            // Contexts shared with the window have to live on its display
+            #[cfg(feature = "kms")]
+            Backend::FbDev if gl_attr.sharing.is_some() => {
+                Context::is_compatible(&gl_attr.sharing, ContextType::FbDev)?;
+                let gl_attr = gl_attr.clone().map_sharing(|ctx| match *ctx {
+                    Context::FbDev(ref ctx) => ctx,
+                    _ => unreachable!(),
+                });
+                return fbdev::Context::new_headless(&el, pf_reqs, &gl_attr, size)
+                    .map(Context::FbDev);
+            }
             #[cfg(feature = "kms")]
             Backend::FbDev => {
-                Context::is_compatible(&gl_attr.sharing, ContextType::Drm)?;
+                Context::is_compatible(&gl_attr.sharing, ContextType::Headless)?;
                 let gl_attr = gl_attr.clone().map_sharing(|ctx| match *ctx {
-                    Context::Drm(ref ctx) => ctx,
+                    Context::Headless(ref ctx) => ctx,
                     _ => unreachable!(),
                 });
-                return kms::Context::new_headless(&el, pf_reqs, &gl_attr, size).map(Context::Drm);
+                return headless::Context::new(pf_reqs, &gl_attr, size).map(Context::Headless);
             }
         }
     }
@@ -254,6 +283,7 @@ impl Context {
             Context::OsMesa(ref ctx) => ctx.make_current(),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => ctx.make_current(),
+            Context::Headless(ref ctx) => ctx.make_current(),
         }
     }
 
@@ -269,6 +299,7 @@ impl Context {
             Context::OsMesa(ref ctx) => ctx.make_not_current(),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => ctx.make_not_current(),
+            Context::Headless(ref ctx) => ctx.make_not_current(),
         }
     }
 
@@ -284,6 +315,7 @@ impl Context {
             Context::OsMesa(ref ctx) => ctx.is_current(),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => ctx.is_current(),
+            Context::Headless(ref ctx) => ctx.is_current(),
         }
     }
 
@@ -299,6 +331,7 @@ impl Context {
             Context::OsMesa(ref ctx) => ctx.get_api(),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => ctx.get_api(),
+            Context::Headless(ref ctx) => ctx.get_api(),
         }
     }
 
@@ -317,6 +350,7 @@ impl Context {
             Context::OsMesa(ref ctx) => RawHandle::Egl(ctx.raw_handle()),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => RawHandle::Egl(ctx.raw_handle()),
+            Context::Headless(ref ctx) => RawHandle::Egl(ctx.raw_handle()),
         }
     }
 
@@ -331,6 +365,7 @@ impl Context {
             Context::Drm(ref ctx) => ctx.get_egl_display(),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => Some(ctx.get_egl_display()),
+            Context::Headless(ref ctx) => Some(ctx.get_egl_display()),
             _ => None,
         }
     }
@@ -363,6 +398,7 @@ impl Context {
             Context::OsMesa(ref ctx) => ctx.get_proc_address(addr),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => ctx.get_proc_address(addr),
+            Context::Headless(ref ctx) => ctx.get_proc_address(addr),
         }
     }
 
@@ -396,6 +432,50 @@ impl Context {
         }
     }
 
+    #[inline]
+    pub fn set_swap_interval(&self, interval: u32) -> Result<(), ContextError> {
+        match *self {
+            #[cfg(feature = "kms")]
+            Context::Drm(ref ctx) => ctx.set_swap_interval(interval),
+            #[cfg(feature = "kms")]
+            Context::FbDev(ref ctx) => ctx.set_swap_interval(interval),
+            _ => Err(ContextError::FunctionUnavailable),
+        }
+    }
+
+    #[inline]
+    pub fn presentation_timestamp(&self) -> Option<PresentationTimestamp> {
+        match *self {
+            #[cfg(feature = "kms")]
+            Context::Drm(ref ctx) => ctx.presentation_timestamp(),
+            #[cfg(feature = "kms")]
+            Context::FbDev(ref ctx) => ctx.presentation_timestamp(),
+            _ => None,
+        }
+    }
+
+    /// Only fbdev contexts can lose their surface, for the others this does nothing. kms
+    /// contexts are lost for good when their monitor is unplugged.
+    #[inline]
+    pub fn suspend(&self) {
+        match *self {
+            #[cfg(feature = "kms")]
+            Context::FbDev(ref ctx) => ctx.suspend(),
+            _ => (),
+        }
+    }
+
+    #[inline]
+    pub fn resume(&self) -> Result<bool, ContextError> {
+        match *self {
+            #[cfg(feature = "kms")]
+            Context::FbDev(ref ctx) => ctx.resume(),
+            #[cfg(feature = "kms")]
+            Context::Drm(ref ctx) => ctx.resume().map(|()| false),
+            _ => Ok(false),
+        }
+    }
+
     #[inline]
     pub fn buffer_age(&self) -> u32 {
         match *self {
@@ -403,7 +483,11 @@ impl Context {
             Context::X11(ref ctx) => ctx.buffer_age(),
             #[cfg(feature = "wayland")]
             Context::Wayland(ref ctx) => ctx.buffer_age(),
-            _ => unreachable!(),
+            #[cfg(feature = "kms")]
+            Context::Drm(ref ctx) => ctx.buffer_age(),
+            #[cfg(feature = "kms")]
+            Context::FbDev(ref ctx) => ctx.buffer_age(),
+            _ => 0,
         }
     }
 
@@ -433,6 +517,7 @@ impl Context {
             Context::Drm(ref ctx) => ctx.get_pixel_format(),
             #[cfg(feature = "kms")]
             Context::FbDev(ref ctx) => ctx.get_pixel_format(),
+            Context::Headless(ref ctx) => ctx.get_pixel_format(),
             _ => unreachable!(),
         }
     }
@@ -464,6 +549,23 @@ pub trait HeadlessContextExt {
     ) -> Result<crate::Context<NotCurrent>, CreationError>
     where
         Self: Sized;
+
+    /// Builds an EGL context that needs neither a window system nor an event loop, through
+    /// `EGL_MESA_platform_surfaceless` or `EGL_EXT_platform_device`. This works on any box with
+    /// Mesa, even without a GPU, which makes it suitable for offscreen rendering in tests.
+    ///
+    /// With a `size` the context renders into a pbuffer, otherwise only into framebuffer
+    /// objects.
+    ///
+    /// Errors can occur if the OpenGL [`Context`][crate::Context] could not be created.
+    /// This generally happens because the underlying platform doesn't support a
+    /// requested feature.
+    fn build_egl_headless(
+        self,
+        size: Option<dpi::PhysicalSize<u32>>,
+    ) -> Result<crate::Context<NotCurrent>, CreationError>
+    where
+        Self: Sized;
 }
 
 impl<'a, T: ContextCurrentState> HeadlessContextExt for crate::ContextBuilder<'a, T> {
@@ -500,6 +602,26 @@ impl<'a, T: ContextCurrentState> HeadlessContextExt for crate::ContextBuilder<'a
         Context::new_headless_impl(el, &pf_reqs, &gl_attr, None)
             .map(|context| crate::Context { context, phantom: PhantomData })
     }
+
+    #[inline]
+    fn build_egl_headless(
+        self,
+        size: Option<dpi::PhysicalSize<u32>>,
+    ) -> Result<crate::Context<NotCurrent>, CreationError>
+    where
+        Self: Sized,
+    {
+        let crate::ContextBuilder { pf_reqs, gl_attr } = self;
+        let gl_attr = gl_attr.map_sharing(|ctx| &ctx.context);
+        Context::is_compatible(&gl_attr.sharing, ContextType::Headless)?;
+        let gl_attr = gl_attr.clone().map_sharing(|ctx| match *ctx {
+            Context::Headless(ref ctx) => ctx,
+            _ => unreachable!(),
+        });
+        headless::Context::new(&pf_reqs, &gl_attr, size)
+            .map(Context::Headless)
+            .map(|context| crate::Context { context, phantom: PhantomData })
+    }
 }
 
 /// A unix-specific extension for the [`ContextBuilder`][crate::ContextBuilder]
@@ -27,6 +27,9 @@ fn main() {
             [
                 "EGL_EXT_buffer_age",
                 "EGL_EXT_create_context_robustness",
+                "EGL_EXT_device_base",
+                "EGL_EXT_device_enumeration",
+                "EGL_EXT_device_query",
                 "EGL_EXT_platform_base",
                 "EGL_EXT_platform_device",
                 "EGL_EXT_platform_wayland",
@@ -35,6 +38,7 @@ fn main() {
                 "EGL_KHR_create_context_no_error",
                 "EGL_KHR_platform_android",
                 "EGL_KHR_platform_gbm",
+                "EGL_ANDROID_get_frame_timestamps",
                 "EGL_ANDROID_native_fence_sync",
                 "EGL_KHR_fence_sync",
                 "EGL_KHR_wait_sync",
@@ -42,6 +46,7 @@ fn main() {
                 "EGL_KHR_platform_x11",
                 "EGL_KHR_swap_buffers_with_damage",
                 "EGL_MESA_platform_gbm",
+                "EGL_MESA_platform_surfaceless",
             ],
         );
 
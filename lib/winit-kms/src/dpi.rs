@@ -84,6 +84,10 @@
 //!   variables to do what you want before resorting to `WINIT_X11_SCALE_FACTOR`.
 //! - **Wayland:** On Wayland, scale factors are set per-screen by the server, and are always
 //!   integers (most often 1 or 2).
+//! - **Kms / FbDev:** The scale factor is calculated from the millimeter dimensions reported by
+//!   the DRM connector or framebuffer driver, the same way as for XRandR. Embedded panels often
+//!   report bogus or no dimensions, so it can be overridden with the `WINIT_KMS_SCALE_FACTOR` and
+//!   `WINIT_FBDEV_SCALE_FACTOR` environment variables, or from the application.
 //! - **iOS:** Scale factors are set by Apple to the value that best suits the device, and range
 //!   from `1.0` to `3.0`. See [this article][apple_1] and [this article][apple_2] for more
 //!   information.
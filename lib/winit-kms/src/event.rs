@@ -40,6 +40,7 @@ use std::path::PathBuf;
 use crate::window::Window;
 use crate::{
     dpi::{PhysicalPosition, PhysicalSize},
+    monitor::MonitorHandle,
     platform_impl,
     window::{Theme, WindowId},
 };
@@ -78,6 +79,21 @@ pub enum Event<'a, T: 'static> {
     /// Emitted when the application has been resumed.
     Resumed,
 
+    /// Emitted when a monitor has been plugged in.
+    ///
+    /// ## Platform-specific
+    ///
+    /// Only emitted by the kms backend.
+    MonitorAdded(MonitorHandle),
+
+    /// Emitted when a monitor has been unplugged. Windows on it stay around until they are
+    /// dropped, but nothing they draw is visible anymore.
+    ///
+    /// ## Platform-specific
+    ///
+    /// Only emitted by the kms backend.
+    MonitorRemoved(MonitorHandle),
+
     /// Emitted when all of the event loop's input events have been processed and redraw processing
     /// is about to begin.
     ///
@@ -139,6 +155,8 @@ impl<T: Clone> Clone for Event<'static, T> {
             LoopDestroyed => LoopDestroyed,
             Suspended => Suspended,
             Resumed => Resumed,
+            MonitorAdded(monitor) => MonitorAdded(monitor.clone()),
+            MonitorRemoved(monitor) => MonitorRemoved(monitor.clone()),
         }
     }
 }
@@ -157,6 +175,8 @@ impl<'a, T> Event<'a, T> {
             LoopDestroyed => Ok(LoopDestroyed),
             Suspended => Ok(Suspended),
             Resumed => Ok(Resumed),
+            MonitorAdded(monitor) => Ok(MonitorAdded(monitor)),
+            MonitorRemoved(monitor) => Ok(MonitorRemoved(monitor)),
         }
     }
 
@@ -177,6 +197,8 @@ impl<'a, T> Event<'a, T> {
             LoopDestroyed => Some(LoopDestroyed),
             Suspended => Some(Suspended),
             Resumed => Some(Resumed),
+            MonitorAdded(monitor) => Some(MonitorAdded(monitor)),
+            MonitorRemoved(monitor) => Some(MonitorRemoved(monitor)),
         }
     }
 }
@@ -338,6 +360,25 @@ pub enum WindowEvent<'a> {
     /// Touch event has been received
     Touch(Touch),
 
+    /// All [`WindowEvent::Touch`] events since the previous `TouchFrame` belong to the same
+    /// hardware sample, e.g. every finger of a pinch moving at once.
+    ///
+    /// ## Platform-specific
+    ///
+    /// - Only emitted by the kms and fbdev backends, where touch events are held back until the
+    ///   end of their frame.
+    TouchFrame { device_id: DeviceId },
+
+    /// A keyboard that can be typed on was plugged in (`true`) or the last one was unplugged
+    /// (`false`).
+    ///
+    /// Useful to switch between touch-first and keyboard-first interfaces.
+    ///
+    /// ## Platform-specific
+    ///
+    /// - Only emitted by the kms and fbdev backends.
+    KeyboardPresenceChanged(bool),
+
     /// The window's scale factor has changed.
     ///
     /// The following user actions can cause DPI changes:
@@ -448,6 +489,10 @@ impl Clone for WindowEvent<'static> {
                 value: *value,
             },
             Touch(touch) => Touch(*touch),
+            TouchFrame { device_id } => TouchFrame {
+                device_id: *device_id,
+            },
+            KeyboardPresenceChanged(present) => KeyboardPresenceChanged(*present),
             ThemeChanged(theme) => ThemeChanged(*theme),
             ScaleFactorChanged { .. } => {
                 unreachable!("Static event can't be about scale factor changing")
@@ -535,6 +580,8 @@ impl<'a> WindowEvent<'a> {
                 value,
             }),
             Touch(touch) => Some(Touch(touch)),
+            TouchFrame { device_id } => Some(TouchFrame { device_id }),
+            KeyboardPresenceChanged(present) => Some(KeyboardPresenceChanged(present)),
             ThemeChanged(theme) => Some(ThemeChanged(theme)),
             ScaleFactorChanged { .. } => None,
         }
@@ -155,6 +155,9 @@ impl MonitorHandle {
     /// ## Platform-specific
     ///
     /// - **X11:** Can be overridden using the `WINIT_X11_SCALE_FACTOR` environment variable.
+    /// - **Kms / FbDev:** Computed from the physical size reported by the driver, 1.0 if there is
+    ///   none. Can be overridden using the `WINIT_KMS_SCALE_FACTOR` and `WINIT_FBDEV_SCALE_FACTOR`
+    ///   environment variables.
     /// - **Android:** Always returns 1.0.
     /// - **Web:** Always returns 1.0
     #[inline]
@@ -14,28 +14,38 @@ use std::ptr;
 #[cfg(feature = "x11")]
 use std::sync::Arc;
 
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+use crate::{event::DeviceId, platform_impl::DeviceId as LinuxDeviceId};
 use crate::{
     event_loop::{EventLoopBuilder, EventLoopWindowTarget},
     monitor::MonitorHandle,
     window::{Window, WindowBuilder},
 };
 
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+use crate::dpi::validate_scale_factor;
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+use crate::error::{ExternalError, NotSupportedError};
 #[cfg(feature = "x11")]
 use crate::dpi::Size;
-#[cfg(feature = "kms")]
-use crate::platform_impl::kms::MODE;
 #[cfg(feature = "x11")]
 use crate::platform_impl::x11::{ffi::XVisualInfo, XConnection};
-#[cfg(any(feature = "x11", feature = "wayland"))]
 use crate::platform_impl::Window as LinuxWindow;
 use crate::platform_impl::{ApplicationName, EventLoopWindowTarget as LinuxEventLoopWindowTarget};
 
 #[cfg(any(feature = "x11", feature = "wayland", feature = "kms"))]
 pub use crate::platform_impl::Backend;
+pub use crate::platform_impl::UnixBackend;
 
 // TODO: stupid hack so that glutin can do its work
 #[cfg(feature = "kms")]
 pub use crate::platform_impl::kms::Card;
+#[cfg(feature = "kms")]
+pub use crate::platform_impl::kms::{PageFlipTracker, PlaneRotation, PlaneTransform, Vblank};
+#[cfg(feature = "fbdev")]
+pub use crate::platform_impl::fbdev::{FbDisplayState, FbPixelFormat};
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+pub use crate::platform_impl::InputDeviceInfo;
 #[doc(hidden)]
 #[cfg(feature = "x11")]
 pub use crate::platform_impl::x11;
@@ -68,29 +78,47 @@ pub trait EventLoopWindowTargetExtUnix {
     #[cfg(feature = "kms")]
     fn drm_device(&self) -> Option<&Card>;
 
-    /// Returns the current crtc of the drm device
+    /// Returns the crtc of the primary output of the drm device
     ///
     /// Returns `None` if the `EventLoop` doesn't use drm.
     #[cfg(feature = "kms")]
     fn drm_crtc(&self) -> Option<&crtc::Info>;
 
-    /// Returns the current connector of the drm device
+    /// Returns the connector of the primary output of the drm device
     ///
     /// Returns `None` if the `EventLoop` doesn't use drm.
     #[cfg(feature = "kms")]
     fn drm_connector(&self) -> Option<&connector::Info>;
 
-    /// Returns the current mode of the drm device
+    /// Returns the current mode of the primary output of the drm device
     ///
     /// Returns `None` if the `EventLoop` doesn't use drm.
     #[cfg(feature = "kms")]
     fn drm_mode(&self) -> Option<Mode>;
 
-    /// Returns the primary plane of the drm device
+    /// Returns the plane of the primary output of the drm device
     ///
     /// Returns `None` if the `EventLoop` doesn't use drm.
     #[cfg(feature = "kms")]
     fn drm_plane(&self) -> Option<plane::Handle>;
+
+    /// Information about the input device behind `device_id`.
+    ///
+    /// Available on the kms and fbdev backends from the moment [`DeviceEvent::Added`] is
+    /// delivered until the device is removed. Returns `None` otherwise.
+    ///
+    /// [`DeviceEvent::Added`]: crate::event::DeviceEvent::Added
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn input_device_info(&self, device_id: DeviceId) -> Option<InputDeviceInfo>;
+
+    /// Whether a keyboard that can be typed on is currently plugged in.
+    ///
+    /// Changes are reported with [`WindowEvent::KeyboardPresenceChanged`]. Always `false` on
+    /// backends other than kms and fbdev.
+    ///
+    /// [`WindowEvent::KeyboardPresenceChanged`]: crate::event::WindowEvent::KeyboardPresenceChanged
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn has_keyboard(&self) -> bool;
 }
 
 impl<T> EventLoopWindowTargetExtUnix for EventLoopWindowTarget<T> {
@@ -164,7 +192,7 @@ impl<T> EventLoopWindowTargetExtUnix for EventLoopWindowTarget<T> {
     #[cfg(feature = "kms")]
     fn drm_mode(&self) -> Option<drm::control::Mode> {
         match self.p {
-            LinuxEventLoopWindowTarget::Kms(_) => *MODE.lock(),
+            LinuxEventLoopWindowTarget::Kms(ref evlp) => Some(evlp.primary_mode()),
             #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
             _ => None,
         }
@@ -179,6 +207,36 @@ impl<T> EventLoopWindowTargetExtUnix for EventLoopWindowTarget<T> {
             _ => None,
         }
     }
+
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn input_device_info(&self, device_id: DeviceId) -> Option<InputDeviceInfo> {
+        match (&self.p, device_id.0) {
+            #[cfg(feature = "kms")]
+            (LinuxEventLoopWindowTarget::Kms(ref evlp), LinuxDeviceId::Kms(id)) => {
+                evlp.input_device_info(id)
+            }
+            #[cfg(feature = "fbdev")]
+            (LinuxEventLoopWindowTarget::FbDev(ref evlp), LinuxDeviceId::FbDev(id)) => {
+                evlp.input_device_info(id)
+            }
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn has_keyboard(&self) -> bool {
+        match self.p {
+            #[cfg(feature = "kms")]
+            LinuxEventLoopWindowTarget::Kms(ref evlp) => evlp.has_keyboard(),
+            #[cfg(feature = "fbdev")]
+            LinuxEventLoopWindowTarget::FbDev(ref evlp) => evlp.has_keyboard(),
+            #[cfg(any(feature = "x11", feature = "wayland"))]
+            _ => false,
+        }
+    }
 }
 
 /// Additional methods on [`EventLoopBuilder`] that are specific to Unix.
@@ -199,6 +257,44 @@ pub trait EventLoopBuilderExtUnix {
     #[cfg(feature = "fbdev")]
     fn with_fbdev(&mut self) -> &mut Self;
 
+    /// Whether the kms and fbdev backends should take over the virtual terminal they run on.
+    ///
+    /// When enabled, the VT is put into graphics mode so the kernel console stops drawing over
+    /// the window, and VT switches are reported as [`Event::Suspended`]/[`Event::Resumed`].
+    /// The VT is restored when the event loop is dropped or the process receives `SIGINT`,
+    /// `SIGTERM` or `SIGHUP`. The VT can be chosen with `WINIT_VT_PATH`, it defaults to
+    /// `/dev/tty0`.
+    ///
+    /// Disabled by default.
+    ///
+    /// [`Event::Suspended`]: crate::event::Event::Suspended
+    /// [`Event::Resumed`]: crate::event::Event::Resumed
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_vt_handling(&mut self, vt_handling: bool) -> &mut Self;
+
+    /// Force using `backend`, like `WINIT_UNIX_BACKEND` does, which it takes precedence over.
+    ///
+    /// Unlike [`with_x11`](Self::with_x11) and co. this can be called whatever features winit
+    /// was built with. Creating the event loop panics if `backend` is not one of them.
+    fn with_unix_backend(&mut self, backend: UnixBackend) -> &mut Self;
+
+    /// The DRM card (e.g. `/dev/dri/card0`) the kms backend opens, or the framebuffer device
+    /// (e.g. `/dev/fb0`) the fbdev backend opens. Takes precedence over `WINIT_DRM_CARD` and
+    /// `WINIT_FBDEV_PATH`.
+    ///
+    /// By default the first card or framebuffer udev finds is used.
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_device_path(&mut self, path: impl Into<std::path::PathBuf>) -> &mut Self;
+
+    /// The libinput calibration matrix applied to every touchscreen of the kms and fbdev
+    /// backends, e.g. to rotate touches with a display that is mounted rotated. The matrix
+    /// `[a, b, c, d, e, f]` maps a touch at `x, y`, both normalized to 0-1, to
+    /// `a * x + b * y + c, d * x + e * y + f`.
+    ///
+    /// By default touchscreens are left as they are configured.
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_touch_calibration(&mut self, matrix: [f32; 6]) -> &mut Self;
+
     /// Whether to allow the event loop to be created off of the main thread.
     ///
     /// By default, the window is only allowed to be created on the main
@@ -235,6 +331,33 @@ impl<T> EventLoopBuilderExtUnix for EventLoopBuilder<T> {
         self
     }
 
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_vt_handling(&mut self, vt_handling: bool) -> &mut Self {
+        self.platform_specific.vt_handling = vt_handling;
+        self
+    }
+
+    #[inline]
+    fn with_unix_backend(&mut self, backend: UnixBackend) -> &mut Self {
+        self.platform_specific.unix_backend = Some(backend);
+        self
+    }
+
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_device_path(&mut self, path: impl Into<std::path::PathBuf>) -> &mut Self {
+        self.platform_specific.device_path = Some(path.into());
+        self
+    }
+
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_touch_calibration(&mut self, matrix: [f32; 6]) -> &mut Self {
+        self.platform_specific.touch_calibration = Some(matrix);
+        self
+    }
+
     #[inline]
     fn with_any_thread(&mut self, any_thread: bool) -> &mut Self {
         self.platform_specific.any_thread = any_thread;
@@ -289,6 +412,102 @@ pub trait WindowExtUnix {
     #[cfg(feature = "wayland")]
     fn wayland_display(&self) -> Option<*mut raw::c_void>;
 
+    /// Overrides the scale factor computed from the physical size of the display, or goes back
+    /// to the computed one with `None`. A `ScaleFactorChanged` event is sent if this changes the
+    /// scale factor. Only relevant on kms and fbdev.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `scale_factor` is not a normal positive number.
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn set_scale_factor_override(&self, scale_factor: Option<f64>);
+
+    /// Returns the connector the window is shown on
+    ///
+    /// Returns `None` if the window doesn't use drm.
+    #[cfg(feature = "kms")]
+    fn drm_connector(&self) -> Option<&connector::Info>;
+
+    /// Returns the crtc driving the window's connector
+    ///
+    /// Returns `None` if the window doesn't use drm.
+    #[cfg(feature = "kms")]
+    fn drm_crtc(&self) -> Option<&crtc::Info>;
+
+    /// Returns the plane the window is scanned out from
+    ///
+    /// Returns `None` if the window doesn't use drm.
+    #[cfg(feature = "kms")]
+    fn drm_plane(&self) -> Option<plane::Handle>;
+
+    /// Returns the current mode of the window's connector
+    ///
+    /// Returns `None` if the window doesn't use drm.
+    #[cfg(feature = "kms")]
+    fn drm_mode(&self) -> Option<Mode>;
+
+    /// Returns the page flip bookkeeping of the window's output.
+    ///
+    /// Whoever presents to the window must call [`PageFlipTracker::flip_queued`] after each
+    /// commit made with `AtomicCommitFlags::PAGE_FLIP_EVENT`, `RedrawRequested` is then held
+    /// back until the flip lands.
+    ///
+    /// Returns `None` if the window doesn't use drm.
+    #[cfg(feature = "kms")]
+    fn drm_page_flips(&self) -> Option<PageFlipTracker>;
+
+    /// Returns the vblank the last page flip of the window landed on, add the refresh period of
+    /// [`drm_mode`](Self::drm_mode) to it to know when the next one is due.
+    ///
+    /// Returns `None` if the window doesn't use drm or nothing has been presented yet.
+    #[cfg(feature = "kms")]
+    fn drm_last_vblank(&self) -> Option<Vblank>;
+
+    /// Returns how the window's plane rotates, reflects and scales its buffer.
+    ///
+    /// Returns `None` if the window doesn't use drm.
+    #[cfg(feature = "kms")]
+    fn drm_plane_transform(&self) -> Option<PlaneTransform>;
+
+    /// Asks the window's plane to rotate, reflect or scale its buffer, so that it doesn't have
+    /// to be done in shaders.
+    ///
+    /// Fails if the hardware can't do it, in which case nothing changes and the application
+    /// should fall back to transforming by itself. Transforms that change the size of the
+    /// buffer are only possible with `WindowBuilderExtUnix::with_plane_transform`.
+    #[cfg(feature = "kms")]
+    fn drm_set_plane_transform(&self, transform: PlaneTransform) -> Result<(), ExternalError>;
+
+    /// Returns the pixel format of the framebuffer the window draws to.
+    ///
+    /// Returns `None` if the window doesn't use fbdev.
+    #[cfg(feature = "fbdev")]
+    fn fbdev_pixel_format(&self) -> Option<FbPixelFormat>;
+
+    /// Returns whether the window's display can currently be drawn to, shared with the event
+    /// loop so it stays up to date.
+    ///
+    /// Returns `None` if the window doesn't use fbdev.
+    #[cfg(feature = "fbdev")]
+    fn fbdev_display_state(&self) -> Option<FbDisplayState>;
+
+    /// Powers the window's display down or back up, e.g. after a period of inactivity.
+    /// `RedrawRequested` is held back while it is blanked.
+    #[cfg(feature = "fbdev")]
+    fn fbdev_set_blanked(&self, blanked: bool) -> Result<(), ExternalError>;
+
+    /// Returns the brightness of the window's backlight from 0 to 1.
+    ///
+    /// Returns `None` if the window doesn't use fbdev or there is no backlight in
+    /// `/sys/class/backlight`.
+    #[cfg(feature = "fbdev")]
+    fn fbdev_backlight(&self) -> Option<f32>;
+
+    /// Sets the brightness of the window's backlight from 0 to 1, e.g. to dim the display after
+    /// a period of inactivity. Some panels turn off at 0.
+    #[cfg(feature = "fbdev")]
+    fn fbdev_set_backlight(&self, brightness: f32) -> Result<(), ExternalError>;
+
     /// Check if the window is ready for drawing
     ///
     /// It is a remnant of a previous implementation detail for the
@@ -370,6 +589,153 @@ impl WindowExtUnix for Window {
         }
     }
 
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn set_scale_factor_override(&self, scale_factor: Option<f64>) {
+        if let Some(scale_factor) = scale_factor {
+            assert!(validate_scale_factor(scale_factor));
+        }
+
+        match self.window {
+            #[cfg(feature = "kms")]
+            LinuxWindow::Kms(ref w) => w.set_scale_factor_override(scale_factor),
+            #[cfg(feature = "fbdev")]
+            LinuxWindow::FbDev(ref w) => w.set_scale_factor_override(scale_factor),
+            #[cfg(any(feature = "x11", feature = "wayland"))]
+            _ => (),
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_connector(&self) -> Option<&connector::Info> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => Some(w.drm_connector()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_crtc(&self) -> Option<&crtc::Info> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => Some(w.drm_crtc()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_plane(&self) -> Option<plane::Handle> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => Some(w.drm_plane()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_mode(&self) -> Option<Mode> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => w.drm_mode(),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_page_flips(&self) -> Option<PageFlipTracker> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => Some(w.drm_page_flips()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_last_vblank(&self) -> Option<Vblank> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => w.drm_last_vblank(),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_plane_transform(&self) -> Option<PlaneTransform> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => Some(w.plane_transform()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn drm_set_plane_transform(&self, transform: PlaneTransform) -> Result<(), ExternalError> {
+        match self.window {
+            LinuxWindow::Kms(ref w) => w.set_plane_transform(transform),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "fbdev"))]
+            _ => Err(ExternalError::NotSupported(NotSupportedError::new())),
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "fbdev")]
+    fn fbdev_pixel_format(&self) -> Option<FbPixelFormat> {
+        match self.window {
+            LinuxWindow::FbDev(ref w) => Some(w.pixel_format()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "kms"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "fbdev")]
+    fn fbdev_display_state(&self) -> Option<FbDisplayState> {
+        match self.window {
+            LinuxWindow::FbDev(ref w) => Some(w.display_state()),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "kms"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "fbdev")]
+    fn fbdev_set_blanked(&self, blanked: bool) -> Result<(), ExternalError> {
+        match self.window {
+            LinuxWindow::FbDev(ref w) => w.set_blanked(blanked),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "kms"))]
+            _ => Err(ExternalError::NotSupported(NotSupportedError::new())),
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "fbdev")]
+    fn fbdev_backlight(&self) -> Option<f32> {
+        match self.window {
+            LinuxWindow::FbDev(ref w) => w.backlight(),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "kms"))]
+            _ => None,
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "fbdev")]
+    fn fbdev_set_backlight(&self, brightness: f32) -> Result<(), ExternalError> {
+        match self.window {
+            LinuxWindow::FbDev(ref w) => w.set_backlight(brightness),
+            #[cfg(any(feature = "x11", feature = "wayland", feature = "kms"))]
+            _ => Err(ExternalError::NotSupported(NotSupportedError::new())),
+        }
+    }
+
     #[inline]
     fn is_ready(&self) -> bool {
         true
@@ -437,6 +803,23 @@ pub trait WindowBuilderExtUnix {
     /// ```
     #[cfg(feature = "x11")]
     fn with_base_size<S: Into<Size>>(self, base_size: S) -> Self;
+
+    /// Build window with the given scale factor instead of the one computed from the physical
+    /// size of the display, which many embedded panels report incorrectly or not at all. Only
+    /// relevant on kms and fbdev, where it also takes precedence over `WINIT_KMS_SCALE_FACTOR`
+    /// and `WINIT_FBDEV_SCALE_FACTOR`.
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `scale_factor` is not a normal positive number.
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_scale_factor(self, scale_factor: f64) -> Self;
+
+    /// Build window with its plane rotating, reflecting or scaling the window's buffer. The
+    /// window's size is the size of that buffer. If the hardware rejects the transform, the
+    /// window is created without one, check with `WindowExtUnix::drm_plane_transform`.
+    #[cfg(feature = "kms")]
+    fn with_plane_transform(self, transform: PlaneTransform) -> Self;
 }
 
 impl WindowBuilderExtUnix for WindowBuilder {
@@ -497,6 +880,21 @@ impl WindowBuilderExtUnix for WindowBuilder {
         self.platform_specific.base_size = Some(base_size.into());
         self
     }
+
+    #[inline]
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    fn with_scale_factor(mut self, scale_factor: f64) -> Self {
+        assert!(validate_scale_factor(scale_factor));
+        self.platform_specific.scale_factor = Some(scale_factor);
+        self
+    }
+
+    #[inline]
+    #[cfg(feature = "kms")]
+    fn with_plane_transform(mut self, transform: PlaneTransform) -> Self {
+        self.platform_specific.plane_transform = transform;
+        self
+    }
 }
 
 /// Additional methods on `MonitorHandle` that are specific to Linux.
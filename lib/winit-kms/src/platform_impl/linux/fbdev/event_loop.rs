@@ -1,7 +1,7 @@
 use parking_lot::Mutex;
 use std::{
     cell::RefCell,
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     marker::PhantomData,
     path::{Path, PathBuf},
     rc::Rc,
@@ -22,10 +22,14 @@ use crate::{
     event_loop::{self, ControlFlow, EventLoopClosed},
     monitor::MonitorHandle,
     platform::unix::Card,
-    platform_impl::{self, platform::sticky_exit_callback, OsError},
+    platform_impl::{
+        self, platform::sticky_exit_callback, InputDeviceInfo, OsError,
+        PlatformSpecificEventLoopAttributes,
+    },
     window::WindowId,
 };
-use crate::platform_impl::fbdev::FBInfo;
+use crate::platform_impl::fbdev::{FBInfo, FbDisplayState, FbPixelFormat};
+use crate::platform_impl::vt::{self, VirtualTerminal, VtEvent};
 
 use super::{
     input::{Interface, LibinputInputBackend, REPEAT_RATE},
@@ -51,6 +55,9 @@ pub struct EventLoopWindowTarget<T> {
     /// Allows window to edit cursor position
     pub(crate) cursor_arc: Arc<Mutex<PhysicalPosition<f64>>>,
 
+    /// Input devices currently plugged in, by device id
+    pub(crate) input_devices: Arc<Mutex<HashMap<u32, InputDeviceInfo>>>,
+
     /// Event loop handle.
     pub event_loop_handle: calloop::LoopHandle<'static, EventSink>,
 
@@ -61,10 +68,35 @@ pub struct EventLoopWindowTarget<T> {
 
     pub(crate) info: FBInfo,
 
+    /// The framebuffer device, for ioctls linuxfb doesn't wrap
+    pub(crate) fb_file: Arc<std::fs::File>,
+
+    /// Whether drawing shows up right now
+    pub(crate) display_state: FbDisplayState,
+
+    /// Scale factor set by the application, takes precedence over the one computed from `info`
+    pub(crate) scale_factor_override: Arc<Mutex<Option<f64>>>,
+
     _marker: std::marker::PhantomData<T>,
 }
 
 impl<T> EventLoopWindowTarget<T> {
+    #[inline]
+    pub fn input_device_info(&self, device_id: super::DeviceId) -> Option<InputDeviceInfo> {
+        self.input_devices.lock().get(&device_id.0).cloned()
+    }
+
+    #[inline]
+    pub fn has_keyboard(&self) -> bool {
+        self.input_devices.lock().values().any(|info| info.keyboard)
+    }
+
+    pub(crate) fn scale_factor(&self) -> f64 {
+        self.scale_factor_override
+            .lock()
+            .unwrap_or_else(|| self.info.scale_factor())
+    }
+
     #[inline]
     pub fn primary_monitor(&self) -> Option<MonitorHandle> {
         Some(MonitorHandle {
@@ -102,23 +134,85 @@ pub struct EventLoop<T: 'static> {
 
     /// Window target.
     window_target: event_loop::EventLoopWindowTarget<T>,
+
+    /// The VT we took over, restored when the event loop is dropped.
+    _vt: Option<Rc<VirtualTerminal>>,
+
+    /// The scale factor the application last knew about.
+    scale_factor: f64,
 }
 
 impl<T: 'static> EventLoop<T> {
-    pub fn new() -> Result<EventLoop<T>, error::OsError> {
-        let fb_path = std::env::var("WINIT_FBDEV_PATH")
-            .map(|path| PathBuf::from(path))
-            .ok()
+    pub fn new(
+        attributes: &PlatformSpecificEventLoopAttributes,
+    ) -> Result<EventLoop<T>, error::OsError> {
+        // Take over the VT first so the console stops drawing before anything else shows up
+        let vt = if attributes.vt_handling {
+            let vt = VirtualTerminal::open().map_err(|e| {
+                os_error!(OsError::FbDevError(format!("failed to set up VT: {}", e)))
+            })?;
+            Some(Rc::new(vt))
+        } else {
+            None
+        };
+
+        let fb_path = attributes
+            .device_path
+            .clone()
+            .or_else(|| std::env::var("WINIT_FBDEV_PATH").map(PathBuf::from).ok())
             .or_else(|| find_fb_path().ok().flatten())
             .ok_or_else(|| os_error!(OsError::FbDevMisc("failed to compile XKB keymap")))?;
 
+        // Kept open for blanking, which linuxfb doesn't do
+        let fb_file = std::fs::File::open(&fb_path)
+            .map_err(|e| os_error!(OsError::FbDevError(format!("failed to open fbdev device: {}", e))))?;
+
         let fb = linuxfb::Framebuffer::new(fb_path)
             .map_err(|e| os_error!(OsError::FbDevError(format!("failed to open fbdev device: {e:?}"))))?;
 
+        #[cfg(feature = "fbdev-ext")]
+        // Seat events are only recorded by the listener, the event loop acts on them once the
+        // input backend exists
+        let seat_events: Rc<RefCell<Vec<bool>>> = Rc::new(RefCell::new(Vec::new()));
+
+        #[cfg(feature = "fbdev-ext")]
+        let seat = {
+            let t_seat_events = seat_events.clone();
+            let mut s = libseat::Seat::open(
+                move |_, event| {
+                    let enabled = matches!(event, libseat::SeatEvent::Enable);
+                    t_seat_events.borrow_mut().push(enabled);
+                },
+                None,
+            )
+            .map_err(|e| {
+                os_error!(OsError::FbDevError(format!("failed to open libseat: {}", e)))
+            })?;
+
+            // While our seat is not active dispatch it so that the seat will activate
+            while !seat_events.borrow_mut().drain(..).any(|enabled| enabled) {
+                s.dispatch(-1).map_err(|e| {
+                    os_error!(OsError::FbDevError(format!("failed to dispatch seat: {}", e)))
+                })?;
+            }
+            Rc::new(RefCell::new(s))
+        };
+
+        #[cfg(feature = "fbdev-ext")]
+        let seat_name = seat.borrow_mut().name().to_string();
+        #[cfg(not(feature = "fbdev-ext"))]
+        let seat_name = "seat0";
+
+        #[cfg(feature = "fbdev-ext")]
+        // Using our seat to open our input manager allows us to do so unprivileged. The
+        // framebuffer itself is not managed by seats, it still needs the `video` user group.
+        let mut input = input::Libinput::new_with_udev(Interface(seat.clone(), HashMap::new()));
+        #[cfg(not(feature = "fbdev-ext"))]
         // Opening our input manager with no seat means we must do so as root
         // (or be part of the `input` user group)
         let mut input = input::Libinput::new_with_udev(Interface);
-        input.udev_assign_seat("seat0").unwrap();
+
+        input.udev_assign_seat(&seat_name).unwrap();
 
         // XKB allows us to keep track of the state of the keyboard and produce keyboard events
         // very similarly to how a Wayland Compositor would.
@@ -169,6 +263,15 @@ impl<T: 'static> EventLoop<T> {
         // let (disp_width, disp_height) = fb.get_size();
         let (disp_width, disp_height) = (800, 480);
         let fb_id = fb.get_id();
+        let size_mm = fb.get_physical_size();
+        let layout = fb.get_pixel_layout();
+        let pixel_format = FbPixelFormat {
+            bits_per_pixel: fb.get_bytes_per_pixel() * 8,
+            red_bits: layout.red.length,
+            green_bits: layout.green.length,
+            blue_bits: layout.blue.length,
+            alpha_bits: layout.alpha.length,
+        };
 
         let event_loop: calloop::EventLoop<'static, EventSink> =
             calloop::EventLoop::try_new().unwrap();
@@ -194,12 +297,21 @@ impl<T: 'static> EventLoop<T> {
 
         let event_sink = EventSink::new();
 
+        // Whether our VT is in the background or the display is blanked, we must not draw while
+        // the former is and needn't while the latter is.
+        let display_state = FbDisplayState::default();
+        let display_state_clone = display_state.clone();
+        let suspended = display_state.clone();
+
         // Handler of redraw requests.
         handle
             .insert_source(
                 event_loop_awakener_source,
                 move |_event, _metadata, data| {
-                    data.push(Event::RedrawRequested(window_id!()));
+                    // A redraw is sent anyway once the VT is resumed or the display unblanked
+                    if display_state_clone.is_active() {
+                        data.push(Event::RedrawRequested(window_id!()));
+                    }
                 },
             )
             .unwrap();
@@ -212,7 +324,7 @@ impl<T: 'static> EventLoop<T> {
 
         let repeat_loop: calloop::Dispatcher<
             'static,
-            calloop::timer::Timer<(KeyboardInput, Option<char>)>,
+            calloop::timer::Timer<(DeviceId, KeyboardInput, Option<char>)>,
             EventSink,
         > = calloop::Dispatcher::new(
             repeat_handler,
@@ -220,13 +332,13 @@ impl<T: 'static> EventLoop<T> {
                 data.push(Event::WindowEvent {
                     window_id: window_id!(),
                     event: WindowEvent::KeyboardInput {
-                        device_id: DeviceId(platform_impl::DeviceId::FbDev(super::DeviceId)),
-                        input: event.0,
+                        device_id: event.0,
+                        input: event.1,
                         is_synthetic: false,
                     },
                 });
 
-                if let Some(c) = event.1 {
+                if let Some(c) = event.2 {
                     data.push(Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::ReceivedCharacter(c),
@@ -241,6 +353,9 @@ impl<T: 'static> EventLoop<T> {
         // It is an Arc<Mutex<>> so that windows can change the cursor position
         let cursor_arc = Arc::new(Mutex::new(PhysicalPosition::new(0.0, 0.0)));
 
+        // Filled in by the input backend as devices come and go
+        let input_devices = Arc::new(Mutex::new(HashMap::new()));
+
         // Our input handler
         let input_backend: LibinputInputBackend = LibinputInputBackend::new(
             input,
@@ -251,6 +366,8 @@ impl<T: 'static> EventLoop<T> {
             // TODO(compose) Re-enable
             // xkb_compose,
             cursor_arc.clone(),
+            input_devices.clone(),
+            attributes.touch_calibration,
         );
 
         // When an input is received, add it to our EventSink
@@ -262,16 +379,106 @@ impl<T: 'static> EventLoop<T> {
                 },
             );
 
-        handle.register_dispatcher(input_loop).unwrap();
+        handle.register_dispatcher(input_loop.clone()).unwrap();
         handle.register_dispatcher(repeat_loop).unwrap();
 
+        #[cfg(feature = "fbdev-ext")]
+        {
+            let seat_fd = seat.borrow_mut().get_fd().map_err(|e| {
+                os_error!(OsError::FbDevError(format!("failed to get seat fd: {}", e)))
+            })?;
+            let input_loop = input_loop.clone();
+            let suspended = suspended.clone();
+
+            handle
+                .insert_source(
+                    calloop::generic::Generic::new(
+                        seat_fd,
+                        calloop::Interest::READ,
+                        calloop::Mode::Level,
+                    ),
+                    move |_, _, data: &mut EventSink| {
+                        seat.borrow_mut()
+                            .dispatch(0)
+                            .map_err(|e| std::io::Error::from_raw_os_error(e.into()))?;
+
+                        let events: Vec<bool> = seat_events.borrow_mut().drain(..).collect();
+                        for enabled in events {
+                            if enabled {
+                                suspended.set_suspended(false);
+                                // Reopens all input devices through the seat
+                                input_loop.as_source_mut().resume();
+                                data.push(Event::Resumed);
+                                data.push(Event::RedrawRequested(window_id!()));
+                            } else {
+                                suspended.set_suspended(true);
+                                // Close our devices before telling the seat we are done with them
+                                input_loop.as_source_mut().suspend();
+                                data.push(Event::Suspended);
+                                if let Err(e) = seat.borrow_mut().disable() {
+                                    warn!("failed to acknowledge seat disable: {}", e);
+                                }
+                            }
+                        }
+
+                        Ok(calloop::PostAction::Continue)
+                    },
+                )
+                .map_err(|e| {
+                    os_error!(OsError::FbDevError(format!(
+                        "failed to listen for seat events: {}",
+                        e
+                    )))
+                })?;
+        }
+
+        if let Some(ref vt) = vt {
+            vt::insert_source(
+                &handle,
+                vt.clone(),
+                move |event, data: &mut EventSink| match event {
+                    VtEvent::Release => {
+                        suspended.set_suspended(true);
+                        input_loop.as_source_mut().suspend();
+                        data.push(Event::Suspended);
+                    }
+                    VtEvent::Acquire => {
+                        suspended.set_suspended(false);
+                        input_loop.as_source_mut().resume();
+                        data.push(Event::Resumed);
+                        // The console does not keep our framebuffer contents around
+                        data.push(Event::RedrawRequested(window_id!()));
+                    }
+                    VtEvent::Terminate => data.push(Event::WindowEvent {
+                        window_id: window_id!(),
+                        event: WindowEvent::CloseRequested,
+                    }),
+                },
+            )
+            .map_err(|e| {
+                os_error!(OsError::FbDevError(format!(
+                    "failed to listen for VT signals: {}",
+                    e
+                )))
+            })?;
+        }
+
+        let info = FBInfo {
+            size: (disp_width, disp_height),
+            size_mm,
+            name: fb_id,
+            pixel_format,
+        };
+        let scale_factor = info.scale_factor();
+
         let window_target = event_loop::EventLoopWindowTarget {
             p: platform_impl::EventLoopWindowTarget::FbDev(EventLoopWindowTarget {
-                info: FBInfo {
-                    size: (disp_width, disp_height),
-                    name: fb_id,
-                },
+                info,
+                fb_file: Arc::new(fb_file),
+                display_state,
+                scale_factor_override: Arc::new(Mutex::new(None)),
                 cursor_arc,
+                input_devices,
                 event_loop_handle: handle,
                 event_sink,
                 event_loop_awakener,
@@ -285,6 +492,8 @@ impl<T: 'static> EventLoop<T> {
             pending_user_events,
             user_events_sender,
             window_target,
+            _vt: vt,
+            scale_factor,
         })
     }
 
@@ -293,6 +502,8 @@ impl<T: 'static> EventLoop<T> {
         F: FnMut(Event<'_, T>, &event_loop::EventLoopWindowTarget<T>, &mut ControlFlow) + 'static,
     {
         let exit_code = self.run_return(callback);
+        // `process::exit` does not run destructors, make sure the VT gets restored
+        drop(self);
         std::process::exit(exit_code);
     }
 
@@ -304,6 +515,9 @@ impl<T: 'static> EventLoop<T> {
         let pending_user_events = self.pending_user_events.clone();
         let mut event_sink_back_buffer = Vec::new();
 
+        // Windows created before the loop started already know their scale factor
+        self.scale_factor = self.with_window_target(|window_target| window_target.scale_factor());
+
         callback(
             Event::NewEvents(StartCause::Init),
             &self.window_target,
@@ -409,6 +623,27 @@ impl<T: 'static> EventLoop<T> {
                 sticky_exit_callback(event, &self.window_target, &mut control_flow, &mut callback);
             }
 
+            // The scale factor changes when the application overrides it. This can't go through
+            // the event sink, since `ScaleFactorChanged` borrows the new size.
+            let (scale_factor, mut new_inner_size) = self.with_window_target(|window_target| {
+                (window_target.scale_factor(), window_target.info.physical_size())
+            });
+            if scale_factor != self.scale_factor {
+                self.scale_factor = scale_factor;
+                sticky_exit_callback(
+                    Event::WindowEvent {
+                        window_id: window_id!(),
+                        event: WindowEvent::ScaleFactorChanged {
+                            scale_factor,
+                            new_inner_size: &mut new_inner_size,
+                        },
+                    },
+                    &self.window_target,
+                    &mut control_flow,
+                    &mut callback,
+                );
+            }
+
             // Send events cleared.
             sticky_exit_callback(
                 Event::MainEventsCleared,
@@ -5,10 +5,10 @@ use crate::{
         DeviceEvent, ElementState, Event, Force, KeyboardInput, ModifiersState, MouseButton,
         MouseScrollDelta, Touch, TouchPhase, WindowEvent,
     },
-    platform_impl::{self, xkb_keymap},
+    platform_impl::{self, calibrate_touchscreen, xkb_keymap, InputDeviceInfo},
     window::WindowId,
 };
-use input::{DeviceCapability, event::{
+use input::{event::{
     keyboard::KeyboardEventTrait,
     pointer::PointerScrollEvent,
     tablet_pad::{ButtonState, KeyState},
@@ -16,9 +16,10 @@ use input::{DeviceCapability, event::{
     touch::{TouchEventPosition, TouchEventSlot},
 }, LibinputInterface};
 use parking_lot::Mutex;
-#[cfg(feature = "kms-ext")]
-use std::collections::HashMap;
+#[cfg(feature = "fbdev-ext")]
+use std::{cell::RefCell, rc::Rc};
 use std::{
+    collections::HashMap,
     os::unix::prelude::{AsRawFd, FromRawFd, RawFd},
     path::Path,
     sync::Arc,
@@ -45,20 +46,23 @@ macro_rules! window_id {
 }
 
 macro_rules! device_id {
-    () => {
-        to_platform_impl!(DeviceId, super::DeviceId)
+    ($id:expr) => {
+        to_platform_impl!(DeviceId, $id)
     };
 }
 
-#[cfg(feature = "kms-ext")]
-pub struct Interface(pub libseat::Seat, pub HashMap<RawFd, i32>);
-#[cfg(not(feature = "kms-ext"))]
+/// The seat is shared with the event loop, which has to dispatch it to learn when it gets
+/// disabled and enabled.
+#[cfg(feature = "fbdev-ext")]
+pub struct Interface(pub Rc<RefCell<libseat::Seat>>, pub HashMap<RawFd, i32>);
+#[cfg(not(feature = "fbdev-ext"))]
 pub struct Interface;
 
-#[cfg(feature = "kms-ext")]
+#[cfg(feature = "fbdev-ext")]
 impl LibinputInterface for Interface {
     fn open_restricted(&mut self, path: &Path, _flags: i32) -> Result<RawFd, i32> {
         self.0
+            .borrow_mut()
             .open_device(&path)
             .map(|(id, file)| {
                 self.1.insert(file, id);
@@ -68,15 +72,15 @@ impl LibinputInterface for Interface {
     }
 
     fn close_restricted(&mut self, fd: RawFd) {
-        if let Some(dev) = self.1.get(&fd).copied() {
-            self.0.close_device(dev).unwrap();
+        if let Some(dev) = self.1.remove(&fd) {
+            self.0.borrow_mut().close_device(dev).unwrap();
         }
 
         unsafe { std::fs::File::from_raw_fd(fd) };
     }
 }
 
-#[cfg(not(feature = "kms-ext"))]
+#[cfg(not(feature = "fbdev-ext"))]
 impl LibinputInterface for Interface {
     fn open_restricted(&mut self, path: &Path, flags: i32) -> Result<RawFd, i32> {
         use std::os::unix::prelude::*;
@@ -103,11 +107,23 @@ pub struct LibinputInputBackend {
     // TODO(compose) Re-enable
     // xkb_compose: xkb::compose::State,
     token: Token,
-    touch_location: PhysicalPosition<f64>,
+    /// Active touches by seat slot, with the id we gave them and their last location
+    touches: HashMap<u32, (u64, PhysicalPosition<f64>)>,
+    /// Touch events waiting for the end of the current frame
+    pending_touches: Vec<Touch>,
+    next_touch_id: u64,
     screen_size: (u32, u32),
     modifiers: ModifiersState,
     cursor_positon: Arc<Mutex<PhysicalPosition<f64>>>,
-    timer_handle: calloop::timer::TimerHandle<(KeyboardInput, Option<char>)>,
+    timer_handle: calloop::timer::TimerHandle<(DeviceId, KeyboardInput, Option<char>)>,
+    /// Devices we know about by id, shared with the event loop so they can be queried
+    devices: Arc<Mutex<HashMap<u32, InputDeviceInfo>>>,
+    /// Ids of the devices in `devices` by sysname
+    device_ids: HashMap<String, u32>,
+    next_device_id: u32,
+    keyboard_present: bool,
+    /// See `EventLoopBuilderExtUnix::with_touch_calibration`.
+    touch_calibration: Option<[f32; 6]>,
 }
 
 impl LibinputInputBackend {
@@ -116,27 +132,55 @@ impl LibinputInputBackend {
     pub fn new(
         context: input::Libinput,
         screen_size: (u32, u32),
-        timer_handle: calloop::timer::TimerHandle<(KeyboardInput, Option<char>)>,
+        timer_handle: calloop::timer::TimerHandle<(DeviceId, KeyboardInput, Option<char>)>,
         xkb_ctx: xkb::State,
         xkb_keymap: xkb::Keymap,
         // TODO(compose) Re-enable
         // xkb_compose: xkb::compose::State,
         cursor_positon: Arc<Mutex<PhysicalPosition<f64>>>,
+        devices: Arc<Mutex<HashMap<u32, InputDeviceInfo>>>,
+        touch_calibration: Option<[f32; 6]>,
     ) -> Self {
         LibinputInputBackend {
             context,
             token: Token::invalid(),
-            touch_location: PhysicalPosition::new(0.0, 0.0),
+            touches: HashMap::new(),
+            pending_touches: Vec::new(),
+            next_touch_id: 0,
             modifiers: ModifiersState::empty(),
             cursor_positon,
             screen_size,
             timer_handle,
             xkb_ctx,
             xkb_keymap,
+            devices,
+            device_ids: HashMap::new(),
+            // 0 is the dummy id
+            next_device_id: 1,
+            keyboard_present: false,
+            touch_calibration,
             // TODO(compose) Re-enable
             // xkb_compose,
         }
     }
+
+    /// Stop reading from input devices, e.g. while our VT is in the background.
+    pub fn suspend(&mut self) {
+        self.timer_handle.cancel_all_timeouts();
+        self.context.suspend();
+        // No frame will follow for these, and the fingers are gone by the time we resume
+        self.touches.clear();
+        self.pending_touches.clear();
+    }
+
+    /// Re-open the input devices closed by [`suspend`](Self::suspend).
+    ///
+    /// libinput re-adds every device on resume, so they go through [`configure_device`] again.
+    pub fn resume(&mut self) {
+        if self.context.resume().is_err() {
+            warn!("failed to resume libinput context");
+        }
+    }
 }
 
 impl AsRawFd for LibinputInputBackend {
@@ -146,21 +190,25 @@ impl AsRawFd for LibinputInputBackend {
 }
 
 macro_rules! handle_device_event {
-    ($ev:expr,$callback:expr) => {
+    ($self:expr,$ev:expr,$callback:expr,$device_id:expr) => {{
         match $ev {
             input::event::DeviceEvent::Added(_) => {
                 $callback(
                     Event::DeviceEvent {
-                        device_id: device_id!(),
+                        device_id: device_id!($device_id),
                         event: DeviceEvent::Added,
                     },
                     &mut (),
                 );
             }
-            input::event::DeviceEvent::Removed(_) => {
+            input::event::DeviceEvent::Removed(e) => {
+                if let Some(id) = $self.device_ids.remove(e.device().sysname()) {
+                    $self.devices.lock().remove(&id);
+                }
+
                 $callback(
                     Event::DeviceEvent {
-                        device_id: device_id!(),
+                        device_id: device_id!($device_id),
                         event: DeviceEvent::Removed,
                     },
                     &mut (),
@@ -168,102 +216,118 @@ macro_rules! handle_device_event {
             }
             _ => {}
         }
-    };
-}
 
-macro_rules! handle_touch_event {
-    ($self:expr,$ev:expr,$callback:expr) => {
-        match $ev {
-            input::event::TouchEvent::Up(e) => $callback(
+        let keyboard_present = $self.devices.lock().values().any(|info| info.keyboard);
+        if keyboard_present != $self.keyboard_present {
+            $self.keyboard_present = keyboard_present;
+
+            $callback(
                 Event::WindowEvent {
                     window_id: window_id!(),
-                    event: WindowEvent::Touch(Touch {
-                        device_id: device_id!(),
-                        phase: TouchPhase::Ended,
-                        location: $self.touch_location,
-                        force: None,
-                        id: e.slot().unwrap() as u64,
-                    }),
+                    event: WindowEvent::KeyboardPresenceChanged(keyboard_present),
                 },
                 &mut (),
-            ),
+            );
+        }
+    }};
+}
+
+macro_rules! handle_touch_event {
+    ($self:expr,$ev:expr,$callback:expr,$device_id:expr) => {
+        match $ev {
             input::event::TouchEvent::Down(e) => {
-                $self.touch_location.x = e.x_transformed($self.screen_size.0);
-                $self.touch_location.y = e.y_transformed($self.screen_size.1);
+                let location = PhysicalPosition::new(
+                    e.x_transformed($self.screen_size.0),
+                    e.y_transformed($self.screen_size.1),
+                );
 
-                $callback(
-                    Event::WindowEvent {
-                        window_id: window_id!(),
-                        event: WindowEvent::Touch(Touch {
-                            device_id: device_id!(),
-                            phase: TouchPhase::Started,
-                            location: $self.touch_location,
-                            force: None,
-                            id: e.slot().unwrap() as u64,
-                        }),
-                    },
-                    &mut (),
-                )
+                // Slots are reused as soon as a finger is lifted, so every new touch gets its own
+                // id that stays the same until that finger is lifted again.
+                let id = $self.next_touch_id;
+                $self.next_touch_id = $self.next_touch_id.wrapping_add(1);
+                $self.touches.insert(e.seat_slot(), (id, location));
+
+                $self.pending_touches.push(Touch {
+                    device_id: device_id!($device_id),
+                    phase: TouchPhase::Started,
+                    location,
+                    force: None,
+                    id,
+                });
             }
             input::event::TouchEvent::Motion(e) => {
-                $self.touch_location.x = e.x_transformed($self.screen_size.0);
-                $self.touch_location.y = e.y_transformed($self.screen_size.1);
+                if let Some((id, location)) = $self.touches.get_mut(&e.seat_slot()) {
+                    location.x = e.x_transformed($self.screen_size.0);
+                    location.y = e.y_transformed($self.screen_size.1);
+
+                    $self.pending_touches.push(Touch {
+                        device_id: device_id!($device_id),
+                        phase: TouchPhase::Moved,
+                        location: *location,
+                        force: None,
+                        id: *id,
+                    });
+                }
+            }
+            input::event::TouchEvent::Up(e) => {
+                if let Some((id, location)) = $self.touches.remove(&e.seat_slot()) {
+                    $self.pending_touches.push(Touch {
+                        device_id: device_id!($device_id),
+                        phase: TouchPhase::Ended,
+                        location,
+                        force: None,
+                        id,
+                    });
+                }
+            }
+            input::event::TouchEvent::Cancel(e) => {
+                if let Some((id, location)) = $self.touches.remove(&e.seat_slot()) {
+                    $self.pending_touches.push(Touch {
+                        device_id: device_id!($device_id),
+                        phase: TouchPhase::Cancelled,
+                        location,
+                        force: None,
+                        id,
+                    });
+                }
+            }
+            // A frame doesn't mean a touch ended, it marks the end of one hardware sample.
+            // Everything buffered since the last frame happened at the same time, so it is
+            // delivered together.
+            input::event::TouchEvent::Frame(_) => {
+                for touch in $self.pending_touches.drain(..) {
+                    $callback(
+                        Event::WindowEvent {
+                            window_id: window_id!(),
+                            event: WindowEvent::Touch(touch),
+                        },
+                        &mut (),
+                    );
+                }
 
                 $callback(
                     Event::WindowEvent {
                         window_id: window_id!(),
-                        event: WindowEvent::Touch(Touch {
-                            device_id: device_id!(),
-                            phase: TouchPhase::Moved,
-                            location: $self.touch_location,
-                            force: None,
-                            id: e.slot().unwrap() as u64,
-                        }),
+                        event: WindowEvent::TouchFrame {
+                            device_id: device_id!($device_id),
+                        },
                     },
                     &mut (),
                 );
             }
-            input::event::TouchEvent::Cancel(e) => $callback(
-                Event::WindowEvent {
-                    window_id: window_id!(),
-                    event: WindowEvent::Touch(Touch {
-                        device_id: device_id!(),
-                        phase: TouchPhase::Cancelled,
-                        location: $self.touch_location,
-                        force: None,
-                        id: e.slot().unwrap() as u64,
-                    }),
-                },
-                &mut (),
-            ),
-            // TODO(nulldev) tt changes: Frame doesn't mean touch ended, it just means that it's the
-            //   end of one sample
-            /*input::event::TouchEvent::Frame(_) => $callback(
-                Event::WindowEvent {
-                    window_id: window_id!(),
-                    event: WindowEvent::Touch(Touch {
-                        device_id: device_id!(),
-                        phase: TouchPhase::Ended,
-                        location: $self.touch_location,
-                        force: None,
-                        id: 0, // e.slot().unwrap() as u64,
-                    }),
-                },
-                &mut (),
-            ),*/
             _ => {}
         }
     };
 }
 
 macro_rules! handle_tablet_tool_event {
-    ($self:expr,$ev:expr,$callback:expr) => {
+    ($self:expr,$ev:expr,$callback:expr,$device_id:expr) => {
         match $ev {
             input::event::TabletToolEvent::Tip(e) => $callback(
                 Event::WindowEvent {
                     window_id: window_id!(),
                     event: WindowEvent::Touch(Touch {
-                        device_id: device_id!(),
+                        device_id: device_id!($device_id),
                         phase: match e.tip_state() {
                             TipState::Down => TouchPhase::Started,
                             TipState::Up => TouchPhase::Ended,
@@ -287,7 +351,7 @@ macro_rules! handle_tablet_tool_event {
                     Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::MouseInput {
-                            device_id: device_id!(),
+                            device_id: device_id!($device_id),
                             state: match e.button_state() {
                                 ButtonState::Pressed => ElementState::Pressed,
                                 ButtonState::Released => ElementState::Released,
@@ -305,7 +369,7 @@ macro_rules! handle_tablet_tool_event {
 
                 $callback(
                     Event::DeviceEvent {
-                        device_id: device_id!(),
+                        device_id: device_id!($device_id),
                         event: DeviceEvent::Button {
                             button: e.button(),
                             state: match e.button_state() {
@@ -323,7 +387,7 @@ macro_rules! handle_tablet_tool_event {
 }
 
 macro_rules! handle_pointer_event {
-    ($self:expr,$ev:expr,$callback:expr) => {
+    ($self:expr,$ev:expr,$callback:expr,$device_id:expr) => {
         match $ev {
             input::event::PointerEvent::Motion(e) => {
                 let mut lock = $self.cursor_positon.lock();
@@ -338,7 +402,7 @@ macro_rules! handle_pointer_event {
                     Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::CursorMoved {
-                            device_id: device_id!(),
+                            device_id: device_id!($device_id),
                             position: *lock,
                             modifiers: $self.modifiers,
                         },
@@ -348,7 +412,7 @@ macro_rules! handle_pointer_event {
 
                 $callback(
                     Event::DeviceEvent {
-                        device_id: device_id!(),
+                        device_id: device_id!($device_id),
                         event: DeviceEvent::MouseMotion {
                             delta: (e.dx(), e.dy()),
                         },
@@ -362,7 +426,7 @@ macro_rules! handle_pointer_event {
                     Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::MouseInput {
-                            device_id: device_id!(),
+                            device_id: device_id!($device_id),
                             state: match e.button_state() {
                                 ButtonState::Pressed => ElementState::Pressed,
                                 ButtonState::Released => ElementState::Released,
@@ -380,7 +444,7 @@ macro_rules! handle_pointer_event {
 
                 $callback(
                     Event::DeviceEvent {
-                        device_id: device_id!(),
+                        device_id: device_id!($device_id),
                         event: DeviceEvent::Button {
                             button: e.button(),
                             state: match e.button_state() {
@@ -400,7 +464,7 @@ macro_rules! handle_pointer_event {
                     Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::MouseWheel {
-                            device_id: device_id!(),
+                            device_id: device_id!($device_id),
                             delta: MouseScrollDelta::LineDelta(
                                 if e.has_axis(Axis::Horizontal) {
                                     e.scroll_value(Axis::Horizontal) as f32
@@ -428,7 +492,7 @@ macro_rules! handle_pointer_event {
                     Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::MouseWheel {
-                            device_id: device_id!(),
+                            device_id: device_id!($device_id),
                             delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(
                                 if e.has_axis(Axis::Horizontal) {
                                     e.scroll_value(Axis::Horizontal)
@@ -460,7 +524,7 @@ macro_rules! handle_pointer_event {
                     Event::WindowEvent {
                         window_id: window_id!(),
                         event: WindowEvent::CursorMoved {
-                            device_id: device_id!(),
+                            device_id: device_id!($device_id),
                             position: *lock,
                             modifiers: $self.modifiers,
                         },
@@ -474,7 +538,7 @@ macro_rules! handle_pointer_event {
 }
 
 macro_rules! handle_keyboard_event {
-    ($self:expr,$ev:expr,$callback:expr) => {{
+    ($self:expr,$ev:expr,$callback:expr,$device_id:expr) => {{
         let state = match $ev.key_state() {
             KeyState::Pressed => ElementState::Pressed,
             KeyState::Released => ElementState::Released,
@@ -512,7 +576,7 @@ macro_rules! handle_keyboard_event {
             Event::WindowEvent {
                 window_id: window_id!(),
                 event: WindowEvent::KeyboardInput {
-                    device_id: device_id!(),
+                    device_id: device_id!($device_id),
                     input,
                     is_synthetic: false,
                 },
@@ -543,9 +607,10 @@ macro_rules! handle_keyboard_event {
                     let ch = $self.xkb_ctx.key_get_utf8(key_offset).chars().next();
 
                     if should_repeat {
-                        $self
-                            .timer_handle
-                            .add_timeout(Duration::from_millis(REPEAT_DELAY), (input, ch));
+                        $self.timer_handle.add_timeout(
+                            Duration::from_millis(REPEAT_DELAY),
+                            (device_id!($device_id), input, ch),
+                        );
                     }
 
                     if let Some(c) = ch {
@@ -655,32 +720,42 @@ impl EventSource for LibinputInputBackend {
         if token == self.token {
             self.context.dispatch()?;
 
-            for event in &mut self.context {
-                // TODO(nulldev) tt changes
-                match &event {
-                    input::Event::Device(ev) => {
-                        match ev {
-                            input::event::DeviceEvent::Added(_) => {
-                                let mut dev = ev.device();
-                                if dev.name() == "tlsc6x_dbg" {
-                                    // let calib_matrix = dev.config_calibration_matrix();
-                                    dev.config_calibration_set_matrix([0.0, 1.0, 0.0, -1.0, 0.0, 1.0]).unwrap();
-                                    // println!("Mat: {:?}", calib_matrix);
-                                    println!("Configured touchscreen rotation!");
-                                }
-                            }
-                            &_ => {}
-                        }
+            // Collected first so the handlers below can use the rest of `self`
+            let events: Vec<input::Event> = self.context.by_ref().collect();
+
+            for event in events {
+                if let input::Event::Device(input::event::DeviceEvent::Added(_)) = event {
+                    let mut device = event.device();
+                    let id = self.next_device_id;
+                    self.next_device_id = self.next_device_id.wrapping_add(1);
+                    self.device_ids.insert(device.sysname().to_string(), id);
+                    self.devices
+                        .lock()
+                        .insert(id, InputDeviceInfo::from_libinput(&device));
+                    if let Some(matrix) = self.touch_calibration {
+                        calibrate_touchscreen(&mut device, matrix);
                     }
-                    &_ => {}
                 }
 
+                let device_id = super::DeviceId(
+                    self.device_ids
+                        .get(event.device().sysname())
+                        .copied()
+                        .unwrap_or(0),
+                );
+
                 match event {
-                    input::Event::Device(ev) => handle_device_event!(ev, callback),
-                    input::Event::Touch(ev) => handle_touch_event!(self, ev, callback),
-                    input::Event::Tablet(ev) => handle_tablet_tool_event!(self, ev, callback),
-                    input::Event::Pointer(ev) => handle_pointer_event!(self, ev, callback),
-                    input::Event::Keyboard(ev) => handle_keyboard_event!(self, ev, callback),
+                    input::Event::Device(ev) => handle_device_event!(self, ev, callback, device_id),
+                    input::Event::Touch(ev) => handle_touch_event!(self, ev, callback, device_id),
+                    input::Event::Tablet(ev) => {
+                        handle_tablet_tool_event!(self, ev, callback, device_id)
+                    }
+                    input::Event::Pointer(ev) => {
+                        handle_pointer_event!(self, ev, callback, device_id)
+                    }
+                    input::Event::Keyboard(ev) => {
+                        handle_keyboard_event!(self, ev, callback, device_id)
+                    }
                     _ => {}
                 }
             }
@@ -4,14 +4,19 @@ pub mod event_loop;
 pub mod input;
 pub mod window;
 use crate::{monitor, platform_impl};
+use crate::platform_impl::{calc_scale_factor, scale_factor_from_env};
 pub use event_loop::EventLoop;
 pub use event_loop::EventLoopProxy;
 pub use event_loop::EventLoopWindowTarget;
 use std::os::unix;
 use std::os::unix::prelude::FromRawFd;
 use std::sync::Arc;
+use parking_lot::Mutex;
 pub use window::Window;
 
+/// Overrides the scale factor computed from the physical size of the framebuffer.
+pub const SCALE_FACTOR_ENV_VAR: &str = "WINIT_FBDEV_SCALE_FACTOR";
+
 #[derive(Debug, Clone)]
 /// A simple wrapper for a device node.
 pub struct Card(pub(crate) Arc<i32>);
@@ -30,26 +35,84 @@ impl Drop for Card {
     }
 }
 
+/// Identifies an input device for as long as it is plugged in. Ids are never reused.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct DeviceId;
+pub struct DeviceId(pub(crate) u32);
 
 #[allow(dead_code)]
 impl DeviceId {
     pub const unsafe fn dummy() -> Self {
-        DeviceId
+        DeviceId(0)
+    }
+}
+
+/// The pixel format of a framebuffer, an EGL config has to match it to draw to the framebuffer
+/// efficiently and with the right colours.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FbPixelFormat {
+    pub bits_per_pixel: u32,
+    pub red_bits: u32,
+    pub green_bits: u32,
+    pub blue_bits: u32,
+    /// 0 if the framebuffer has no alpha channel
+    pub alpha_bits: u32,
+}
+
+/// Whether the display behind a fbdev window can currently be drawn to. The event loop tracks
+/// VT and seat switches, the window tracks blanking.
+#[derive(Debug, Clone, Default)]
+pub struct FbDisplayState(Arc<Mutex<DisplayState>>);
+
+#[derive(Debug, Default)]
+struct DisplayState {
+    suspended: bool,
+    blanked: bool,
+}
+
+impl FbDisplayState {
+    /// Whether our VT or seat is in the background, the framebuffer belongs to someone else then.
+    pub fn is_suspended(&self) -> bool {
+        self.0.lock().suspended
+    }
+
+    /// Whether the display was blanked with `WindowExtUnix::fbdev_set_blanked`.
+    pub fn is_blanked(&self) -> bool {
+        self.0.lock().blanked
+    }
+
+    /// Whether anything drawn now would show up.
+    pub fn is_active(&self) -> bool {
+        let state = self.0.lock();
+        !state.suspended && !state.blanked
+    }
+
+    pub(crate) fn set_suspended(&self, suspended: bool) {
+        self.0.lock().suspended = suspended;
+    }
+
+    pub(crate) fn set_blanked(&self, blanked: bool) {
+        self.0.lock().blanked = blanked;
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub(crate) struct FBInfo {
     size: (u32, u32),
+    /// Physical size in millimeters, as reported by the driver. May be (0, 0).
+    size_mm: (u32, u32),
     name: String,
+    pixel_format: FbPixelFormat,
 }
 
 impl FBInfo {
     fn physical_size(&self) -> PhysicalSize<u32> {
         PhysicalSize::new(self.size.0, self.size.1)
     }
+
+    fn scale_factor(&self) -> f64 {
+        scale_factor_from_env(SCALE_FACTOR_ENV_VAR)
+            .unwrap_or_else(|| calc_scale_factor(self.size, self.size_mm))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -81,7 +144,7 @@ impl MonitorHandle {
 
     #[inline]
     pub fn scale_factor(&self) -> f64 {
-        1.0
+        self.info.scale_factor()
     }
 
     #[inline]
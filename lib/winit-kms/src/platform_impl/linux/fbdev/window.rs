@@ -15,24 +15,40 @@ use crate::{
     platform_impl,
     window::{CursorIcon, Fullscreen, WindowAttributes},
 };
-use crate::platform_impl::fbdev::FBInfo;
+use crate::platform_impl::fbdev::{FBInfo, FbDisplayState};
+
+// <linux/fb.h>
+const FBIOBLANK: libc::c_ulong = 0x4611;
+const FB_BLANK_UNBLANK: libc::c_int = 0;
+const FB_BLANK_POWERDOWN: libc::c_int = 4;
 
 pub struct Window {
     ping: calloop::ping::Ping,
     cursor: Arc<Mutex<PhysicalPosition<f64>>>,
     info: FBInfo,
+    fb_file: Arc<std::fs::File>,
+    display_state: FbDisplayState,
+    scale_factor_override: Arc<Mutex<Option<f64>>>,
 }
 
 impl Window {
     pub fn new<T>(
         event_loop_window_target: &super::event_loop::EventLoopWindowTarget<T>,
         _attributes: WindowAttributes,
-        _platform_attributes: platform_impl::PlatformSpecificWindowBuilderAttributes,
+        platform_attributes: platform_impl::PlatformSpecificWindowBuilderAttributes,
     ) -> Result<Self, error::OsError> {
+        let scale_factor_override = event_loop_window_target.scale_factor_override.clone();
+        if let Some(scale_factor) = platform_attributes.scale_factor {
+            *scale_factor_override.lock() = Some(scale_factor);
+        }
+
         Ok(Self {
             cursor: event_loop_window_target.cursor_arc.clone(),
             ping: event_loop_window_target.event_loop_awakener.clone(),
             info: event_loop_window_target.info.clone(),
+            fb_file: event_loop_window_target.fb_file.clone(),
+            display_state: event_loop_window_target.display_state.clone(),
+            scale_factor_override,
         })
     }
     #[inline]
@@ -114,12 +130,22 @@ impl Window {
 
     #[inline]
     pub fn scale_factor(&self) -> f64 {
-        1.0
+        self.scale_factor_override
+            .lock()
+            .unwrap_or_else(|| self.info.scale_factor())
+    }
+
+    /// Replaces the computed scale factor, `None` goes back to the computed one.
+    #[inline]
+    pub fn set_scale_factor_override(&self, scale_factor: Option<f64>) {
+        *self.scale_factor_override.lock() = scale_factor;
+        // Wake the event loop up so it notices the change
+        self.ping.ping();
     }
 
     #[inline]
     pub fn set_cursor_position(&self, position: Position) -> Result<(), ExternalError> {
-        *self.cursor.lock() = position.to_physical(1.0);
+        *self.cursor.lock() = position.to_physical(self.scale_factor());
         Ok(())
     }
 
@@ -191,6 +217,56 @@ impl Window {
         rwh
     }
 
+    #[inline]
+    pub fn pixel_format(&self) -> super::FbPixelFormat {
+        self.info.pixel_format
+    }
+
+    #[inline]
+    pub fn display_state(&self) -> FbDisplayState {
+        self.display_state.clone()
+    }
+
+    /// Powers the display down or back up. No redraws are sent while it is blanked, one is sent
+    /// when it is unblanked.
+    pub fn set_blanked(&self, blanked: bool) -> Result<(), ExternalError> {
+        let level = if blanked { FB_BLANK_POWERDOWN } else { FB_BLANK_UNBLANK };
+        if unsafe { libc::ioctl(self.fb_file.as_raw_fd(), FBIOBLANK as _, level) } < 0 {
+            return Err(ExternalError::Os(os_error!(platform_impl::OsError::FbDevError(
+                format!("failed to blank the display: {}", std::io::Error::last_os_error())
+            ))));
+        }
+
+        self.display_state.set_blanked(blanked);
+        if !blanked {
+            self.request_redraw();
+        }
+        Ok(())
+    }
+
+    /// The brightness of the backlight from 0 to 1, `None` without a backlight.
+    pub fn backlight(&self) -> Option<f32> {
+        let dir = backlight_dir()?;
+        let max_brightness = read_sysfs_u32(&dir.join("max_brightness"))?;
+        let brightness = read_sysfs_u32(&dir.join("brightness"))?;
+        (max_brightness > 0).then(|| brightness as f32 / max_brightness as f32)
+    }
+
+    /// Sets the brightness of the backlight from 0 to 1. Some panels turn off at 0.
+    pub fn set_backlight(&self, brightness: f32) -> Result<(), ExternalError> {
+        let dir = backlight_dir()
+            .ok_or_else(|| ExternalError::NotSupported(NotSupportedError::new()))?;
+        let max_brightness = read_sysfs_u32(&dir.join("max_brightness"))
+            .ok_or_else(|| ExternalError::NotSupported(NotSupportedError::new()))?;
+        let level = (brightness.clamp(0.0, 1.0) * max_brightness as f32).round() as u32;
+        if let Err(err) = std::fs::write(dir.join("brightness"), level.to_string()) {
+            return Err(ExternalError::Os(os_error!(platform_impl::OsError::FbDevError(
+                format!("failed to set the backlight: {}", err)
+            ))));
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn primary_monitor(&self) -> Option<MonitorHandle> {
         self.current_monitor().map(|m| MonitorHandle {
@@ -198,3 +274,17 @@ impl Window {
         })
     }
 }
+
+/// The first backlight in sysfs, on a device with one panel that is the panel's.
+fn backlight_dir() -> Option<std::path::PathBuf> {
+    let mut dirs: Vec<_> = std::fs::read_dir("/sys/class/backlight")
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    dirs.sort();
+    dirs.into_iter().next()
+}
+
+fn read_sysfs_u32(path: &std::path::Path) -> Option<u32> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
@@ -1,7 +1,7 @@
 use parking_lot::Mutex;
 use std::{
-    cell::RefCell,
-    collections::VecDeque,
+    cell::{Cell, RefCell},
+    collections::{HashMap, HashSet, VecDeque},
     marker::PhantomData,
     path::{Path, PathBuf},
     rc::Rc,
@@ -9,7 +9,7 @@ use std::{
     time::{Duration, Instant},
 };
 #[cfg(feature = "kms-ext")]
-use std::{collections::HashMap, sync::atomic::AtomicBool};
+use std::sync::atomic::AtomicBool;
 use udev::Enumerator;
 use xkbcommon::xkb;
 
@@ -21,18 +21,24 @@ use sctk::reexports::calloop;
 use crate::error;
 
 use crate::{
-    dpi::PhysicalPosition,
+    dpi::{PhysicalPosition, PhysicalSize},
     event::{DeviceId, Event, KeyboardInput, StartCause, WindowEvent},
     event_loop::{self, ControlFlow, EventLoopClosed},
     monitor::MonitorHandle,
     platform::unix::Card,
-    platform_impl::{self, platform::sticky_exit_callback, OsError},
+    platform_impl::{
+        self,
+        platform::sticky_exit_callback,
+        vt::{self, VirtualTerminal, VtEvent},
+        InputDeviceInfo, OsError, PlatformSpecificEventLoopAttributes,
+    },
     window::WindowId,
 };
 
 use super::{
+    connected_connectors, find_crtc, find_plane,
     input::{Interface, LibinputInputBackend, REPEAT_RATE},
-    MODE,
+    preferred_mode, Output, Outputs, Vblank,
 };
 
 macro_rules! to_platform_impl {
@@ -42,8 +48,8 @@ macro_rules! to_platform_impl {
 }
 
 macro_rules! window_id {
-    () => {
-        to_platform_impl!(WindowId, super::WindowId)
+    ($id:expr) => {
+        to_platform_impl!(WindowId, $id)
     };
 }
 
@@ -52,18 +58,33 @@ macro_rules! window_id {
 type EventSink = Vec<Event<'static, ()>>;
 
 pub struct EventLoopWindowTarget<T> {
-    /// Drm Connector
+    /// Drm Connector of the primary output, windows are shown on it unless they ask otherwise
     pub connector: connector::Info,
 
-    /// Drm crtc
+    /// Drm crtc of the primary output
     pub crtc: crtc::Info,
 
-    /// Drm plane
+    /// Drm plane of the primary output
     pub plane: plane::Handle,
 
+    /// Preferred mode of the primary output
+    pub mode: Mode,
+
+    /// Whether the device supports atomic modesetting, the legacy API is used if it doesn't
+    pub(crate) atomic: bool,
+
+    /// Outputs that have a window
+    pub(crate) outputs: Outputs,
+
+    /// Windows that asked to be redrawn since the last time the event loop woke up
+    pub(crate) pending_redraws: Arc<Mutex<HashSet<super::WindowId>>>,
+
     /// Allows window to edit cursor position
     pub(crate) cursor_arc: Arc<Mutex<PhysicalPosition<f64>>>,
 
+    /// Input devices currently plugged in, by device id
+    pub(crate) input_devices: Arc<Mutex<HashMap<u32, InputDeviceInfo>>>,
+
     /// Drm device
     pub device: Card,
 
@@ -79,78 +100,82 @@ pub struct EventLoopWindowTarget<T> {
 }
 
 impl<T> EventLoopWindowTarget<T> {
+    #[inline]
+    pub fn input_device_info(&self, device_id: super::DeviceId) -> Option<InputDeviceInfo> {
+        self.input_devices.lock().get(&device_id.0).cloned()
+    }
+
+    #[inline]
+    pub fn has_keyboard(&self) -> bool {
+        self.input_devices.lock().values().any(|info| info.keyboard)
+    }
+
+    /// The current mode of the primary output.
+    pub(crate) fn primary_mode(&self) -> Mode {
+        self.outputs
+            .lock()
+            .iter()
+            .find(|output| output.connector.handle() == self.connector.handle())
+            .map_or(self.mode, |output| output.mode)
+    }
+
     #[inline]
     pub fn primary_monitor(&self) -> Option<MonitorHandle> {
         Some(MonitorHandle {
-            inner: platform_impl::MonitorHandle::Kms(super::MonitorHandle {
-                connector: self.connector.clone(),
-                name: (*MODE.lock())?.name().to_string_lossy().into_owned(),
-            }),
+            inner: platform_impl::MonitorHandle::Kms(super::MonitorHandle::new(
+                self.connector.clone(),
+            )),
         })
     }
 
     #[inline]
     pub fn available_monitors(&self) -> VecDeque<super::MonitorHandle> {
-        if let Some(mode) = *MODE.lock() {
-            self.device
-                .resource_handles()
-                .unwrap()
-                .connectors()
-                .iter()
-                .map(|f| super::MonitorHandle {
-                    connector: self.device.get_connector(*f).unwrap(),
-                    name: mode.name().to_string_lossy().into_owned(),
-                })
-                .collect()
-        } else {
-            VecDeque::new()
-        }
+        connected_connectors(&self.device)
+            .into_iter()
+            .map(super::MonitorHandle::new)
+            .collect()
     }
 }
 
-fn find_plane(
-    planes: PlaneResourceHandles,
-    res: ResourceHandles,
-    crtc: &crtc::Info,
-    drm: &Card,
-) -> plane::Handle {
-    let (p_better_planes, p_compatible_planes): (
-        // The primary planes available to us
-        Vec<plane::Handle>,
-        // Other, not-ideal planes that are however useable
-        Vec<plane::Handle>,
-    ) = planes
-        .planes()
+/// Input devices are not tied to an output, so their events go to the first window.
+fn input_window_id(outputs: &Outputs) -> WindowId {
+    let id = outputs
+        .lock()
+        .first()
+        .map_or(super::WindowId(0), |output| output.window_id);
+    window_id!(id)
+}
+
+fn window_ids(outputs: &Outputs) -> Vec<WindowId> {
+    outputs
+        .lock()
         .iter()
-        .filter(|&&plane| {
-            // Get the plane info from a handle
-            drm.get_plane(plane)
-                .map(|plane_info| {
-                    let compatible_crtcs = res.filter_crtcs(plane_info.possible_crtcs());
-                    // Makes sure that the plane can be used with the CRTC we selected earlier
-                    compatible_crtcs.contains(&crtc.handle())
-                })
-                .unwrap_or(false)
-        })
-        .partition(|&&plane| {
-            // Get the plane properties from a handle
-            if let Ok(props) = drm.get_properties(plane) {
-                let (ids, vals) = props.as_props_and_values();
-                for (&id, &val) in ids.iter().zip(vals.iter()) {
-                    if let Ok(info) = drm.get_property(id) {
-                        // Checks if the plane is a primary plane, and returns true if it is,
-                        // if not it returns false
-                        if info.name().to_str().map(|x| x == "type").unwrap_or(false) {
-                            return val == (PlaneType::Primary as u32).into();
-                        }
-                    }
-                }
-            }
-            false
-        });
+        .map(|output| window_id!(output.window_id))
+        .collect()
+}
+
+/// The device number of `card`, to tell its udev events from those of other cards.
+fn card_devnum(card: &Card) -> Option<libc::dev_t> {
+    use std::os::unix::prelude::AsRawFd;
 
-    // Get the first (best) plane we find, or the first compatibile plane
-    *p_better_planes.get(0).unwrap_or(&p_compatible_planes[0])
+    let mut stat = std::mem::MaybeUninit::<libc::stat>::uninit();
+    if unsafe { libc::fstat(card.as_raw_fd(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    Some(unsafe { stat.assume_init() }.st_rdev)
+}
+
+/// How long to hold the next redraw of `output` back after a flip landed on `vblank`, so that
+/// the flip after it lands `swap_interval` vblanks later. `None` for intervals below 2.
+fn pace_delay(output: &Output, vblank: Vblank) -> Option<Duration> {
+    let interval = output.page_flips.swap_interval();
+    if interval < 2 {
+        return None;
+    }
+    let refresh = Duration::from_secs(1) / output.mode.vrefresh().max(1);
+    // Just past the vblank before the one we want, so the flip lands on the next
+    let due = vblank.time + refresh * (interval - 1) + Duration::from_millis(1);
+    Some(due.saturating_sub(super::monotonic_now()))
 }
 
 fn find_card_path(seat_name: &str) -> Result<PathBuf, error::OsError> {
@@ -223,10 +248,28 @@ pub struct EventLoop<T: 'static> {
 
     /// Window target.
     window_target: event_loop::EventLoopWindowTarget<T>,
+
+    /// The VT we took over, restored when the event loop is dropped.
+    _vt: Option<Rc<VirtualTerminal>>,
+
+    /// The scale factor and size each window last told the application about.
+    window_states: HashMap<super::WindowId, (f64, PhysicalSize<u32>)>,
 }
 
 impl<T: 'static> EventLoop<T> {
-    pub fn new() -> Result<EventLoop<T>, error::OsError> {
+    pub fn new(
+        attributes: &PlatformSpecificEventLoopAttributes,
+    ) -> Result<EventLoop<T>, error::OsError> {
+        // Take over the VT first so the console stops drawing before anything else shows up
+        let vt = if attributes.vt_handling {
+            let vt = VirtualTerminal::open().map_err(|e| {
+                os_error!(OsError::KmsError(format!("failed to set up VT: {}", e)))
+            })?;
+            Some(Rc::new(vt))
+        } else {
+            None
+        };
+
         #[cfg(feature = "kms-ext")]
         // When we create the seat here, we should probably wait for it to become active before we
         // use it.
@@ -265,9 +308,12 @@ impl<T: 'static> EventLoop<T> {
 
         // find_card_path uses `udev` to enumerate the cards that are currently available, and then
         // choose the first (usually perferred) one
-        let card_path = std::env::var("WINIT_DRM_CARD")
-            .ok()
-            .map_or_else(|| find_card_path(seat_name), |p| Ok(Into::into(p)))?;
+        let card_path = match attributes.device_path.clone() {
+            Some(path) => path,
+            None => std::env::var("WINIT_DRM_CARD")
+                .ok()
+                .map_or_else(|| find_card_path(seat_name), |p| Ok(Into::into(p)))?,
+        };
 
         #[cfg(feature = "kms-ext")]
         // Opening the card using our seat allows us to do so unprivallaged
@@ -350,15 +396,34 @@ impl<T: 'static> EventLoop<T> {
         })?;
         let xkb_compose = xkb::compose::State::new(&compose_table, xkb::compose::STATE_NO_FLAGS);
 
-        // Allows use to use the non-legacy atomic system
-        drm::Device::set_client_capability(&drm, drm::ClientCapability::Atomic, true).map_err(
-            |e| {
-                os_error!(OsError::KmsError(format!(
-                    "drm device does not support atomic modesetting :{}",
+        // Allows use to use the non-legacy atomic system, older kernels and drivers only have the
+        // legacy one
+        let atomic = match drm::Device::set_client_capability(
+            &drm,
+            drm::ClientCapability::Atomic,
+            true,
+        ) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!(
+                    "drm device does not support atomic modesetting, using the legacy API: {}",
                     e
-                )))
-            },
-        )?;
+                );
+                // Atomic implies universal planes, we still need them to find the primary plane
+                drm::Device::set_client_capability(
+                    &drm,
+                    drm::ClientCapability::UniversalPlanes,
+                    true,
+                )
+                .map_err(|e| {
+                    os_error!(OsError::KmsError(format!(
+                        "drm device does not support universal planes: {}",
+                        e
+                    )))
+                })?;
+                false
+            }
+        };
 
         // Load the information.
         let res = drm.resource_handles().map_err(|e| {
@@ -368,47 +433,27 @@ impl<T: 'static> EventLoop<T> {
             )))
         })?;
 
-        // Enumerate available connectors
-        let coninfo: Vec<connector::Info> = res
-            .connectors()
-            .iter()
-            .flat_map(|con| drm.get_connector(*con))
-            .collect();
-
-        // Enumerate available CRTCs
-        let crtcinfo: Vec<crtc::Info> = res
-            .crtcs()
-            .iter()
-            .flat_map(|crtc| drm.get_crtc(*crtc))
-            .collect();
-
-        // Filter each connector until we find one that's connected.
-        let con = coninfo
-            .iter()
-            .find(|&i| i.state() == connector::State::Connected)
+        // The first connected connector is our primary output
+        let con = connected_connectors(&drm)
+            .into_iter()
+            .next()
             .ok_or_else(|| os_error!(OsError::KmsMisc("no connected connectors")))?;
 
-        // Get the first (usually perferred) CRTC
-        let crtc = crtcinfo
-            .get(0)
+        // Get a CRTC that can drive it
+        let crtc = find_crtc(&drm, &res, &con, &[])
             .ok_or_else(|| os_error!(OsError::KmsMisc("no crtcs found")))?;
 
         // Get the perferred (or first) mode
-        let &mode = con
-            .modes()
-            .iter()
-            .find(|f| f.mode_type().contains(ModeTypeFlags::PREFERRED))
-            .or_else(|| con.modes().get(0))
+        let mode = preferred_mode(&con)
             .ok_or_else(|| os_error!(OsError::KmsMisc("no modes found on connector")))?;
 
-        *MODE.lock() = Some(mode);
-
         // Enumerate available planes
         let planes = drm
             .plane_handles()
             .map_err(|e| os_error!(OsError::KmsError(format!("could not list planes: {}", e))))?;
 
-        let p_plane = find_plane(planes, res, crtc, &drm);
+        let p_plane = find_plane(planes, &res, &crtc, &drm)
+            .ok_or_else(|| os_error!(OsError::KmsMisc("no planes found")))?;
 
         let (disp_width, disp_height) = mode.size();
 
@@ -436,41 +481,232 @@ impl<T: 'static> EventLoop<T> {
 
         let event_sink = EventSink::new();
 
+        // Filled in as windows are created
+        let outputs: Outputs = Arc::new(Mutex::new(Vec::new()));
+        let pending_redraws = Arc::new(Mutex::new(HashSet::new()));
+
+        // Whether our VT is in the background, we must not touch the display while it is.
+        let suspended = Rc::new(Cell::new(false));
+        let suspended_clone = suspended.clone();
+        let redraws = pending_redraws.clone();
+        let redraw_outputs = outputs.clone();
+
         // Handler of redraw requests.
         handle
             .insert_source(
                 event_loop_awakener_source,
                 move |_event, _metadata, data| {
-                    data.push(Event::RedrawRequested(window_id!()));
+                    let mut redraws = redraws.lock();
+                    // A redraw is sent anyway once the VT is resumed
+                    if suspended_clone.get() {
+                        redraws.clear();
+                        return;
+                    }
+                    // Windows with a flip in flight get their redraw once it lands and the swap
+                    // interval passed, windows whose monitor was unplugged never get one
+                    let outputs = redraw_outputs.lock();
+                    redraws.retain(|&window| {
+                        let output = outputs.iter().find(|output| output.window_id == window);
+                        if output.map_or(false, |output| output.page_flips.is_output_lost()) {
+                            return false;
+                        }
+                        let held = output.map_or(false, |output| output.page_flips.holds_redraws());
+                        if !held {
+                            data.push(Event::RedrawRequested(window_id!(window)));
+                        }
+                        held
+                    });
                 },
             )
             .unwrap();
 
+        // Releases redraws held back for a swap interval above 1, see `pace_delay`
+        let pace_timer = calloop::timer::Timer::new().map_err(|e| {
+            os_error!(OsError::KmsError(format!(
+                "failed to create the swap interval timer: {}",
+                e
+            )))
+        })?;
+        let pace_handle = pace_timer.handle();
+        let pace_outputs = outputs.clone();
+        let pace_redraws = pending_redraws.clone();
+        let pace_suspended = suspended.clone();
+
+        handle
+            .insert_source(pace_timer, move |window, _handle, data: &mut EventSink| {
+                if let Some(output) = pace_outputs
+                    .lock()
+                    .iter()
+                    .find(|output| output.window_id == window)
+                {
+                    output.page_flips.set_held(false);
+                }
+                if pace_redraws.lock().remove(&window) && !pace_suspended.get() {
+                    data.push(Event::RedrawRequested(window_id!(window)));
+                }
+            })
+            .map_err(|e| {
+                os_error!(OsError::KmsError(format!(
+                    "failed to listen for the swap interval timer: {}",
+                    e
+                )))
+            })?;
+
+        // The kernel tells us on the DRM fd when a flip committed with `PAGE_FLIP_EVENT` landed
+        let flip_outputs = outputs.clone();
+        let flip_redraws = pending_redraws.clone();
+        let flip_suspended = suspended.clone();
+
+        handle
+            .insert_source(
+                calloop::generic::Generic::new(
+                    drm.clone(),
+                    calloop::Interest::READ,
+                    calloop::Mode::Level,
+                ),
+                move |_, card, data: &mut EventSink| {
+                    let events = match card.receive_events() {
+                        Ok(events) => events,
+                        Err(e) => {
+                            warn!("failed to read DRM events: {}", e);
+                            return Ok(calloop::PostAction::Continue);
+                        }
+                    };
+                    for event in events {
+                        if let drm::control::Event::PageFlip(event) = event {
+                            let landed = flip_outputs
+                                .lock()
+                                .iter()
+                                .find(|output| output.crtc.handle() == event.crtc)
+                                .map(|output| {
+                                    let vblank = Vblank {
+                                        sequence: event.frame,
+                                        time: event.duration,
+                                    };
+                                    output.page_flips.flip_landed(vblank);
+                                    let delay = pace_delay(output, vblank);
+                                    output.page_flips.set_held(delay.is_some());
+                                    (output.window_id, delay)
+                                });
+                            match landed {
+                                Some((window, Some(delay))) => {
+                                    pace_handle.add_timeout(delay, window);
+                                }
+                                Some((window, None)) => {
+                                    if flip_redraws.lock().remove(&window) && !flip_suspended.get()
+                                    {
+                                        data.push(Event::RedrawRequested(window_id!(window)));
+                                    }
+                                }
+                                None => {}
+                            }
+                        }
+                    }
+                    Ok(calloop::PostAction::Continue)
+                },
+            )
+            .map_err(|e| {
+                os_error!(OsError::KmsError(format!(
+                    "failed to listen for page flips: {}",
+                    e
+                )))
+            })?;
+
+        // The kernel tells udev whenever a connector is plugged in or out
+        let card_devnum = card_devnum(&drm);
+        let hotplug_monitor = udev::MonitorBuilder::new()
+            .and_then(|builder| builder.match_subsystem("drm"))
+            .and_then(|builder| builder.listen())
+            .map_err(|e| {
+                os_error!(OsError::KmsError(format!(
+                    "failed to monitor drm devices: {}",
+                    e
+                )))
+            })?;
+        let hotplug_drm = drm.clone();
+        let hotplug_outputs = outputs.clone();
+        let mut connected = connected_connectors(&drm);
+
+        handle
+            .insert_source(
+                calloop::generic::Generic::new(
+                    hotplug_monitor,
+                    calloop::Interest::READ,
+                    calloop::Mode::Level,
+                ),
+                move |_, socket, data: &mut EventSink| {
+                    // The event only says that something changed on the card, not what. Other
+                    // cards, e.g. another GPU, are none of our business.
+                    let changed = socket.fold(false, |changed, event| {
+                        changed
+                            || (event.event_type() == udev::EventType::Change
+                                && (card_devnum.is_none() || event.devnum() == card_devnum))
+                    });
+                    if !changed {
+                        return Ok(calloop::PostAction::Continue);
+                    }
+
+                    let now = connected_connectors(&hotplug_drm);
+                    let monitor = |connector: &connector::Info| MonitorHandle {
+                        inner: platform_impl::MonitorHandle::Kms(super::MonitorHandle::new(
+                            connector.clone(),
+                        )),
+                    };
+                    for connector in &connected {
+                        if !now.iter().any(|c| c.handle() == connector.handle()) {
+                            // Nothing the window draws shows up anymore
+                            for output in hotplug_outputs.lock().iter() {
+                                if output.connector.handle() == connector.handle() {
+                                    output.page_flips.output_lost();
+                                }
+                            }
+                            data.push(Event::MonitorRemoved(monitor(connector)));
+                        }
+                    }
+                    for connector in &now {
+                        if !connected.iter().any(|c| c.handle() == connector.handle()) {
+                            data.push(Event::MonitorAdded(monitor(connector)));
+                        }
+                    }
+                    connected = now;
+
+                    Ok(calloop::PostAction::Continue)
+                },
+            )
+            .map_err(|e| {
+                os_error!(OsError::KmsError(format!(
+                    "failed to listen for hotplug events: {}",
+                    e
+                )))
+            })?;
+
         // This is used so that when you hold down a key, the same `KeyboardInput` event will be
         // repeated until the key is released or another key is pressed down
         let repeat_handler = calloop::timer::Timer::new().unwrap();
 
         let repeat_handle = repeat_handler.handle();
+        let repeat_outputs = outputs.clone();
 
         let repeat_loop: calloop::Dispatcher<
             'static,
-            calloop::timer::Timer<(KeyboardInput, Option<char>)>,
+            calloop::timer::Timer<(DeviceId, KeyboardInput, Option<char>)>,
             EventSink,
         > = calloop::Dispatcher::new(
             repeat_handler,
             move |event, metadata, data: &mut EventSink| {
+                let window_id = input_window_id(&repeat_outputs);
                 data.push(Event::WindowEvent {
-                    window_id: window_id!(),
+                    window_id,
                     event: WindowEvent::KeyboardInput {
-                        device_id: DeviceId(platform_impl::DeviceId::Kms(super::DeviceId)),
-                        input: event.0,
+                        device_id: event.0,
+                        input: event.1,
                         is_synthetic: false,
                     },
                 });
 
-                if let Some(c) = event.1 {
+                if let Some(c) = event.2 {
                     data.push(Event::WindowEvent {
-                        window_id: window_id!(),
+                        window_id,
                         event: WindowEvent::ReceivedCharacter(c),
                     });
                 }
@@ -483,6 +719,9 @@ impl<T: 'static> EventLoop<T> {
         // It is an Arc<Mutex<>> so that windows can change the cursor position
         let cursor_arc = Arc::new(Mutex::new(PhysicalPosition::new(0.0, 0.0)));
 
+        // Filled in by the input backend as devices come and go
+        let input_devices = Arc::new(Mutex::new(HashMap::new()));
+
         // Our input handler
         let input_backend: LibinputInputBackend = LibinputInputBackend::new(
             input,
@@ -492,27 +731,90 @@ impl<T: 'static> EventLoop<T> {
             keymap,
             xkb_compose,
             cursor_arc.clone(),
+            input_devices.clone(),
+            attributes.touch_calibration,
         );
 
         // When an input is received, add it to our EventSink
+        let input_outputs = outputs.clone();
         let input_loop: calloop::Dispatcher<'static, LibinputInputBackend, EventSink> =
             calloop::Dispatcher::new(
                 input_backend,
-                move |event, _metadata, data: &mut EventSink| {
+                move |mut event, _metadata, data: &mut EventSink| {
+                    if let Event::WindowEvent {
+                        ref mut window_id, ..
+                    } = event
+                    {
+                        *window_id = input_window_id(&input_outputs);
+                    }
                     data.push(event);
                 },
             );
 
-        handle.register_dispatcher(input_loop).unwrap();
+        handle.register_dispatcher(input_loop.clone()).unwrap();
         handle.register_dispatcher(repeat_loop).unwrap();
 
+        if let Some(ref vt) = vt {
+            let drm = drm.clone();
+            let outputs = outputs.clone();
+            vt::insert_source(
+                &handle,
+                vt.clone(),
+                move |event, data: &mut EventSink| match event {
+                    VtEvent::Release => {
+                        suspended.set(true);
+                        input_loop.as_source_mut().suspend();
+                        // Whoever owns the VT we are switching to needs DRM master
+                        if let Err(e) = drm::Device::release_master_lock(&drm) {
+                            warn!("failed to drop DRM master: {}", e);
+                        }
+                        data.push(Event::Suspended);
+                    }
+                    VtEvent::Acquire => {
+                        suspended.set(false);
+                        if let Err(e) = drm::Device::acquire_master_lock(&drm) {
+                            warn!("failed to acquire DRM master: {}", e);
+                        }
+                        input_loop.as_source_mut().resume();
+                        data.push(Event::Resumed);
+                        // A flip that was in flight when we lost master is not coming anymore
+                        for output in outputs.lock().iter() {
+                            output.page_flips.reset();
+                        }
+                        for window_id in window_ids(&outputs) {
+                            data.push(Event::RedrawRequested(window_id));
+                        }
+                    }
+                    VtEvent::Terminate => {
+                        for window_id in window_ids(&outputs) {
+                            data.push(Event::WindowEvent {
+                                window_id,
+                                event: WindowEvent::CloseRequested,
+                            });
+                        }
+                    }
+                },
+            )
+            .map_err(|e| {
+                os_error!(OsError::KmsError(format!(
+                    "failed to listen for VT signals: {}",
+                    e
+                )))
+            })?;
+        }
+
         let window_target = event_loop::EventLoopWindowTarget {
             p: platform_impl::EventLoopWindowTarget::Kms(EventLoopWindowTarget {
-                connector: con.clone(),
-                crtc: *crtc,
+                connector: con,
+                crtc,
+                mode,
+                atomic,
                 device: drm,
                 plane: p_plane,
+                outputs,
+                pending_redraws,
                 cursor_arc,
+                input_devices,
                 event_loop_handle: handle,
                 event_sink,
                 event_loop_awakener,
@@ -526,6 +828,8 @@ impl<T: 'static> EventLoop<T> {
             pending_user_events,
             user_events_sender,
             window_target,
+            _vt: vt,
+            window_states: HashMap::new(),
         })
     }
 
@@ -534,6 +838,8 @@ impl<T: 'static> EventLoop<T> {
         F: FnMut(Event<'_, T>, &event_loop::EventLoopWindowTarget<T>, &mut ControlFlow) + 'static,
     {
         let exit_code = self.run_return(callback);
+        // `process::exit` does not run destructors, make sure the VT gets restored
+        drop(self);
         std::process::exit(exit_code);
     }
 
@@ -551,11 +857,14 @@ impl<T: 'static> EventLoop<T> {
             &mut control_flow,
         );
 
-        callback(
-            Event::RedrawRequested(window_id!()),
-            &self.window_target,
-            &mut control_flow,
-        );
+        let windows = self.with_window_target(|window_target| window_ids(&window_target.outputs));
+        for window_id in windows {
+            callback(
+                Event::RedrawRequested(window_id),
+                &self.window_target,
+                &mut control_flow,
+            );
+        }
 
         let exit_code = loop {
             match control_flow {
@@ -650,6 +959,54 @@ impl<T: 'static> EventLoop<T> {
                 sticky_exit_callback(event, &self.window_target, &mut control_flow, &mut callback);
             }
 
+            // The size and scale factor change with the mode, or when the application overrides
+            // the scale factor. This can't go through the event sink, since
+            // `ScaleFactorChanged` borrows the new size.
+            let states: Vec<_> = self.with_window_target(|window_target| {
+                window_target
+                    .outputs
+                    .lock()
+                    .iter()
+                    .map(|output| (output.window_id, output.scale_factor(), output.size()))
+                    .collect()
+            });
+            self.window_states
+                .retain(|id, _| states.iter().any(|(window, _, _)| window == id));
+            for (id, scale_factor, size) in states {
+                // Windows that were just created already know their state
+                let (old_scale_factor, old_size) =
+                    match self.window_states.insert(id, (scale_factor, size)) {
+                        Some(old) => old,
+                        None => continue,
+                    };
+
+                if scale_factor != old_scale_factor {
+                    let mut new_inner_size = size;
+                    sticky_exit_callback(
+                        Event::WindowEvent {
+                            window_id: window_id!(id),
+                            event: WindowEvent::ScaleFactorChanged {
+                                scale_factor,
+                                new_inner_size: &mut new_inner_size,
+                            },
+                        },
+                        &self.window_target,
+                        &mut control_flow,
+                        &mut callback,
+                    );
+                } else if size != old_size {
+                    sticky_exit_callback(
+                        Event::WindowEvent {
+                            window_id: window_id!(id),
+                            event: WindowEvent::Resized(size),
+                        },
+                        &self.window_target,
+                        &mut control_flow,
+                        &mut callback,
+                    );
+                }
+            }
+
             // Send events cleared.
             sticky_exit_callback(
                 Event::MainEventsCleared,
@@ -4,6 +4,7 @@ pub mod event_loop;
 pub mod input;
 pub mod window;
 use crate::{monitor, platform_impl};
+use crate::platform_impl::{calc_scale_factor, scale_factor_from_env};
 pub use drm::SystemError;
 use drm::{
     control::{Device as ControlDevice, *},
@@ -16,9 +17,303 @@ use parking_lot::Mutex;
 use std::os::unix;
 use std::os::unix::prelude::FromRawFd;
 use std::sync::Arc;
+use std::time::Duration;
 pub use window::Window;
 
-pub static MODE: Mutex<Option<Mode>> = parking_lot::const_mutex(None);
+/// Overrides the scale factor computed from the physical size of the connector.
+pub const SCALE_FACTOR_ENV_VAR: &str = "WINIT_KMS_SCALE_FACTOR";
+
+/// A connector that is driven by a window, and what was allocated to drive it.
+///
+/// Shared between the event loop and the windows so that each window can have its own mode.
+#[derive(Debug, Clone)]
+pub(crate) struct Output {
+    pub(crate) window_id: WindowId,
+    pub(crate) connector: connector::Info,
+    pub(crate) crtc: crtc::Info,
+    pub(crate) plane: plane::Handle,
+    pub(crate) mode: Mode,
+    /// Scale factor set by the application, takes precedence over the computed one
+    pub(crate) scale_factor_override: Option<f64>,
+    pub(crate) page_flips: PageFlipTracker,
+    pub(crate) transform: PlaneTransform,
+}
+
+impl Output {
+    /// The size of the buffers the window is drawn into.
+    pub(crate) fn size(&self) -> PhysicalSize<u32> {
+        self.transform.buffer_size(&self.mode)
+    }
+
+    pub(crate) fn scale_factor(&self) -> f64 {
+        self.scale_factor_override
+            .unwrap_or_else(|| scale_factor(&self.connector, &self.mode))
+    }
+}
+
+/// Rotation of a plane, counter-clockwise like the DRM `rotation` property.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlaneRotation {
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Default for PlaneRotation {
+    fn default() -> Self {
+        PlaneRotation::Rotate0
+    }
+}
+
+/// How the display controller transforms a window's buffer while scanning it out.
+///
+/// Doing this in hardware saves rotating and scaling everything in shaders, but not every plane
+/// supports it, so setting one can fail.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PlaneTransform {
+    pub rotation: PlaneRotation,
+    /// Mirrors the buffer horizontally, before it is rotated
+    pub reflect_x: bool,
+    /// Mirrors the buffer vertically, before it is rotated
+    pub reflect_y: bool,
+    /// Size of the buffer that gets scaled up (or down) to the mode, `None` to not scale
+    pub source_size: Option<PhysicalSize<u32>>,
+}
+
+impl PlaneTransform {
+    pub fn is_identity(&self) -> bool {
+        *self == PlaneTransform::default()
+    }
+
+    /// The size of the buffer that fills `mode` once transformed.
+    pub fn buffer_size(&self, mode: &Mode) -> PhysicalSize<u32> {
+        let (width, height) = mode.size();
+        self.source_size.unwrap_or_else(|| match self.rotation {
+            PlaneRotation::Rotate90 | PlaneRotation::Rotate270 => {
+                PhysicalSize::new(height as u32, width as u32)
+            }
+            PlaneRotation::Rotate0 | PlaneRotation::Rotate180 => {
+                PhysicalSize::new(width as u32, height as u32)
+            }
+        })
+    }
+
+    /// The value of the plane's `rotation` property, see `DRM_MODE_ROTATE_*` and
+    /// `DRM_MODE_REFLECT_*`.
+    pub(crate) fn rotation_bits(&self) -> u64 {
+        let rotation = match self.rotation {
+            PlaneRotation::Rotate0 => 1 << 0,
+            PlaneRotation::Rotate90 => 1 << 1,
+            PlaneRotation::Rotate180 => 1 << 2,
+            PlaneRotation::Rotate270 => 1 << 3,
+        };
+        rotation | (self.reflect_x as u64) << 4 | (self.reflect_y as u64) << 5
+    }
+}
+
+/// A vblank of an output, as reported by the page flip that landed on it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Vblank {
+    /// The vblank counter of the crtc
+    pub sequence: u32,
+    /// When the vblank happened, on the `CLOCK_MONOTONIC` clock
+    pub time: Duration,
+}
+
+#[derive(Debug, Default)]
+struct PageFlipState {
+    pending: bool,
+    last_vblank: Option<Vblank>,
+    /// The monitor was unplugged
+    lost: bool,
+    /// See [`PageFlipTracker::set_swap_interval`]
+    swap_interval: u32,
+    /// The flip landed, but the next redraw waits for the swap interval
+    held: bool,
+}
+
+/// Page flip bookkeeping of a window's output.
+///
+/// Whoever presents to the window reports each flip it commits, the event loop notes when the
+/// flip lands and holds `RedrawRequested` back in between, so that drawing starts right after a
+/// vblank instead of blocking on the next commit.
+#[derive(Debug, Clone, Default)]
+pub struct PageFlipTracker(Arc<Mutex<PageFlipState>>);
+
+impl PageFlipTracker {
+    /// Call after a commit with `AtomicCommitFlags::PAGE_FLIP_EVENT` went through.
+    pub fn flip_queued(&self) {
+        self.0.lock().pending = true;
+    }
+
+    /// Whether a committed flip has not landed yet.
+    pub fn is_flip_pending(&self) -> bool {
+        self.0.lock().pending
+    }
+
+    /// The vblank the last flip landed on.
+    pub fn last_vblank(&self) -> Option<Vblank> {
+        self.0.lock().last_vblank
+    }
+
+    /// Whether the monitor of the output was unplugged. No flips land on it anymore and the
+    /// window gets no more `RedrawRequested`, it should be dropped.
+    pub fn is_output_lost(&self) -> bool {
+        self.0.lock().lost
+    }
+
+    pub(crate) fn output_lost(&self) {
+        let mut state = self.0.lock();
+        state.lost = true;
+        state.pending = false;
+        state.held = false;
+    }
+
+    pub(crate) fn flip_landed(&self, vblank: Vblank) {
+        let mut state = self.0.lock();
+        state.pending = false;
+        state.last_vblank = Some(vblank);
+    }
+
+    /// Forgets about the flip in flight, e.g. when we lost DRM master before it landed.
+    pub(crate) fn reset(&self) {
+        let mut state = self.0.lock();
+        state.pending = false;
+        state.held = false;
+    }
+
+    /// Makes the event loop hold `RedrawRequested` back for another `interval - 1` vblanks
+    /// after a flip landed, so that the flip after it lands `interval` vblanks later. Intervals
+    /// of 0 and 1 don't hold anything back.
+    pub fn set_swap_interval(&self, interval: u32) {
+        self.0.lock().swap_interval = interval;
+    }
+
+    pub(crate) fn swap_interval(&self) -> u32 {
+        self.0.lock().swap_interval
+    }
+
+    /// Whether `RedrawRequested` has to wait, for a flip to land or for the swap interval.
+    pub(crate) fn holds_redraws(&self) -> bool {
+        let state = self.0.lock();
+        state.pending || state.held
+    }
+
+    pub(crate) fn set_held(&self, held: bool) {
+        self.0.lock().held = held;
+    }
+}
+
+/// The current time on `CLOCK_MONOTONIC`, the clock [`Vblank`]s are on.
+pub(crate) fn monotonic_now() -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// All outputs that currently have a window, in the order the windows were created.
+pub(crate) type Outputs = Arc<Mutex<Vec<Output>>>;
+
+/// The preferred (or first) mode of `connector`.
+pub(crate) fn preferred_mode(connector: &connector::Info) -> Option<Mode> {
+    connector
+        .modes()
+        .iter()
+        .find(|f| f.mode_type().contains(ModeTypeFlags::PREFERRED))
+        .or_else(|| connector.modes().get(0))
+        .copied()
+}
+
+/// Finds a CRTC that can drive `connector` and is not in `used`.
+pub(crate) fn find_crtc(
+    card: &Card,
+    res: &ResourceHandles,
+    connector: &connector::Info,
+    used: &[crtc::Handle],
+) -> Option<crtc::Info> {
+    connector
+        .encoders()
+        .iter()
+        .flatten()
+        .flat_map(|encoder| card.get_encoder(*encoder))
+        .flat_map(|encoder| res.filter_crtcs(encoder.possible_crtcs()))
+        .find(|crtc| !used.contains(crtc))
+        .and_then(|crtc| card.get_crtc(crtc).ok())
+}
+
+/// Finds the primary plane of `crtc`, or any plane that can be used with it.
+pub(crate) fn find_plane(
+    planes: PlaneResourceHandles,
+    res: &ResourceHandles,
+    crtc: &crtc::Info,
+    drm: &Card,
+) -> Option<plane::Handle> {
+    let (p_better_planes, p_compatible_planes): (
+        // The primary planes available to us
+        Vec<plane::Handle>,
+        // Other, not-ideal planes that are however useable
+        Vec<plane::Handle>,
+    ) = planes
+        .planes()
+        .iter()
+        .filter(|&&plane| {
+            // Get the plane info from a handle
+            drm.get_plane(plane)
+                .map(|plane_info| {
+                    let compatible_crtcs = res.filter_crtcs(plane_info.possible_crtcs());
+                    // Makes sure that the plane can be used with the CRTC we selected earlier
+                    compatible_crtcs.contains(&crtc.handle())
+                })
+                .unwrap_or(false)
+        })
+        .partition(|&&plane| {
+            // Get the plane properties from a handle
+            if let Ok(props) = drm.get_properties(plane) {
+                let (ids, vals) = props.as_props_and_values();
+                for (&id, &val) in ids.iter().zip(vals.iter()) {
+                    if let Ok(info) = drm.get_property(id) {
+                        // Checks if the plane is a primary plane, and returns true if it is,
+                        // if not it returns false
+                        if info.name().to_str().map(|x| x == "type").unwrap_or(false) {
+                            return val == (PlaneType::Primary as u32).into();
+                        }
+                    }
+                }
+            }
+            false
+        });
+
+    // Get the first (best) plane we find, or the first compatibile plane
+    p_better_planes
+        .first()
+        .or_else(|| p_compatible_planes.first())
+        .copied()
+}
+
+/// All connectors of `card` that have a display plugged in.
+pub(crate) fn connected_connectors(card: &Card) -> Vec<connector::Info> {
+    card.resource_handles()
+        .map(|res| {
+            res.connectors()
+                .iter()
+                .flat_map(|con| card.get_connector(*con))
+                .filter(|con| con.state() == connector::State::Connected)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The scale factor of `connector` when driven at `mode`.
+pub(crate) fn scale_factor(connector: &connector::Info, mode: &Mode) -> f64 {
+    scale_factor_from_env(SCALE_FACTOR_ENV_VAR).unwrap_or_else(|| {
+        let size = mode.size();
+        calc_scale_factor(
+            (size.0 as u32, size.1 as u32),
+            connector.size().unwrap_or((0, 0)),
+        )
+    })
+}
 
 #[derive(Debug, Clone)]
 /// A simple wrapper for a device node.
@@ -42,13 +337,14 @@ impl Drop for Card {
 impl Device for Card {}
 impl ControlDevice for Card {}
 
+/// Identifies an input device for as long as it is plugged in. Ids are never reused.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct DeviceId;
+pub struct DeviceId(pub(crate) u32);
 
 #[allow(dead_code)]
 impl DeviceId {
     pub const unsafe fn dummy() -> Self {
-        DeviceId
+        DeviceId(0)
     }
 }
 
@@ -58,6 +354,18 @@ pub struct MonitorHandle {
     name: String,
 }
 
+impl MonitorHandle {
+    pub(crate) fn new(connector: connector::Info) -> Self {
+        // Along the lines of the names the kernel uses, e.g. `HDMIA-1`
+        let name = format!("{:?}-{}", connector.interface(), connector.interface_id());
+        MonitorHandle { connector, name }
+    }
+
+    pub(crate) fn connector(&self) -> &connector::Info {
+        &self.connector
+    }
+}
+
 impl PartialOrd for MonitorHandle {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(
@@ -89,7 +397,7 @@ impl MonitorHandle {
 
     #[inline]
     pub fn size(&self) -> PhysicalSize<u32> {
-        let size = self.connector.modes()[0].size();
+        let size = preferred_mode(&self.connector).map_or((0, 0), |mode| mode.size());
         PhysicalSize::new(size.0 as u32, size.1 as u32)
     }
 
@@ -100,7 +408,9 @@ impl MonitorHandle {
 
     #[inline]
     pub fn scale_factor(&self) -> f64 {
-        1.0
+        preferred_mode(&self.connector)
+            .map(|mode| scale_factor(&self.connector, &mode))
+            .unwrap_or(1.0)
     }
 
     #[inline]
@@ -142,20 +452,30 @@ impl VideoMode {
     #[inline]
     pub fn monitor(&self) -> monitor::MonitorHandle {
         monitor::MonitorHandle {
-            inner: platform_impl::MonitorHandle::Kms(MonitorHandle {
-                connector: self.connector.clone(),
-                name: self.mode.name().to_string_lossy().into_owned(),
-            }),
+            inner: platform_impl::MonitorHandle::Kms(MonitorHandle::new(self.connector.clone())),
         }
     }
+
+    pub(crate) fn connector(&self) -> &connector::Info {
+        &self.connector
+    }
+
+    pub(crate) fn mode(&self) -> Mode {
+        self.mode
+    }
 }
 
+/// Windows are identified by the handle of the connector they are shown on.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
-pub struct WindowId;
+pub struct WindowId(pub(crate) u32);
 
 #[allow(dead_code)]
 impl WindowId {
+    pub(crate) fn from_connector(connector: connector::Handle) -> Self {
+        WindowId(connector.into())
+    }
+
     pub const unsafe fn dummy() -> Self {
-        Self
+        WindowId(0)
     }
 }
@@ -1,7 +1,17 @@
-use std::{collections::VecDeque, os::unix::prelude::AsRawFd, sync::Arc};
+use std::{
+    collections::{HashSet, VecDeque},
+    os::unix::prelude::AsRawFd,
+    sync::Arc,
+};
 
-use super::MODE;
-use drm::control::*;
+use super::{
+    connected_connectors, find_crtc, find_plane, preferred_mode, Output, Outputs, PageFlipTracker,
+    PlaneTransform, Vblank,
+};
+use drm::{
+    buffer::DrmFourcc,
+    control::{dumbbuffer::DumbBuffer, *},
+};
 use parking_lot::Mutex;
 
 #[cfg(feature = "wayland")]
@@ -19,12 +29,58 @@ use crate::{
 };
 
 pub struct Window {
+    id: super::WindowId,
     connector: connector::Info,
     crtc: crtc::Info,
     ping: calloop::ping::Ping,
     plane: plane::Handle,
     cursor: Arc<Mutex<PhysicalPosition<f64>>>,
     card: Card,
+    outputs: Outputs,
+    pending_redraws: Arc<Mutex<HashSet<super::WindowId>>>,
+    page_flips: PageFlipTracker,
+    atomic: bool,
+    /// What the crtc showed after the last mode set with the legacy API. It stays allocated
+    /// until the next mode set or until the window is dropped, not just until the first flip
+    /// replaced it: removing a framebuffer that is still shown turns the crtc off.
+    black_buffer: Mutex<Option<BlackBuffer>>,
+}
+
+/// A black buffer for the legacy API, which can't enable a crtc without something to show.
+struct BlackBuffer {
+    buffer: DumbBuffer,
+    fb: framebuffer::Handle,
+}
+
+impl BlackBuffer {
+    fn destroy(self, card: &Card) {
+        if let Err(e) = card.destroy_framebuffer(self.fb) {
+            warn!("failed to remove framebuffer: {}", e);
+        }
+        if let Err(e) = card.destroy_dumb_buffer(self.buffer) {
+            warn!("failed to destroy buffer: {}", e);
+        }
+    }
+}
+
+/// The connector the window asked to be shown on with its fullscreen attribute, and the mode
+/// it asked for.
+fn requested_output<T>(
+    event_loop_window_target: &super::event_loop::EventLoopWindowTarget<T>,
+    attributes: &WindowAttributes,
+) -> (connector::Info, Option<Mode>) {
+    match attributes.fullscreen {
+        Some(Fullscreen::Exclusive(VideoMode {
+            video_mode: platform_impl::VideoMode::Kms(ref video_mode),
+        })) => (video_mode.connector().clone(), Some(video_mode.mode())),
+        Some(Fullscreen::Borderless(Some(MonitorHandle {
+            inner: platform_impl::MonitorHandle::Kms(ref monitor),
+        }))) => (monitor.connector().clone(), None),
+        _ => (
+            event_loop_window_target.connector.clone(),
+            Some(event_loop_window_target.mode),
+        ),
+    }
 }
 
 fn find_prop_id<T: ResourceHandle>(
@@ -63,141 +119,339 @@ macro_rules! add_property {
     };
 }
 
-impl Window {
-    pub fn new<T>(
-        event_loop_window_target: &super::event_loop::EventLoopWindowTarget<T>,
-        _attributes: WindowAttributes,
-        _platform_attributes: platform_impl::PlatformSpecificWindowBuilderAttributes,
-    ) -> Result<Self, error::OsError> {
-        let mut atomic_req = atomic::AtomicModeReq::new();
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.connector.handle(),
-            event_loop_window_target.device,
-            "CRTC_ID",
-            property::Value::CRTC(Some(event_loop_window_target.crtc.handle())),
-        );
-
-        let mode = &(MODE.lock().ok_or_else(|| {
-            os_error!(platform_impl::OsError::KmsMisc("mode is not initialized"))
-        })?);
-
-        let blob = event_loop_window_target
-            .device
-            .create_property_blob(mode)
-            .map_err(|_| os_error!(platform_impl::OsError::KmsMisc("failed to create blob")))?;
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.crtc.handle(),
-            event_loop_window_target.device,
-            "MODE_ID",
-            blob,
-        );
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.crtc.handle(),
-            event_loop_window_target.device,
-            "ACTIVE",
-            property::Value::Boolean(true),
-        );
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "CRTC_ID",
-            property::Value::CRTC(Some(event_loop_window_target.crtc.handle())),
-        );
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "SRC_X",
-            property::Value::UnsignedRange(0),
-        );
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "SRC_Y",
-            property::Value::UnsignedRange(0),
-        );
-
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "SRC_W",
-            property::Value::UnsignedRange((mode.size().0 as u64) << 16),
-        );
+/// Shows `plane` on `connector` at `mode`, driven by `crtc`. Returns the black buffer shown
+/// with the legacy API.
+fn set_mode(
+    card: &Card,
+    atomic: bool,
+    connector: &connector::Info,
+    crtc: &crtc::Info,
+    plane: plane::Handle,
+    mode: &Mode,
+    transform: &PlaneTransform,
+) -> Result<Option<BlackBuffer>, error::OsError> {
+    if !atomic {
+        if !transform.is_identity() {
+            return Err(os_error!(platform_impl::OsError::KmsMisc(
+                "plane transforms need atomic modesetting"
+            )));
+        }
+        return set_mode_legacy(card, connector, crtc, mode).map(Some);
+    }
 
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "SRC_H",
-            property::Value::UnsignedRange((mode.size().1 as u64) << 16),
-        );
+    let mut atomic_req = atomic::AtomicModeReq::new();
+
+    add_property!(
+        atomic_req,
+        connector.handle(),
+        card,
+        "CRTC_ID",
+        property::Value::CRTC(Some(crtc.handle())),
+    );
+
+    let blob = card
+        .create_property_blob(mode)
+        .map_err(|_| os_error!(platform_impl::OsError::KmsMisc("failed to create blob")))?;
+
+    add_property!(
+        atomic_req,
+        crtc.handle(),
+        card,
+        "MODE_ID",
+        blob,
+    );
+
+    add_property!(
+        atomic_req,
+        crtc.handle(),
+        card,
+        "ACTIVE",
+        property::Value::Boolean(true),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "CRTC_ID",
+        property::Value::CRTC(Some(crtc.handle())),
+    );
+
+    add_plane_geometry(&mut atomic_req, card, plane, mode, transform)?;
+
+    card.atomic_commit(AtomicCommitFlags::ALLOW_MODESET, atomic_req)
+        .map_err(|e| {
+            os_error!(platform_impl::OsError::KmsError(format!(
+                "failed to set mode: {}",
+                e
+            )))
+        })?;
+    Ok(None)
+}
 
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "CRTC_X",
-            property::Value::SignedRange(0),
-        );
+/// Sets `mode` with the legacy API, which can't enable a crtc without something to show. It gets a
+/// black buffer until the first frame is presented, the caller has to destroy it once it is
+/// replaced.
+fn set_mode_legacy(
+    card: &Card,
+    connector: &connector::Info,
+    crtc: &crtc::Info,
+    mode: &Mode,
+) -> Result<BlackBuffer, error::OsError> {
+    let (width, height) = mode.size();
+    let buffer = card
+        .create_dumb_buffer((width as u32, height as u32), DrmFourcc::Xrgb8888, 32)
+        .map_err(|e| {
+            os_error!(platform_impl::OsError::KmsError(format!(
+                "failed to create buffer: {}",
+                e
+            )))
+        })?;
+    let fb = match card.add_framebuffer(&buffer, 24, 32) {
+        Ok(fb) => fb,
+        Err(e) => {
+            let _ = card.destroy_dumb_buffer(buffer);
+            return Err(os_error!(platform_impl::OsError::KmsError(format!(
+                "failed to add framebuffer: {}",
+                e
+            ))));
+        }
+    };
+    let black_buffer = BlackBuffer { buffer, fb };
+
+    match card.set_crtc(
+        crtc.handle(),
+        Some(fb),
+        (0, 0),
+        &[connector.handle()],
+        Some(*mode),
+    ) {
+        Ok(()) => Ok(black_buffer),
+        Err(e) => {
+            black_buffer.destroy(card);
+            Err(os_error!(platform_impl::OsError::KmsError(format!(
+                "failed to set mode: {}",
+                e
+            ))))
+        }
+    }
+}
 
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "CRTC_Y",
-            property::Value::SignedRange(0),
-        );
+/// Adds where `plane` scans out from and to when showing `mode` with `transform`.
+fn add_plane_geometry(
+    atomic_req: &mut atomic::AtomicModeReq,
+    card: &Card,
+    plane: plane::Handle,
+    mode: &Mode,
+    transform: &PlaneTransform,
+) -> Result<(), error::OsError> {
+    // Planes that can't rotate or reflect don't have the property at all
+    match find_prop_id(card, plane, "rotation") {
+        Some(rotation) => atomic_req.add_property(
+            plane,
+            rotation,
+            property::Value::Bitmask(transform.rotation_bits()),
+        ),
+        None if transform.rotation_bits() != PlaneTransform::default().rotation_bits() => {
+            return Err(os_error!(platform_impl::OsError::KmsMisc(
+                "plane does not support rotation"
+            )));
+        }
+        None => (),
+    }
 
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "CRTC_W",
-            property::Value::UnsignedRange(mode.size().0 as u64),
-        );
+    let buffer_size = transform.buffer_size(mode);
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "SRC_X",
+        property::Value::UnsignedRange(0),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "SRC_Y",
+        property::Value::UnsignedRange(0),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "SRC_W",
+        property::Value::UnsignedRange((buffer_size.width as u64) << 16),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "SRC_H",
+        property::Value::UnsignedRange((buffer_size.height as u64) << 16),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "CRTC_X",
+        property::Value::SignedRange(0),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "CRTC_Y",
+        property::Value::SignedRange(0),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "CRTC_W",
+        property::Value::UnsignedRange(mode.size().0 as u64),
+    );
+
+    add_property!(
+        atomic_req,
+        plane,
+        card,
+        "CRTC_H",
+        property::Value::UnsignedRange(mode.size().1 as u64),
+    );
+
+    Ok(())
+}
 
-        add_property!(
-            atomic_req,
-            event_loop_window_target.plane,
-            event_loop_window_target.device,
-            "CRTC_H",
-            property::Value::UnsignedRange(mode.size().1 as u64),
-        );
+impl Window {
+    pub fn new<T>(
+        event_loop_window_target: &super::event_loop::EventLoopWindowTarget<T>,
+        attributes: WindowAttributes,
+        platform_attributes: platform_impl::PlatformSpecificWindowBuilderAttributes,
+    ) -> Result<Self, error::OsError> {
+        let card = &event_loop_window_target.device;
+        let (connector, mode) = requested_output(event_loop_window_target, &attributes);
+        // The handle we were given may be from before the monitor was replugged
+        let connector = card.get_connector(connector.handle()).map_err(|e| {
+            os_error!(platform_impl::OsError::KmsError(format!(
+                "failed to get connector: {}",
+                e
+            )))
+        })?;
+        if connector.state() != connector::State::Connected {
+            return Err(os_error!(platform_impl::OsError::KmsMisc(
+                "monitor is not connected"
+            )));
+        }
+        let mode = mode.or_else(|| preferred_mode(&connector)).ok_or_else(|| {
+            os_error!(platform_impl::OsError::KmsMisc("no modes found on connector"))
+        })?;
+
+        let mut outputs = event_loop_window_target.outputs.lock();
+        if outputs
+            .iter()
+            .any(|output| output.connector.handle() == connector.handle())
+        {
+            return Err(os_error!(platform_impl::OsError::KmsMisc(
+                "monitor already has a window"
+            )));
+        }
 
-        event_loop_window_target
-            .device
-            .atomic_commit(AtomicCommitFlags::ALLOW_MODESET, atomic_req)
-            .map_err(|e| {
+        let primary_crtc_used = outputs
+            .iter()
+            .any(|output| output.crtc.handle() == event_loop_window_target.crtc.handle());
+        let (crtc, plane) = if connector.handle() == event_loop_window_target.connector.handle()
+            && !primary_crtc_used
+        {
+            (event_loop_window_target.crtc, event_loop_window_target.plane)
+        } else {
+            let res = card.resource_handles().map_err(|e| {
                 os_error!(platform_impl::OsError::KmsError(format!(
-                    "failed to set mode: {}",
+                    "could not load normal resource ids: {}",
+                    e
+                )))
+            })?;
+            let used: Vec<crtc::Handle> =
+                outputs.iter().map(|output| output.crtc.handle()).collect();
+            let crtc = find_crtc(card, &res, &connector, &used).ok_or_else(|| {
+                os_error!(platform_impl::OsError::KmsMisc("no free crtc for this monitor"))
+            })?;
+            let planes = card.plane_handles().map_err(|e| {
+                os_error!(platform_impl::OsError::KmsError(format!(
+                    "could not list planes: {}",
                     e
                 )))
             })?;
+            let plane = find_plane(planes, &res, &crtc, card).ok_or_else(|| {
+                os_error!(platform_impl::OsError::KmsMisc("no plane for this monitor"))
+            })?;
+            (crtc, plane)
+        };
+
+        let mut transform = platform_attributes.plane_transform;
+        let atomic = event_loop_window_target.atomic;
+        let result = set_mode(card, atomic, &connector, &crtc, plane, &mode, &transform);
+        let black_buffer = match result {
+            Ok(black_buffer) => black_buffer,
+            Err(e) if transform.is_identity() => return Err(e),
+            Err(e) => {
+                // The application can still rotate and scale by itself, it can tell from
+                // `plane_transform` that it has to
+                warn!(
+                    "plane transform {:?} was rejected, not using one: {}",
+                    transform, e
+                );
+                transform = PlaneTransform::default();
+                set_mode(card, atomic, &connector, &crtc, plane, &mode, &transform)?
+            }
+        };
+
+        let id = super::WindowId::from_connector(connector.handle());
+        let page_flips = PageFlipTracker::default();
+        outputs.push(Output {
+            window_id: id,
+            connector: connector.clone(),
+            crtc,
+            plane,
+            mode,
+            scale_factor_override: platform_attributes.scale_factor,
+            page_flips: page_flips.clone(),
+            transform,
+        });
 
         Ok(Self {
-            connector: event_loop_window_target.connector.clone(),
-            crtc: event_loop_window_target.crtc,
-            plane: event_loop_window_target.plane,
+            id,
+            connector,
+            crtc,
+            plane,
             cursor: event_loop_window_target.cursor_arc.clone(),
             ping: event_loop_window_target.event_loop_awakener.clone(),
-            card: event_loop_window_target.device.clone(),
+            card: card.clone(),
+            outputs: event_loop_window_target.outputs.clone(),
+            pending_redraws: event_loop_window_target.pending_redraws.clone(),
+            page_flips,
+            atomic,
+            black_buffer: Mutex::new(black_buffer),
         })
     }
+
+    fn with_output<R>(&self, f: impl FnOnce(&mut Output) -> R) -> Option<R> {
+        self.outputs
+            .lock()
+            .iter_mut()
+            .find(|output| output.window_id == self.id)
+            .map(f)
+    }
+
     #[inline]
     pub fn id(&self) -> super::WindowId {
-        super::WindowId
+        self.id
     }
 
     #[inline]
@@ -226,8 +480,7 @@ impl Window {
 
     #[inline]
     pub fn inner_size(&self) -> PhysicalSize<u32> {
-        let size = MODE.lock().expect("mode is not initialized").size();
-        PhysicalSize::new(size.0 as u32, size.1 as u32)
+        self.with_output(|output| output.size()).unwrap_or_default()
     }
 
     #[inline]
@@ -261,7 +514,15 @@ impl Window {
     }
 
     #[inline]
-    pub fn set_cursor_visible(&self, _visible: bool) {}
+    pub fn set_cursor_visible(&self, visible: bool) {
+        // We have no cursor image of our own, but whoever had the crtc before may have left one
+        if !visible {
+            // The legacy cursor API works for atomic clients too
+            if let Err(e) = self.card.set_cursor::<DumbBuffer>(self.crtc.handle(), None) {
+                warn!("failed to hide the cursor: {}", e);
+            }
+        }
+    }
 
     #[inline]
     pub fn drag_window(&self) -> Result<(), ExternalError> {
@@ -275,12 +536,25 @@ impl Window {
 
     #[inline]
     pub fn scale_factor(&self) -> f64 {
-        1.0
+        self.with_output(|output| output.scale_factor()).unwrap_or(1.0)
+    }
+
+    /// Replaces the computed scale factor, `None` goes back to the computed one.
+    #[inline]
+    pub fn set_scale_factor_override(&self, scale_factor: Option<f64>) {
+        self.with_output(|output| output.scale_factor_override = scale_factor);
+        // Wake the event loop up so it notices the change
+        self.ping.ping();
     }
 
     #[inline]
     pub fn set_cursor_position(&self, position: Position) -> Result<(), ExternalError> {
-        *self.cursor.lock() = position.to_physical(1.0);
+        let position = position.to_physical(self.scale_factor());
+        // Drivers without a cursor plane don't support this, which is fine since we don't show one
+        let _ = self
+            .card
+            .move_cursor(self.crtc.handle(), (position.x as i32, position.y as i32));
+        *self.cursor.lock() = position;
         Ok(())
     }
 
@@ -299,7 +573,7 @@ impl Window {
     pub fn fullscreen(&self) -> Option<Fullscreen> {
         Some(Fullscreen::Exclusive(VideoMode {
             video_mode: platform_impl::VideoMode::Kms(super::VideoMode {
-                mode: MODE.lock().expect("mode is not initialized"),
+                mode: self.drm_mode()?,
                 connector: self.connector.clone(),
             }),
         }))
@@ -318,44 +592,97 @@ impl Window {
                     }),
                 } == fullscreen
             }) {
-                let mut atomic_req = atomic::AtomicModeReq::new();
-                let blob = self.card.create_property_blob(&mo).unwrap();
+                let result = if self.atomic {
+                    self.set_mode_atomic(&mo)
+                } else {
+                    set_mode_legacy(&self.card, &self.connector, &self.crtc, &mo).map(
+                        |black_buffer| {
+                            // The old one is no longer scanned out
+                            if let Some(old) = self.black_buffer.lock().replace(black_buffer) {
+                                old.destroy(&self.card);
+                            }
+                        },
+                    )
+                };
+                if let Err(e) = result {
+                    warn!("failed to switch to mode {:?}: {}", mo, e);
+                    return;
+                }
+
+                // The event loop picks up the new size and scale factor from here
+                self.with_output(|output| output.mode = mo);
+                self.request_redraw();
+            }
+        }
+    }
 
-                atomic_req.add_property(
-                    self.crtc.handle(),
-                    find_prop_id!(&self.card, self.crtc.handle(), "MODE_ID").unwrap(),
-                    blob,
-                );
+    /// Switches the crtc to `mode`, keeping the plane transform.
+    fn set_mode_atomic(&self, mode: &Mode) -> Result<(), error::OsError> {
+        let mut atomic_req = atomic::AtomicModeReq::new();
+        let blob = self
+            .card
+            .create_property_blob(mode)
+            .map_err(|_| os_error!(platform_impl::OsError::KmsMisc("failed to create blob")))?;
 
-                atomic_req.add_property(
-                    self.plane,
-                    find_prop_id!(&self.card, self.plane, "SRC_W").unwrap(),
-                    property::Value::UnsignedRange((mo.size().0 as u64) << 16),
-                );
+        add_property!(
+            atomic_req,
+            self.crtc.handle(),
+            self.card,
+            "MODE_ID",
+            blob,
+        );
 
-                atomic_req.add_property(
-                    self.plane,
-                    find_prop_id!(&self.card, self.plane, "SRC_H").unwrap(),
-                    property::Value::UnsignedRange((mo.size().1 as u64) << 16),
-                );
+        let transform = self.plane_transform();
+        add_plane_geometry(&mut atomic_req, &self.card, self.plane, mode, &transform)?;
 
-                atomic_req.add_property(
-                    self.plane,
-                    find_prop_id!(&self.card, self.plane, "CRTC_W").unwrap(),
-                    property::Value::UnsignedRange(mo.size().0 as u64),
-                );
+        self.card
+            .atomic_commit(AtomicCommitFlags::ALLOW_MODESET, atomic_req)
+            .map_err(|e| {
+                os_error!(platform_impl::OsError::KmsError(format!(
+                    "failed to set mode: {}",
+                    e
+                )))
+            })
+    }
 
-                atomic_req.add_property(
-                    self.plane,
-                    find_prop_id!(&self.card, self.plane, "CRTC_H").unwrap(),
-                    property::Value::UnsignedRange(mo.size().1 as u64),
-                );
+    /// How the plane currently transforms the window's buffer.
+    #[inline]
+    pub fn plane_transform(&self) -> PlaneTransform {
+        self.with_output(|output| output.transform).unwrap_or_default()
+    }
 
-                self.card
-                    .atomic_commit(AtomicCommitFlags::ALLOW_MODESET, atomic_req)
-                    .unwrap();
-            }
+    /// Rotates, reflects or scales the window's buffer on the plane.
+    ///
+    /// Fails if the hardware can't do it, or if the buffer would need a different size, which
+    /// can only be chosen when the window is created.
+    pub fn set_plane_transform(&self, transform: PlaneTransform) -> Result<(), ExternalError> {
+        if !self.atomic {
+            return Err(ExternalError::NotSupported(NotSupportedError::new()));
         }
+        let mode = self
+            .drm_mode()
+            .ok_or_else(|| ExternalError::NotSupported(NotSupportedError::new()))?;
+        if transform.buffer_size(&mode) != self.inner_size() {
+            return Err(ExternalError::NotSupported(NotSupportedError::new()));
+        }
+
+        let mut atomic_req = atomic::AtomicModeReq::new();
+        add_plane_geometry(&mut atomic_req, &self.card, self.plane, &mode, &transform)
+            .map_err(ExternalError::Os)?;
+        // Atomic commits are all or nothing, so a rejected transform leaves the plane as it was
+        self.card
+            .atomic_commit(AtomicCommitFlags::empty(), atomic_req)
+            .map_err(|e| {
+                ExternalError::Os(os_error!(platform_impl::OsError::KmsError(format!(
+                    "plane transform was rejected: {}",
+                    e
+                ))))
+            })?;
+
+        self.with_output(|output| output.transform = transform);
+        // Whatever compensated for the old transform has to be redrawn
+        self.request_redraw();
+        Ok(())
     }
 
     #[inline]
@@ -373,33 +700,21 @@ impl Window {
 
     #[inline]
     pub fn request_redraw(&self) {
+        self.pending_redraws.lock().insert(self.id);
         self.ping.ping();
     }
 
     #[inline]
     pub fn current_monitor(&self) -> Option<super::MonitorHandle> {
-        Some(super::MonitorHandle {
-            connector: self.connector.clone(),
-            name: (*MODE.lock())?.name().to_string_lossy().into_owned(),
-        })
+        Some(super::MonitorHandle::new(self.connector.clone()))
     }
 
     #[inline]
     pub fn available_monitors(&self) -> VecDeque<super::MonitorHandle> {
-        if let Some(mode) = *MODE.lock() {
-            self.card
-                .resource_handles()
-                .unwrap()
-                .connectors()
-                .iter()
-                .map(|f| super::MonitorHandle {
-                    connector: self.card.get_connector(*f).unwrap(),
-                    name: mode.name().to_string_lossy().into_owned(),
-                })
-                .collect()
-        } else {
-            VecDeque::new()
-        }
+        connected_connectors(&self.card)
+            .into_iter()
+            .map(super::MonitorHandle::new)
+            .collect()
     }
 
     #[inline]
@@ -412,11 +727,53 @@ impl Window {
 
     #[inline]
     pub fn primary_monitor(&self) -> Option<MonitorHandle> {
+        // The monitor of the first window, which also gets all input
+        let connector = self.outputs.lock().first()?.connector.clone();
         Some(MonitorHandle {
-            inner: platform_impl::MonitorHandle::Kms(super::MonitorHandle {
-                connector: self.connector.clone(),
-                name: (*MODE.lock())?.name().to_string_lossy().into_owned(),
-            }),
+            inner: platform_impl::MonitorHandle::Kms(super::MonitorHandle::new(connector)),
         })
     }
+
+    #[inline]
+    pub fn drm_connector(&self) -> &connector::Info {
+        &self.connector
+    }
+
+    #[inline]
+    pub fn drm_crtc(&self) -> &crtc::Info {
+        &self.crtc
+    }
+
+    #[inline]
+    pub fn drm_plane(&self) -> plane::Handle {
+        self.plane
+    }
+
+    #[inline]
+    pub fn drm_mode(&self) -> Option<Mode> {
+        self.with_output(|output| output.mode)
+    }
+
+    #[inline]
+    pub fn drm_page_flips(&self) -> PageFlipTracker {
+        self.page_flips.clone()
+    }
+
+    #[inline]
+    pub fn drm_last_vblank(&self) -> Option<Vblank> {
+        self.page_flips.last_vblank()
+    }
+}
+
+impl Drop for Window {
+    fn drop(&mut self) {
+        // Hand the CRTC back so that another window can use it. The output is left as is, so
+        // that the last frame stays on screen.
+        self.outputs
+            .lock()
+            .retain(|output| output.window_id != self.id);
+        if let Some(black_buffer) = self.black_buffer.get_mut().take() {
+            black_buffer.destroy(&self.card);
+        }
+    }
 }
@@ -39,6 +39,8 @@ pub(crate) use crate::icon::RgbaIcon as PlatformIcon;
 pub mod fbdev;
 #[cfg(feature = "kms")]
 pub mod kms;
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+pub mod vt;
 #[cfg(feature = "wayland")]
 pub mod wayland;
 #[cfg(feature = "x11")]
@@ -67,10 +69,121 @@ pub enum Backend {
     FbDev,
 }
 
+/// A backend to force, named whatever features winit was built with. See
+/// `EventLoopBuilderExtUnix::with_unix_backend`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum UnixBackend {
+    X11,
+    Wayland,
+    Kms,
+    FbDev,
+}
+
+/// Information about an input device handled by the kms or fbdev backends.
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InputDeviceInfo {
+    /// The name the device reports, e.g. `Logitech USB Keyboard`.
+    pub name: String,
+    /// The kernel name of the device node, e.g. `event3`.
+    pub sysname: String,
+    pub vendor_id: u32,
+    pub product_id: u32,
+    /// Whether this is a keyboard that can be typed on, as opposed to e.g. a power button which
+    /// also only sends key events.
+    pub keyboard: bool,
+    pub pointer: bool,
+    pub touch: bool,
+    pub tablet: bool,
+}
+
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+impl InputDeviceInfo {
+    pub(crate) fn from_libinput(device: &input::Device) -> Self {
+        use input::DeviceCapability;
+
+        // KEY_A from <linux/input-event-codes.h>
+        const KEY_A: u32 = 30;
+
+        InputDeviceInfo {
+            name: device.name().to_string(),
+            sysname: device.sysname().to_string(),
+            vendor_id: device.id_vendor(),
+            product_id: device.id_product(),
+            keyboard: device.has_capability(DeviceCapability::Keyboard)
+                && matches!(device.keyboard_has_key(KEY_A), Ok(true)),
+            pointer: device.has_capability(DeviceCapability::Pointer),
+            touch: device.has_capability(DeviceCapability::Touch),
+            tablet: device.has_capability(DeviceCapability::TabletTool),
+        }
+    }
+}
+
+/// Sets the calibration `matrix` if `device` is a touchscreen that has one.
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+pub(crate) fn calibrate_touchscreen(device: &mut input::Device, matrix: [f32; 6]) {
+    use input::DeviceCapability;
+
+    if !device.has_capability(DeviceCapability::Touch) || !device.config_calibration_has_matrix() {
+        return;
+    }
+    if device.config_calibration_set_matrix(matrix).is_err() {
+        warn!("failed to set the calibration matrix of {}", device.name());
+    }
+}
+
+/// Computes a scale factor from the physical size of a display, the same way the X11 backend
+/// does for XRandR monitors. Returns 1.0 if the driver does not report a size.
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+pub(crate) fn calc_scale_factor(size_px: (u32, u32), size_mm: (u32, u32)) -> f64 {
+    if size_mm.0 == 0 || size_mm.1 == 0 {
+        return 1.0;
+    }
+
+    let area_px = size_px.0 as f64 * size_px.1 as f64;
+    let area_mm = size_mm.0 as f64 * size_mm.1 as f64;
+    let ppmm = (area_px / area_mm).sqrt();
+    // Quantize 1/12 step size
+    let scale_factor = ((ppmm * (12.0 * 25.4 / 96.0)).round() / 12.0).max(1.0);
+    assert!(crate::dpi::validate_scale_factor(scale_factor));
+    if scale_factor <= 20.0 {
+        scale_factor
+    } else {
+        // Bogus physical sizes (e.g. 1x1mm) are not unheard of
+        1.0
+    }
+}
+
+/// Reads a scale factor override from the environment variable `var`.
+#[cfg(any(feature = "kms", feature = "fbdev"))]
+pub(crate) fn scale_factor_from_env(var: &str) -> Option<f64> {
+    let value = env::var(var).ok()?;
+    match value.parse::<f64>() {
+        Ok(scale_factor) if crate::dpi::validate_scale_factor(scale_factor) => Some(scale_factor),
+        _ => {
+            warn!(
+                "`{}` must be a normal positive floating point number, got `{}`",
+                var, value
+            );
+            None
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Hash)]
 pub(crate) struct PlatformSpecificEventLoopAttributes {
     pub(crate) forced_backend: Option<Backend>,
     pub(crate) any_thread: bool,
+    pub(crate) unix_backend: Option<UnixBackend>,
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    pub(crate) vt_handling: bool,
+    /// The DRM card or framebuffer device to open, instead of `WINIT_DRM_CARD` or
+    /// `WINIT_FBDEV_PATH`.
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    pub(crate) device_path: Option<std::path::PathBuf>,
+    /// The libinput calibration matrix of every touchscreen.
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    pub(crate) touch_calibration: Option<[f32; 6]>,
 }
 
 impl Default for PlatformSpecificEventLoopAttributes {
@@ -78,6 +191,13 @@ impl Default for PlatformSpecificEventLoopAttributes {
         Self {
             forced_backend: None,
             any_thread: false,
+            unix_backend: None,
+            #[cfg(any(feature = "kms", feature = "fbdev"))]
+            vt_handling: false,
+            #[cfg(any(feature = "kms", feature = "fbdev"))]
+            device_path: None,
+            #[cfg(any(feature = "kms", feature = "fbdev"))]
+            touch_calibration: None,
         }
     }
 }
@@ -111,6 +231,10 @@ pub struct PlatformSpecificWindowBuilderAttributes {
     pub x11_window_types: Vec<XWindowType>,
     #[cfg(feature = "x11")]
     pub gtk_theme_variant: Option<String>,
+    #[cfg(any(feature = "kms", feature = "fbdev"))]
+    pub scale_factor: Option<f64>,
+    #[cfg(feature = "kms")]
+    pub plane_transform: kms::PlaneTransform,
 }
 
 impl Default for PlatformSpecificWindowBuilderAttributes {
@@ -131,6 +255,10 @@ impl Default for PlatformSpecificWindowBuilderAttributes {
             x11_window_types: vec![XWindowType::Normal],
             #[cfg(feature = "x11")]
             gtk_theme_variant: None,
+            #[cfg(any(feature = "kms", feature = "fbdev"))]
+            scale_factor: None,
+            #[cfg(feature = "kms")]
+            plane_transform: Default::default(),
         }
     }
 }
@@ -770,18 +898,34 @@ impl<T: 'static> EventLoop<T> {
         #[cfg(feature = "kms")]
         if attributes.forced_backend == Some(Backend::Kms) {
             // TODO: Propagate
-            return EventLoop::new_drm_any_thread().expect("failed to open drm connection");
+            return EventLoop::new_drm_any_thread(attributes)
+                .expect("failed to open drm connection");
         }
 
         #[cfg(feature = "fbdev")]
         if attributes.forced_backend == Some(Backend::FbDev) {
             // TODO: Propagate
-            return EventLoop::new_fbdev_any_thread().expect("failed to open fbdev connection");
+            return EventLoop::new_fbdev_any_thread(attributes)
+                .expect("failed to open fbdev connection");
         }
 
-        if let Ok(env_var) = env::var(BACKEND_PREFERENCE_ENV_VAR) {
-            match env_var.as_str() {
-                "x11" => {
+        let unix_backend = attributes.unix_backend.or_else(|| {
+            let env_var = env::var(BACKEND_PREFERENCE_ENV_VAR).ok()?;
+            Some(match env_var.as_str() {
+                "x11" => UnixBackend::X11,
+                "wayland" => UnixBackend::Wayland,
+                "drm" | "kms" | "gbm" | "tty" => UnixBackend::Kms,
+                "fbdev" => UnixBackend::FbDev,
+                _ => panic!(
+                    "Unknown environment variable value for {}, try one of `x11`,`wayland`",
+                    BACKEND_PREFERENCE_ENV_VAR,
+                ),
+            })
+        });
+
+        if let Some(unix_backend) = unix_backend {
+            match unix_backend {
+                UnixBackend::X11 => {
                     // TODO: propagate
                     #[cfg(feature = "x11")]
                     return EventLoop::new_x11_any_thread()
@@ -789,31 +933,27 @@ impl<T: 'static> EventLoop<T> {
                     #[cfg(not(feature = "x11"))]
                     panic!("x11 feature is not enabled")
                 }
-                "wayland" => {
+                UnixBackend::Wayland => {
                     #[cfg(feature = "wayland")]
                     return EventLoop::new_wayland_any_thread()
                         .expect("Failed to initialize Wayland backend");
                     #[cfg(not(feature = "wayland"))]
                     panic!("wayland feature is not enabled");
                 }
-                "drm" | "kms" | "gbm" | "tty" => {
+                UnixBackend::Kms => {
                     #[cfg(feature = "kms")]
-                    return EventLoop::new_drm_any_thread()
+                    return EventLoop::new_drm_any_thread(attributes)
                         .expect("Failed to initialize drm backend");
                     #[cfg(not(feature = "kms"))]
                     panic!("kms feature is not enabled");
                 }
-                "fbdev" => {
+                UnixBackend::FbDev => {
                     #[cfg(feature = "fbdev")]
-                    return EventLoop::new_fbdev_any_thread()
+                    return EventLoop::new_fbdev_any_thread(attributes)
                         .expect("Failed to initialize fbdev backend");
                     #[cfg(not(feature = "fbdev"))]
                     panic!("fbdev feature is not enabled");
                 }
-                _ => panic!(
-                    "Unknown environment variable value for {}, try one of `x11`,`wayland`",
-                    BACKEND_PREFERENCE_ENV_VAR,
-                ),
             }
         }
 
@@ -830,13 +970,13 @@ impl<T: 'static> EventLoop<T> {
         };
 
         #[cfg(feature = "kms")]
-        let drm_err = match EventLoop::new_drm_any_thread() {
+        let drm_err = match EventLoop::new_drm_any_thread(attributes) {
             Ok(event_loop) => return event_loop,
             Err(err) => err,
         };
 
         #[cfg(feature = "fbdev")]
-        let fbdev_err = match EventLoop::new_fbdev_any_thread() {
+        let fbdev_err = match EventLoop::new_fbdev_any_thread(attributes) {
             Ok(event_loop) => return event_loop,
             Err(err) => err,
         };
@@ -872,13 +1012,17 @@ impl<T: 'static> EventLoop<T> {
     }
 
     #[cfg(feature = "kms")]
-    fn new_drm_any_thread() -> Result<EventLoop<T>, RootOsError> {
-        kms::EventLoop::new().map(EventLoop::Kms)
+    fn new_drm_any_thread(
+        attributes: &PlatformSpecificEventLoopAttributes,
+    ) -> Result<EventLoop<T>, RootOsError> {
+        kms::EventLoop::new(attributes).map(EventLoop::Kms)
     }
 
     #[cfg(feature = "fbdev")]
-    fn new_fbdev_any_thread() -> Result<EventLoop<T>, RootOsError> {
-        fbdev::EventLoop::new().map(EventLoop::FbDev)
+    fn new_fbdev_any_thread(
+        attributes: &PlatformSpecificEventLoopAttributes,
+    ) -> Result<EventLoop<T>, RootOsError> {
+        fbdev::EventLoop::new(attributes).map(EventLoop::FbDev)
     }
 
     pub fn create_proxy(&self) -> EventLoopProxy<T> {
@@ -0,0 +1,245 @@
+//! Virtual terminal management for the fbdev and kms backends.
+//!
+//! When running directly on a VT, the kernel console keeps drawing its cursor and text on top of
+//! whatever we render, and it has no idea that it should hand the display back to us after a VT
+//! switch. This module puts the active VT into graphics mode, hides the console cursor and takes
+//! over VT switching (`VT_PROCESS`) so that switches can be turned into `Suspended`/`Resumed`
+//! events. Everything is restored when the [`VirtualTerminal`] is dropped.
+
+use std::{
+    cell::Cell,
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    os::unix::prelude::{AsRawFd, OpenOptionsExt, RawFd},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+#[cfg(feature = "wayland")]
+use sctk::reexports::calloop;
+
+use calloop::signals::{Signal, Signals};
+
+// <linux/kd.h>
+const KDSETMODE: libc::c_ulong = 0x4B3A;
+const KDGETMODE: libc::c_ulong = 0x4B3B;
+const KD_GRAPHICS: libc::c_int = 0x01;
+const KDGKBMODE: libc::c_ulong = 0x4B44;
+const KDSKBMODE: libc::c_ulong = 0x4B45;
+const K_OFF: libc::c_int = 0x04;
+
+// <linux/vt.h>
+const VT_GETMODE: libc::c_ulong = 0x5601;
+const VT_SETMODE: libc::c_ulong = 0x5602;
+const VT_RELDISP: libc::c_ulong = 0x5605;
+const VT_PROCESS: libc::c_char = 0x01;
+const VT_ACKACQ: libc::c_int = 0x02;
+
+/// Sent by the kernel when another VT wants the display.
+pub const RELEASE_SIGNAL: Signal = Signal::SIGUSR1;
+/// Sent by the kernel when our VT becomes active again.
+pub const ACQUIRE_SIGNAL: Signal = Signal::SIGUSR2;
+/// Signals after which the VT is restored and the application is asked to close.
+pub const TERMINATE_SIGNALS: [Signal; 3] = [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP];
+
+const FBCON_CURSOR_BLINK: &str = "/sys/class/graphics/fbcon/cursor_blink";
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+struct VtMode {
+    mode: libc::c_char,
+    waitv: libc::c_char,
+    relsig: libc::c_short,
+    acqsig: libc::c_short,
+    frsig: libc::c_short,
+}
+
+fn ioctl_read<T: Default>(fd: RawFd, request: libc::c_ulong) -> io::Result<T> {
+    let mut value = T::default();
+    if unsafe { libc::ioctl(fd, request as _, &mut value as *mut T) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(value)
+}
+
+fn ioctl_write(fd: RawFd, request: libc::c_ulong, arg: libc::c_ulong) -> io::Result<()> {
+    if unsafe { libc::ioctl(fd, request as _, arg) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The VT we are running on, switched into graphics mode.
+#[derive(Debug)]
+pub struct VirtualTerminal {
+    tty: File,
+    kd_mode: libc::c_int,
+    kb_mode: libc::c_int,
+    vt_mode: VtMode,
+    cursor_blink: Option<String>,
+    restored: Cell<bool>,
+}
+
+impl VirtualTerminal {
+    /// Take over the VT at `WINIT_VT_PATH`, or the currently active VT if it is not set.
+    pub fn open() -> io::Result<Self> {
+        let path = std::env::var("WINIT_VT_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from("/dev/tty0"));
+
+        Self::open_path(&path)
+    }
+
+    /// Take over the VT at `path`, e.g. `/dev/tty1`. Fails with `ENOTTY` if it is not a VT.
+    pub fn open_path(path: &Path) -> io::Result<Self> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_CLOEXEC)
+            .open(path)?;
+        let fd = tty.as_raw_fd();
+
+        // Fails with ENOTTY if this is not a VT, in which case there is nothing for us to manage
+        let kd_mode: libc::c_int = ioctl_read(fd, KDGETMODE)?;
+        let kb_mode: libc::c_int = ioctl_read(fd, KDGKBMODE)?;
+        let vt_mode: VtMode = ioctl_read(fd, VT_GETMODE)?;
+
+        let cursor_blink = std::fs::read_to_string(FBCON_CURSOR_BLINK)
+            .ok()
+            .map(|s| s.trim().to_string());
+
+        let vt = VirtualTerminal {
+            tty,
+            kd_mode,
+            kb_mode,
+            vt_mode,
+            cursor_blink,
+            restored: Cell::new(false),
+        };
+
+        // If any of the following fails, `Drop` puts back whatever we already changed
+        ioctl_write(fd, KDSETMODE, KD_GRAPHICS as _)?;
+        // Keystrokes are read from evdev by libinput, they should not end up on the console
+        ioctl_write(fd, KDSKBMODE, K_OFF as _)?;
+
+        let process_mode = VtMode {
+            mode: VT_PROCESS,
+            waitv: 0,
+            relsig: RELEASE_SIGNAL as libc::c_short,
+            acqsig: ACQUIRE_SIGNAL as libc::c_short,
+            frsig: 0,
+        };
+        if unsafe { libc::ioctl(fd, VT_SETMODE as _, &process_mode as *const VtMode) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Hide the text cursor and stop fbcon from blinking it on top of us. Neither is
+        // critical, so errors are only logged.
+        if let Err(e) = (&vt.tty).write_all(b"\x1b[?25l") {
+            warn!("failed to hide the VT cursor: {}", e);
+        }
+        if vt.cursor_blink.is_some() {
+            if let Err(e) = std::fs::write(FBCON_CURSOR_BLINK, "0") {
+                warn!("failed to disable fbcon cursor blink: {}", e);
+            }
+        }
+
+        Ok(vt)
+    }
+
+    /// Allow the pending switch away from our VT to happen.
+    pub fn release_display(&self) -> io::Result<()> {
+        ioctl_write(self.tty.as_raw_fd(), VT_RELDISP, 1)
+    }
+
+    /// Acknowledge that our VT is active again.
+    pub fn acquire_display(&self) -> io::Result<()> {
+        ioctl_write(self.tty.as_raw_fd(), VT_RELDISP, VT_ACKACQ as _)
+    }
+
+    /// Put the VT back the way we found it. Calling this more than once is a no-op.
+    pub fn restore(&self) {
+        if self.restored.replace(true) {
+            return;
+        }
+
+        let fd = self.tty.as_raw_fd();
+        if unsafe { libc::ioctl(fd, VT_SETMODE as _, &self.vt_mode as *const VtMode) } < 0 {
+            warn!("failed to restore VT mode: {}", io::Error::last_os_error());
+        }
+        if let Err(e) = ioctl_write(fd, KDSKBMODE, self.kb_mode as _) {
+            warn!("failed to restore VT keyboard mode: {}", e);
+        }
+        if let Err(e) = ioctl_write(fd, KDSETMODE, self.kd_mode as _) {
+            warn!("failed to restore VT display mode: {}", e);
+        }
+        if let Some(ref blink) = self.cursor_blink {
+            let _ = std::fs::write(FBCON_CURSOR_BLINK, blink);
+        }
+        let _ = (&self.tty).write_all(b"\x1b[?25h");
+    }
+}
+
+impl AsRawFd for VirtualTerminal {
+    fn as_raw_fd(&self) -> RawFd {
+        self.tty.as_raw_fd()
+    }
+}
+
+impl Drop for VirtualTerminal {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+/// What happened to our VT, as reported to the backend by [`insert_source`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VtEvent {
+    /// We are about to lose the display, it is released once the callback returns.
+    Release,
+    /// We have the display back.
+    Acquire,
+    /// The process was asked to terminate, the VT has already been restored.
+    Terminate,
+}
+
+/// Listen for VT switch and termination signals on `handle`.
+///
+/// The VT side of things (acknowledging switches, restoring on termination) is handled here,
+/// `callback` only has to translate the [`VtEvent`] for the backend.
+pub fn insert_source<Data, F>(
+    handle: &calloop::LoopHandle<'static, Data>,
+    vt: Rc<VirtualTerminal>,
+    mut callback: F,
+) -> io::Result<()>
+where
+    F: FnMut(VtEvent, &mut Data) + 'static,
+{
+    let mut signals = vec![RELEASE_SIGNAL, ACQUIRE_SIGNAL];
+    signals.extend_from_slice(&TERMINATE_SIGNALS);
+    let source = Signals::new(&signals)?;
+
+    handle
+        .insert_source(source, move |event, _, data| match event.signal() {
+            RELEASE_SIGNAL => {
+                // Let the backend stop touching the display before we give it away
+                callback(VtEvent::Release, data);
+                if let Err(e) = vt.release_display() {
+                    warn!("failed to release VT: {}", e);
+                }
+            }
+            ACQUIRE_SIGNAL => {
+                if let Err(e) = vt.acquire_display() {
+                    warn!("failed to acquire VT: {}", e);
+                }
+                callback(VtEvent::Acquire, data);
+            }
+            _ => {
+                vt.restore();
+                callback(VtEvent::Terminate, data);
+            }
+        })
+        .map_err(|e| e.error)?;
+
+    Ok(())
+}
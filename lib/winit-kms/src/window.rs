@@ -417,6 +417,8 @@ impl Window {
     /// ## Platform-specific
     ///
     /// - **X11:** This respects Xft.dpi, and can be overridden using the `WINIT_X11_SCALE_FACTOR` environment variable.
+    /// - **Kms / FbDev:** Same as the monitor's, unless overridden with
+    ///   `WindowBuilderExtUnix::with_scale_factor` or `WindowExtUnix::set_scale_factor_override`.
     /// - **Android:** Always returns 1.0.
     /// - **iOS:** Can only be called on the main thread. Returns the underlying `UIView`'s
     ///   [`contentScaleFactor`].
@@ -5,6 +5,18 @@ use egui::{Color32, Event, pos2, Pos2, Rect, Rounding, Stroke, TouchPhase, Vec2,
 fn main() {
     let mut native_options = eframe::NativeOptions::default();
     native_options.initial_window_size = Some(vec2(800 as f32, 480 as f32));
+    // The panel is mounted in portrait, on a desktop the window is upright already
+    if std::env::var_os("WAYLAND_DISPLAY").is_none() && std::env::var_os("DISPLAY").is_none() {
+        native_options.embedded = eframe::EmbeddedOptions {
+            rotation: eframe::egui_glow::Rotation::Cw90,
+            touch_only: true,
+            hide_cursor: true,
+            ..Default::default()
+        };
+        // Touches request a repaint each, animations don't need the full rate either
+        native_options.max_fps = Some(30.0);
+        native_options.animation_fps = Some(15.0);
+    }
     eframe::run_native("My egui App", native_options, Box::new(|cc| Box::new(MyEguiApp::new(cc))));
 }
 
@@ -13,7 +25,6 @@ struct MyEguiApp {}
 
 impl MyEguiApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        cc.egui_ctx.set_pixels_per_point(1.5);
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
         // Restore app state using cc.storage (requires the "persistence" feature).
         // Use the cc.gl (a glow::Context) to create graphics shaders and buffers that you can use
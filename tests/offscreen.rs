@@ -0,0 +1,433 @@
+//! Paints with [`egui_glow::Painter`] into a framebuffer object, using a headless EGL context
+//! that needs neither a display nor a GPU (Mesa's llvmpipe is enough).
+//!
+//! These live here rather than in egui_glow because they need the glutin fork, which only this
+//! crate patches in.
+
+#![cfg(target_os = "linux")]
+#![allow(unsafe_code)]
+
+use std::sync::Arc;
+
+use egui::epaint::{ImageDelta, Primitive};
+use egui::{pos2, ClippedPrimitive, Color32, ColorImage, Mesh, Rect, TextureFilter, TextureId};
+use egui_glow::glow::{self, HasContext as _};
+use egui_glow::Rotation;
+use glutin::platform::unix::HeadlessContextExt as _;
+
+/// The egui screen size in pixels, painted with [`Rotation::Cw90`] into a framebuffer of
+/// [`FRAMEBUFFER_SIZE`].
+const SCREEN_SIZE: [u32; 2] = [800, 480];
+const FRAMEBUFFER_SIZE: [u32; 2] = [480, 800];
+
+struct Offscreen {
+    // Keeps the context alive as long as `gl`
+    _context: glutin::Context<glutin::PossiblyCurrent>,
+    gl: Arc<glow::Context>,
+    fbo: glow::Framebuffer,
+    texture: glow::Texture,
+}
+
+impl Offscreen {
+    /// Panics if there is no headless EGL, rather than letting the tests pass without painting.
+    fn new() -> Self {
+        let context = glutin::ContextBuilder::new()
+            .build_egl_headless(None)
+            .unwrap_or_else(|err| {
+                panic!(
+                    "No headless EGL context, these tests need Mesa with \
+                     EGL_MESA_platform_surfaceless or EGL_EXT_platform_device: {}",
+                    err
+                )
+            });
+        let context = unsafe { context.make_current() }
+            .map_err(|(_, err)| err)
+            .expect("make_current");
+        let gl = unsafe { glow::Context::from_loader_function(|s| context.get_proc_address(s)) };
+
+        let [width, height] = [FRAMEBUFFER_SIZE[0] as i32, FRAMEBUFFER_SIZE[1] as i32];
+        unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                width,
+                height,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let fbo = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            assert_eq!(
+                gl.check_framebuffer_status(glow::FRAMEBUFFER),
+                glow::FRAMEBUFFER_COMPLETE
+            );
+
+            Self {
+                _context: context,
+                gl: Arc::new(gl),
+                fbo,
+                texture,
+            }
+        }
+    }
+
+    fn painter(&self, rotation: Rotation) -> egui_glow::Painter {
+        let mut painter = egui_glow::Painter::new(self.gl.clone(), None, "").unwrap();
+        painter.set_output_rotation(rotation);
+        let white = ColorImage::new([1, 1], Color32::WHITE);
+        painter.set_texture(
+            TextureId::default(),
+            &ImageDelta::full(white, TextureFilter::Nearest),
+        );
+        painter
+    }
+
+    /// Binds the framebuffer and clears it to transparent black.
+    fn clear(&self) {
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+        }
+        egui_glow::painter::clear(&self.gl, FRAMEBUFFER_SIZE, egui::Rgba::TRANSPARENT);
+    }
+
+    /// Bottom row first, like `glReadPixels` returns them.
+    fn pixels(&self) -> Vec<[u8; 4]> {
+        let [width, height] = [FRAMEBUFFER_SIZE[0] as i32, FRAMEBUFFER_SIZE[1] as i32];
+        let mut pixels = vec![0_u8; (width * height * 4) as usize];
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+            self.gl.read_pixels(
+                0,
+                0,
+                width,
+                height,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut pixels),
+            );
+        }
+        pixels
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect()
+    }
+}
+
+impl Drop for Offscreen {
+    fn drop(&mut self) {
+        unsafe {
+            self.gl.delete_framebuffer(self.fbo);
+            self.gl.delete_texture(self.texture);
+        }
+    }
+}
+
+fn rect(rect: Rect, color: Color32, clip_rect: Rect) -> ClippedPrimitive {
+    let mut mesh = Mesh::default();
+    mesh.add_colored_rect(rect, color);
+    ClippedPrimitive {
+        clip_rect,
+        primitive: Primitive::Mesh(mesh),
+    }
+}
+
+fn screen_rect() -> Rect {
+    Rect::from_min_max(
+        pos2(0.0, 0.0),
+        pos2(SCREEN_SIZE[0] as f32, SCREEN_SIZE[1] as f32),
+    )
+}
+
+/// Clears the framebuffer to transparent black and paints `primitives` into it.
+fn paint(offscreen: &Offscreen, primitives: &[ClippedPrimitive]) -> Vec<[u8; 4]> {
+    paint_rotated(offscreen, Rotation::Cw90, SCREEN_SIZE, primitives)
+}
+
+fn paint_rotated(
+    offscreen: &Offscreen,
+    rotation: Rotation,
+    screen_size: [u32; 2],
+    primitives: &[ClippedPrimitive],
+) -> Vec<[u8; 4]> {
+    assert_eq!(rotation.framebuffer_size(screen_size), FRAMEBUFFER_SIZE);
+    let mut painter = offscreen.painter(rotation);
+    offscreen.clear();
+    painter.paint_primitives(screen_size, 1.0, primitives);
+    painter.destroy();
+    offscreen.pixels()
+}
+
+/// Checks that the pixels in each `[x, y, width, height]` (from the top left of the
+/// framebuffer) have its color, and that all others are cleared.
+fn assert_painted(pixels: &[[u8; 4]], rotation: Rotation, rects: &[([u32; 4], [u8; 4])]) {
+    let [fb_width, fb_height] = FRAMEBUFFER_SIZE;
+    for (i, &pixel) in pixels.iter().enumerate() {
+        let px = i as u32 % fb_width;
+        let py = fb_height - 1 - i as u32 / fb_width;
+        let expected = rects
+            .iter()
+            .find(|([x, y, width, height], _)| {
+                (*x..x + width).contains(&px) && (*y..y + height).contains(&py)
+            })
+            .map_or([0, 0, 0, 0], |(_, color)| *color);
+        assert_eq!(pixel, expected, "{:?} at {:?}", rotation, (px, py));
+    }
+}
+
+#[test]
+fn paints_the_whole_screen() {
+    let offscreen = Offscreen::new();
+
+    let pixels = paint(
+        &offscreen,
+        &[rect(screen_rect(), Color32::RED, screen_rect())],
+    );
+    assert!(pixels.iter().all(|&p| p == [255, 0, 0, 255]));
+}
+
+#[test]
+fn clips_to_the_clip_rect() {
+    let offscreen = Offscreen::new();
+
+    let clip_rect = Rect::from_min_max(pos2(0.0, 0.0), pos2(400.0, 480.0));
+    let pixels = paint(
+        &offscreen,
+        &[rect(screen_rect(), Color32::GREEN, clip_rect)],
+    );
+    let painted = pixels.iter().filter(|&&p| p == [0, 255, 0, 255]).count();
+    let cleared = pixels.iter().filter(|&&p| p == [0, 0, 0, 0]).count();
+    assert_eq!(painted, 400 * 480);
+    assert_eq!(cleared, pixels.len() - painted);
+}
+
+#[test]
+fn rotates_the_top_left_corner() {
+    let offscreen = Offscreen::new();
+
+    // Where a 10x20 rect in the top left corner of the screen ends up in the framebuffer, as
+    // `[x, y, width, height]` from the top left of the framebuffer
+    let cases = [
+        (Rotation::None, [480, 800], [0, 0, 10, 20]),
+        (Rotation::Cw90, [800, 480], [460, 0, 20, 10]),
+        (Rotation::Cw180, [480, 800], [470, 780, 10, 20]),
+        (Rotation::Cw270, [800, 480], [0, 790, 20, 10]),
+    ];
+    for (rotation, screen_size, [x, y, width, height]) in cases {
+        let screen_rect = Rect::from_min_max(
+            pos2(0.0, 0.0),
+            pos2(screen_size[0] as f32, screen_size[1] as f32),
+        );
+        let corner = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 20.0));
+        // The clip rect cuts the screen wide rect down to the corner, so scissoring is rotated too
+        let pixels = paint_rotated(
+            &offscreen,
+            rotation,
+            screen_size,
+            &[rect(screen_rect, Color32::RED, corner)],
+        );
+
+        assert_painted(
+            &pixels,
+            rotation,
+            &[([x, y, width, height], [255, 0, 0, 255])],
+        );
+    }
+}
+
+#[test]
+fn paints_callbacks_upright() {
+    let offscreen = Offscreen::new();
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    // A 10x20 callback in the top left corner, red in its top half and green in its bottom half.
+    // Where the top left of either half ends up in the framebuffer:
+    let cases = [
+        (Rotation::None, [480, 800], [0, 0], [0, 10]),
+        (Rotation::Cw90, [800, 480], [470, 0], [460, 0]),
+        (Rotation::Cw180, [480, 800], [470, 790], [470, 780]),
+        (Rotation::Cw270, [800, 480], [0, 790], [10, 790]),
+    ];
+    for (rotation, screen_size, [red_x, red_y], [green_x, green_y]) in cases {
+        let corner = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 20.0));
+        let callback = egui_glow::CallbackFn::new(|info, painter| {
+            let gl = painter.gl();
+            let viewport = info.viewport_in_pixels();
+            unsafe {
+                gl.clear_color(0.0, 1.0, 0.0, 1.0);
+                gl.clear(glow::COLOR_BUFFER_BIT);
+                gl.enable(glow::SCISSOR_TEST);
+                gl.scissor(
+                    viewport.left_px as i32,
+                    (viewport.from_bottom_px + viewport.height_px / 2.0) as i32,
+                    viewport.width_px as i32,
+                    (viewport.height_px / 2.0) as i32,
+                );
+                gl.clear_color(1.0, 0.0, 0.0, 1.0);
+                gl.clear(glow::COLOR_BUFFER_BIT);
+            }
+        });
+        let pixels = paint_rotated(
+            &offscreen,
+            rotation,
+            screen_size,
+            &[ClippedPrimitive {
+                clip_rect: corner,
+                primitive: Primitive::Callback(egui::PaintCallback {
+                    rect: corner,
+                    callback: Arc::new(callback),
+                }),
+            }],
+        );
+
+        // Either half is 10x10 whichever way it is turned
+        assert_painted(
+            &pixels,
+            rotation,
+            &[
+                ([red_x, red_y, 10, 10], RED),
+                ([green_x, green_y, 10, 10], GREEN),
+            ],
+        );
+    }
+}
+
+#[test]
+fn reads_the_screen_back_upright() {
+    let offscreen = Offscreen::new();
+
+    for (rotation, screen_size) in [
+        (Rotation::None, [480, 800]),
+        (Rotation::Cw90, [800, 480]),
+        (Rotation::Cw180, [480, 800]),
+        (Rotation::Cw270, [800, 480]),
+    ] {
+        let screen_rect = Rect::from_min_max(
+            pos2(0.0, 0.0),
+            pos2(screen_size[0] as f32, screen_size[1] as f32),
+        );
+        let corner = Rect::from_min_max(pos2(0.0, 0.0), pos2(10.0, 20.0));
+        let mut painter = offscreen.painter(rotation);
+        offscreen.clear();
+        painter.paint_primitives(screen_size, 1.0, &[rect(screen_rect, Color32::RED, corner)]);
+        let image = painter.read_screen_rgba(Rect::from_min_max(pos2(5.0, 10.0), pos2(25.0, 40.0)));
+        painter.destroy();
+
+        assert_eq!(image.size, [20, 30], "{:?}", rotation);
+        for y in 0..30 {
+            for x in 0..20 {
+                let expected = if x < 5 && y < 10 {
+                    Color32::RED
+                } else {
+                    Color32::TRANSPARENT
+                };
+                assert_eq!(image[(x, y)], expected, "{:?} at {:?}", rotation, (x, y));
+            }
+        }
+    }
+}
+
+#[test]
+fn evicts_textures_over_budget() {
+    let offscreen = Offscreen::new();
+
+    let mut painter = offscreen.painter(Rotation::Cw90);
+    let budget = painter.texture_budget();
+    budget.set_budget(Some(5000));
+    let evicted = Arc::new(std::sync::Mutex::new(vec![]));
+    let [album_art, cover] = [TextureId::Managed(1), TextureId::Managed(2)];
+    for texture_id in [album_art, cover] {
+        let image = ColorImage::new([32, 32], Color32::WHITE);
+        painter.set_texture(texture_id, &ImageDelta::full(image, TextureFilter::Linear));
+        let evicted = evicted.clone();
+        budget.set_evictable(texture_id, move |texture_id| {
+            evicted.lock().unwrap().push(texture_id);
+        });
+    }
+    assert_eq!(budget.usage().bytes_used, 4 + 2 * 32 * 32 * 4);
+
+    // Only `cover` is on screen, so `album_art` goes
+    let mut mesh = Mesh::with_texture(cover);
+    mesh.add_rect_with_uv(
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(32.0, 32.0)),
+        Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+        Color32::WHITE,
+    );
+    offscreen.clear();
+    painter.paint_primitives(
+        SCREEN_SIZE,
+        1.0,
+        &[ClippedPrimitive {
+            clip_rect: screen_rect(),
+            primitive: Primitive::Mesh(mesh),
+        }],
+    );
+
+    assert_eq!(*evicted.lock().unwrap(), vec![album_art]);
+    assert!(painter.texture(album_art).is_none());
+    assert!(painter.texture(cover).is_some());
+    let usage = budget.usage();
+    assert_eq!(usage.bytes_used, 4 + 32 * 32 * 4);
+    assert_eq!(usage.num_evicted, 1);
+    painter.destroy();
+}
+
+#[test]
+fn counts_texture_copies() {
+    let offscreen = Offscreen::new();
+
+    let mut painter = offscreen.painter(Rotation::Cw90);
+    let budget = painter.texture_budget();
+    assert_eq!(budget.usage().bytes_copied, 0);
+
+    painter.set_keep_texture_copies(true);
+    let texture_id = TextureId::Managed(1);
+    let image = ColorImage::new([32, 32], Color32::WHITE);
+    painter.set_texture(texture_id, &ImageDelta::full(image, TextureFilter::Linear));
+    assert_eq!(budget.usage().bytes_copied, 32 * 32 * 4);
+
+    painter.free_texture(texture_id);
+    assert_eq!(budget.usage().bytes_copied, 0);
+    painter.destroy();
+}
+
+#[test]
+fn blends_in_gamma_space() {
+    let offscreen = Offscreen::new();
+
+    let mut painter = egui_glow::Painter::new_gamma_space(offscreen.gl.clone(), "").unwrap();
+    painter.set_output_rotation(Rotation::Cw90);
+    let white = ColorImage::new([1, 1], Color32::WHITE);
+    painter.set_texture(
+        TextureId::default(),
+        &ImageDelta::full(white, TextureFilter::Nearest),
+    );
+    offscreen.clear();
+    let gray = Color32::from_rgba_premultiplied(128, 128, 128, 128);
+    painter.paint_primitives(
+        SCREEN_SIZE,
+        1.0,
+        &[rect(screen_rect(), gray, screen_rect())],
+    );
+    painter.destroy();
+
+    // Stays as encoded, instead of being decoded to linear for blending
+    assert!(offscreen
+        .pixels()
+        .iter()
+        .all(|&p| p == [128, 128, 128, 128]));
+}